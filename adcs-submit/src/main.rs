@@ -1,6 +1,6 @@
 mod operations;
 
-use std::{env, process::exit, fmt::Display, ffi::OsStr};
+use std::{env, process::exit, fmt::Display, ffi::OsStr, net::SocketAddr, path::PathBuf};
 use bcder::{Mode, decode::{Constructed, DecodeError, BytesSource, Source}};
 use bytes::Bytes;
 use libadcs::{NamedCertificate, AdcsError, EnrollmentResponse};
@@ -12,6 +12,9 @@ use tracing_log::LogTracer;
 use x509_certificate::{rfc2986::CertificationRequest};
 use clap::Parser;
 
+const SYSTEM_KEYTAB: &str = "FILE:/etc/krb5.keytab";
+const MACHINE_CREDENTIAL_CACHE: &str = "MEMORY:libadcs-machine";
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Environment
@@ -19,8 +22,22 @@ pub struct Environment
   #[arg(short, long)]
   realm: String,
 
+  /// Enrollment policy endpoint.  Discovered from the directory when omitted
+  #[arg(short, long)]
+  endpoint: Option<String>,
+
+  /// Copy of the SYSVOL share, such as a mount or the samba cache, read for enrollment policy
+  /// servers set by group policy when no endpoint is given
+  #[arg(long)]
+  sysvol: Option<PathBuf>,
+
+  /// Enroll as the host, using the system keytab, instead of the logged in user
   #[arg(short, long)]
-  endpoint: String,
+  machine: bool,
+
+  /// Nameserver to discover global catalogs with, instead of those of the host.  May be repeated
+  #[arg(long)]
+  nameserver: Vec<SocketAddr>,
 
   #[clap(flatten)]
   verbose: clap_verbosity_flag::Verbosity,
@@ -139,9 +156,24 @@ fn certmonger_submit(env: Environment) -> Result<(i32, String), Error>
   }
 }
 
+// libadcs takes kerberos credentials from the environment, so point the process at the host keytab
+// and a private credential cache before anything else, while it is still single threaded
+fn machine_credentials()
+{
+  if env::var_os("KRB5_CLIENT_KTNAME").is_none()
+  {
+    env::set_var("KRB5_CLIENT_KTNAME", SYSTEM_KEYTAB);
+  }
+  env::set_var("KRB5CCNAME", MACHINE_CREDENTIAL_CACHE);
+}
+
 fn main()
 {
   let env = Environment::parse();
+  if env.machine
+  {
+    machine_credentials();
+  }
   LogTracer::init().unwrap();
   let subscriber = tracing_subscriber::fmt()
     .compact()
@@ -1,4 +1,4 @@
-use libadcs::{CertificateServicesClient, Url};
+use libadcs::{CertificateServicesClient, EnrollmentContext, LdapConnectionOptionsBuilder, PendingRequest, Url};
 use x509_certificate::rfc2986::CertificationRequest;
 use crate::{EnrollmentResponse, Error, RootCertificates, Environment};
 
@@ -11,18 +11,31 @@ impl Operations
 {
   pub fn new(env: Environment) -> Result<Self, Error>
   {
-    let client = CertificateServicesClient::new(env.realm, Url::parse(&env.endpoint).unwrap(), false)?;
+    let context = if env.machine { EnrollmentContext::Machine } else { EnrollmentContext::User };
+    let options = LdapConnectionOptionsBuilder::default()
+      .nameservers(env.nameserver)
+      .build()
+      .map_err(|err| Error::Underconfigurated(err.to_string()))?;
+    let client = match env.endpoint
+    {
+      Some(endpoint) => CertificateServicesClient::new(env.realm, Url::parse(&endpoint).unwrap(), context, options)?,
+      None => CertificateServicesClient::discover(env.realm, env.sysvol, context, options)?
+    };
     Ok(Self { client })
   }
 
   pub fn submit(self, csr: CertificationRequest, ca_profile: String) -> Result<EnrollmentResponse, Error>
   {
-    Ok(self.client.submit(csr, &ca_profile)?)
+    Ok(self.client.submit_unsigned(csr, &ca_profile)?)
   }
 
   pub fn poll(self, ca_cookie: String) -> Result<EnrollmentResponse, Error>
   {
-    todo!()
+    let pending: PendingRequest = ca_cookie
+      .trim()
+      .parse()
+      .map_err(|_| Error::BadEnvironment("CERTMONGER_CA_COOKIE".to_owned(), ca_cookie.clone()))?;
+    Ok(self.client.poll(&pending)?)
   }
 
   pub fn identify(self) -> Result<String, Error>
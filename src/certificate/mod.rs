@@ -0,0 +1,65 @@
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+use bcder::{decode::Constructed, Mode, Oid};
+use x509_certificate::X509Certificate;
+
+use crate::{template::RaRequirements, AdcsError};
+
+pub const ID_EXTENDED_KEY_USAGE: &[u8] = &[85, 29, 37];
+pub const ID_ANY_EXTENDED_KEY_USAGE: &[u8] = &[85, 29, 37, 0];
+pub const ID_APPLICATION_POLICIES: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 21, 10];
+
+// the application policies a certificate is good for.  microsoft's application policies extension
+// takes the place of extended key usage when both are present.  None when there is neither, as the
+// certificate is then good for any purpose, and an empty list when the extension cannot be read
+pub(crate) fn application_policies(certificate: &X509Certificate) -> Option<Vec<Oid>>
+{
+  let extension = |id: &[u8]| certificate.iter_extensions().find(|extension| extension.id.as_ref() == id).map(|extension| extension.value.to_bytes());
+  match (extension(ID_APPLICATION_POLICIES), extension(ID_EXTENDED_KEY_USAGE))
+  {
+    (Some(value), _) => Some(Constructed::decode(value, Mode::Der, |cons| cons.take_sequence(|cons|
+    {
+      let mut policies = Vec::new();
+      while let Some(policy) = cons.take_opt_sequence(|cons|
+      {
+        let policy = Oid::take_from(cons)?;
+        cons.skip_all()?;
+        Ok(policy)
+      })?
+      {
+        policies.push(policy);
+      }
+      Ok(policies)
+    })).unwrap_or_default()),
+    (None, Some(value)) => Some(Constructed::decode(value, Mode::Der, |cons| cons.take_sequence(|cons|
+    {
+      let mut usages = Vec::new();
+      while let Some(usage) = Oid::take_opt_from(cons)?
+      {
+        usages.push(usage);
+      }
+      Ok(usages)
+    })).unwrap_or_default()),
+    (None, None) => None
+  }
+}
+
+// the CA would only deny a request that the agent cannot satisfy, so it is refused before sending
+pub(crate) fn check_agent(template: &str, requirements: &RaRequirements, agent_certificate: &X509Certificate) -> Result<(), AdcsError>
+{
+  if requirements.signatures > 1
+  {
+    return Err(AdcsError::TooManyAgentSignatures(template.to_owned(), requirements.signatures));
+  }
+  if let Some(policies) = application_policies(agent_certificate)
+  {
+    let any_purpose = policies.iter().any(|policy| policy.as_ref() == ID_ANY_EXTENDED_KEY_USAGE);
+    if let Some(missing) = requirements.application_policies.iter().find(|required| !any_purpose && !policies.contains(required))
+    {
+      return Err(AdcsError::AgentPolicyMissing(missing.to_string(), template.to_owned()));
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,35 @@
+use std::str::FromStr;
+
+use bcder::Oid;
+use bytes::Bytes;
+use x509_certificate::{KeyAlgorithm, X509CertificateBuilder};
+
+use super::{application_policies, ID_EXTENDED_KEY_USAGE, ID_APPLICATION_POLICIES};
+
+#[test]
+fn agent_application_policies()
+{
+  // szOID_ENROLLMENT_AGENT, as an extended key usage and as an application policy
+  let agent = [0x06, 0x0a, 43, 6, 1, 4, 1, 130, 55, 20, 2, 1];
+  let certificate = |extensions: &[(&'static [u8], Vec<u8>)]|
+  {
+    let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+    builder.subject().append_common_name_utf8_string("agent").expect("error setting subject");
+    for (oid, value) in extensions
+    {
+      builder.add_extension_der_data(Oid(Bytes::from_static(*oid)), false, value);
+    }
+    builder.create_with_random_keypair().expect("failed to generate certificate").0
+  };
+  let enrollment_agent = Oid::<Bytes>::from_str("1.3.6.1.4.1.311.20.2.1").expect("bad oid");
+
+  let extended_key_usage = certificate(&[(ID_EXTENDED_KEY_USAGE, [&[0x30, 0x0c][..], &agent].concat())]);
+  assert_eq!(application_policies(&extended_key_usage), Some(vec![enrollment_agent.clone()]));
+  let application_policy = certificate(&[
+    (ID_EXTENDED_KEY_USAGE, vec![0x30, 0x00]),
+    (ID_APPLICATION_POLICIES, [&[0x30, 0x0e, 0x30, 0x0c][..], &agent].concat())
+  ]);
+  assert_eq!(application_policies(&application_policy), Some(vec![enrollment_agent]));
+  assert_eq!(application_policies(&certificate(&[])), None);
+  assert_eq!(application_policies(&certificate(&[(ID_EXTENDED_KEY_USAGE, vec![0x04, 0x00])])), Some(vec![]));
+}
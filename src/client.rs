@@ -1,11 +1,21 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
 use bcder::Oid;
+use derive_builder::Builder;
 use itertools::Itertools;
 use thiserror::Error;
 use tracing::{event, Level, instrument};
 use url::Url;
-use x509_certificate::{rfc2986::CertificationRequest, X509Certificate};
+use x509_certificate::{rfc2986::CertificationRequest, X509Certificate, CapturedX509Certificate, KeyInfoSigner};
 
-use crate::{ClientAuthentication, NamedCertificate, cmc::{rfc5272::AttributeValue, CmcRequestBuilder}, EncodeError, AdcsError, ldap::LdapManager, ldap_client, http_client, PolicyEndpoint};
+use crate::{csr::{CertificationRequestBuilder, Identity}, certificate::check_agent, template::{SubjectNameFlags, EnrollmentFlags, PrivateKeyFlags, GeneralFlags, KeyRequirements, RaRequirements, KeyArchivalAttributes}, ClientAuthentication, NamedCertificate, cmc::{rfc5272::AttributeValue, archival::{KeyArchival, SymmetricAlgorithm}, CmcRequestBuilder}, EncodeError, DecodeError, AdcsError, ldap::LdapManager, ldap_client, group_policy, http_client, PolicyEndpoint, EnrollmentContext, LdapConnectionOptions};
+#[cfg(feature = "enrollment_https")]
+use crate::http_client::HttpsEnrollmentClient;
+#[cfg(feature = "enrollment_rpc")]
+use crate::rpc_client::RpcEnrollmentClient;
 
 #[derive(Error, Debug)]
 pub enum ConfigurationError
@@ -17,12 +27,248 @@ pub enum ConfigurationError
   NoLdapSupport,
 
   #[error("unknown scheme specified: {0}")]
-  UnknownScheme(String)
+  UnknownScheme(String),
+
+  #[error("libadcs was compiled without any enrollment support")]
+  NoEnrollmentSupport
 }
 
 pub trait EnrollmentClient
 {
   fn submit(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>;
+  fn renew(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>;
+  fn poll(&self, request_id: u32, location: &RequestLocation) -> Result<EnrollmentResponse, AdcsError>;
+  fn handles(&self, transport: &Transport) -> bool;
+  fn exchange_certificate(&self, enrollment_service: &EnrollmentService) -> Result<X509Certificate, AdcsError>;
+}
+
+pub struct CertificateServicesClient
+{
+  policy: Policy,
+  identity: Identity,
+  enrollment_clients: Vec<Box<dyn EnrollmentClient>>
+}
+
+impl CertificateServicesClient
+{
+  #[instrument]
+  pub fn new(realm: String, endpoint: Url, context: EnrollmentContext, options: LdapConnectionOptions) -> Result<Self, AdcsError>
+  {
+    let policy_endpoint = PolicyEndpoint::new(endpoint, ClientAuthentication::TransportKerberos, 0);
+    let mut ldap = LdapManager::new(realm.to_lowercase(), context, &options)?;
+    Self::from_ldap(&mut ldap, vec![policy_endpoint])
+  }
+
+  // policy servers from group policy, if any, are tried before the policy held in the directory
+  #[instrument]
+  pub fn discover(realm: String, sysvol: Option<PathBuf>, context: EnrollmentContext, options: LdapConnectionOptions) -> Result<Self, AdcsError>
+  {
+    let mut ldap = LdapManager::new(realm.to_lowercase(), context, &options)?;
+    let policy_endpoints = Policy::endpoints(&mut ldap, sysvol.as_deref());
+    Self::from_ldap(&mut ldap, policy_endpoints)
+  }
+
+  fn from_ldap(ldap: &mut LdapManager, policy_endpoints: Vec<PolicyEndpoint>) -> Result<Self, AdcsError>
+  {
+    let policy = Policy::discover(ldap, None, policy_endpoints)?;
+    Ok(Self { policy, identity: ldap.get_identity(), enrollment_clients: Self::enrollment_clients(#[cfg(feature = "enrollment_https")] ldap.get_principal()) })
+  }
+
+  // MS-CAESO 4.4.5.3.2.3 - https endpoints first, ordered by priority, then rpc
+  fn enrollment_clients(#[cfg(feature = "enrollment_https")] principal: Option<&str>) -> Vec<Box<dyn EnrollmentClient>>
+  {
+    let mut enrollment_clients: Vec<Box<dyn EnrollmentClient>> = vec![];
+    #[cfg(feature = "enrollment_https")]
+    enrollment_clients.push(Box::new(HttpsEnrollmentClient::new(principal.map(str::to_owned))));
+    #[cfg(feature = "enrollment_rpc")]
+    enrollment_clients.push(Box::new(RpcEnrollmentClient));
+    enrollment_clients
+  }
+
+  #[instrument(skip(self, request, signer))]
+  pub fn submit(&self, request: CertificationRequest, template: &str, signer: &dyn KeyInfoSigner) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let certificate_template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    self.submit_encoded(&self.policy, certificate_template.apply_to_request(request, signer)?, template)
+  }
+
+  // for callers that hold only the certification request, such as certmonger helpers, whose key
+  // lives elsewhere.  the pkcs#10 signature remains the only proof of possession
+  #[instrument(skip(self, request))]
+  pub fn submit_unsigned(&self, request: CertificationRequest, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let certificate_template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    self.submit_encoded(&self.policy, certificate_template.apply_to_unsigned_request(request)?, template)
+  }
+
+  #[instrument(skip(self, request, agent_certificate, agent_signer))]
+  pub fn submit_on_behalf_of(&self, request: CertificationRequest, template: &str, requester_name: &str, agent_certificate: CapturedX509Certificate, agent_signer: &dyn KeyInfoSigner) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let certificate_template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    if let Some(requirements) = self.policy.get_agent_requirements(template)?
+    {
+      check_agent(template, requirements, &agent_certificate)?;
+    }
+    self.submit_encoded(&self.policy, certificate_template.apply_to_agent_request(request, requester_name, agent_signer, agent_certificate)?, template)
+  }
+
+  // the private key is encrypted to one CA's exchange certificate, so the request may only go to that CA
+  #[instrument(skip(self, request, private_key, signer))]
+  pub fn submit_with_archival(&self, request: CertificationRequest, template: &str, private_key: &[u8], signer: &dyn KeyInfoSigner) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let certificate_template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    let algorithm = SymmetricAlgorithm::from_template(&certificate_template.get_key_archival().cloned().unwrap_or_default())
+      .map_err(EncodeError::from)?;
+    let mut last_error = None;
+    for enrollment_service in self.policy.get_enrollment_services_for_template(certificate_template)?
+    {
+      match self.exchange_certificate(enrollment_service)
+      {
+        Ok(exchange_certificate) =>
+        {
+          let archival = KeyArchival::new(exchange_certificate, algorithm, private_key).map_err(EncodeError::from)?;
+          let request = certificate_template.apply_to_archival_request(request.clone(), signer, archival)?;
+          match self.submit_encoded(&self.policy.restricted_to(enrollment_service), request, template)
+          {
+            Ok(response) => return Ok(response),
+            Err(err) =>
+            {
+              event!(Level::WARN, "error submitting archival request to {}: {}.  skipping", enrollment_service.get_certificate().nickname, err);
+              last_error = Some(err);
+            }
+          }
+        },
+        Err(err) =>
+        {
+          event!(Level::WARN, "error retrieving exchange certificate: {}.  skipping", err);
+          last_error = Some(err);
+        }
+      }
+    }
+    Err(last_error.unwrap_or_else(|| AdcsError::NoEnrollmentEndpoint(template.to_owned())))
+  }
+
+  // the directory only publishes the CA's signing certificate.  the exchange certificate is issued
+  // by the CA itself and rotated on its own schedule, so it is only available over the enrollment
+  // transports and there is no ldap path for it
+  #[instrument(skip(self, enrollment_service))]
+  pub fn exchange_certificate(&self, enrollment_service: &EnrollmentService) -> Result<X509Certificate, AdcsError>
+  {
+    let mut last_error = None;
+    for enrollment_client in self.enrollment_clients.iter()
+    {
+      match enrollment_client.exchange_certificate(enrollment_service)
+      {
+        Ok(certificate) => return Ok(certificate),
+        Err(err) =>
+        {
+          event!(Level::WARN, "error retrieving exchange certificate: {}.  trying next transport", err);
+          last_error = Some(err);
+        }
+      }
+    }
+    Err(last_error.unwrap_or_else(|| ConfigurationError::NoEnrollmentSupport.into()))
+  }
+
+  fn submit_encoded(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let mut last_error = None;
+    for enrollment_client in self.enrollment_clients.iter()
+    {
+      match enrollment_client.submit(policy, request.clone(), template)
+      {
+        Ok(response) => return Ok(response),
+        Err(err) =>
+        {
+          event!(Level::WARN, "error during enrollment: {}.  trying next transport", err);
+          last_error = Some(err);
+        }
+      }
+    }
+    Err(last_error.unwrap_or_else(|| ConfigurationError::NoEnrollmentSupport.into()))
+  }
+
+  #[instrument(skip(self, request, signer))]
+  pub fn renew(&self, request: CertificationRequest, template: &str, certificate: CapturedX509Certificate, signer: &dyn KeyInfoSigner) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let certificate_template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    let request = certificate_template.apply_to_renewal(request, signer, certificate)?;
+    let mut last_error = None;
+    for enrollment_client in self.enrollment_clients.iter()
+    {
+      match enrollment_client.renew(&self.policy, request.clone(), template)
+      {
+        Ok(response) => return Ok(response),
+        Err(err) =>
+        {
+          event!(Level::WARN, "error during renewal: {}.  trying next transport", err);
+          last_error = Some(err);
+        }
+      }
+    }
+    Err(last_error.unwrap_or_else(|| ConfigurationError::NoEnrollmentSupport.into()))
+  }
+
+  // request ids are only unique within a CA, so a request is only ever polled where it was submitted
+  #[instrument(skip(self))]
+  pub fn poll(&self, pending: &PendingRequest) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let location = pending.location.as_ref().ok_or_else(|| DecodeError::BadRequestId(pending.to_string()))?;
+    match self.enrollment_clients.iter().find(|enrollment_client| enrollment_client.handles(&location.transport))
+    {
+      Some(enrollment_client) => enrollment_client.poll(pending.request_id, location),
+      None => Err(ConfigurationError::NoEnrollmentSupport.into())
+    }
+  }
+
+  pub fn root_certificates(&self) -> Vec<NamedCertificate>
+  {
+    self.policy.get_root_certificates().cloned().collect()
+  }
+
+  pub fn chain_certificates(&self) -> Vec<NamedCertificate>
+  {
+    self.policy.get_intermediate_certificates().cloned().collect()
+  }
+
+  pub fn template_names(&self) -> Result<Vec<String>, AdcsError>
+  {
+    Ok(self.policy
+      .get_templates()
+      .filter(|template| template.can_enroll())
+      .map(|template| template.get_name().to_owned())
+      .collect())
+  }
+
+  #[inline]
+  pub fn get_policy(&self) -> &'_ Policy
+  {
+    &self.policy
+  }
+
+  #[inline]
+  pub fn get_identity(&self) -> &'_ Identity
+  {
+    &self.identity
+  }
+
+  pub fn request_builder(&self, template: &str) -> Result<CertificationRequestBuilder<'_>, AdcsError>
+  {
+    let template = self.policy
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    Ok(CertificationRequestBuilder::new(template, self.identity.clone()))
+  }
 }
 
 pub enum EnrollmentResponse
@@ -32,14 +278,125 @@ pub enum EnrollmentResponse
     entity: X509Certificate,
     chain: Vec<X509Certificate>
   },
-  Pending(u32),
+  Pending(PendingRequest),
   Rejected(String)
 }
 
+impl EnrollmentResponse
+{
+  // responses only carry the request id, so the transport that received one records where it went
+  pub(crate) fn located(self, authority: &str, transport: Transport, template: &str) -> Self
+  {
+    match self
+    {
+      EnrollmentResponse::Pending(PendingRequest { request_id, .. }) => EnrollmentResponse::Pending(PendingRequest
+      {
+        request_id,
+        location: Some(RequestLocation { authority: authority.to_owned(), transport, template: template.to_owned() })
+      }),
+      response => response
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport
+{
+  Https(Url),
+  // the host name of the CA
+  Rpc(String)
+}
+
+// the CA a request is pending at, and the template it was made for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestLocation
+{
+  pub(crate) authority: String,
+  pub(crate) transport: Transport,
+  pub(crate) template: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingRequest
+{
+  request_id: u32,
+  location: Option<RequestLocation>
+}
+
+impl PendingRequest
+{
+  pub(crate) fn new(request_id: u32) -> Self
+  {
+    Self { request_id, location: None }
+  }
+
+  pub fn request_id(&self) -> u32
+  {
+    self.request_id
+  }
+
+  pub fn location(&self) -> Option<&RequestLocation>
+  {
+    self.location.as_ref()
+  }
+}
+
+// a form encoded cookie, so names with spaces or punctuation survive the trip through certmonger
+impl Display for PendingRequest
+{
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+  {
+    let mut cookie = url::form_urlencoded::Serializer::new(String::new());
+    cookie.append_pair("id", &self.request_id.to_string());
+    if let Some(location) = &self.location
+    {
+      match &location.transport
+      {
+        Transport::Https(endpoint) => cookie.append_pair("https", endpoint.as_str()),
+        Transport::Rpc(host_name) => cookie.append_pair("rpc", host_name)
+      };
+      cookie.append_pair("ca", &location.authority);
+      cookie.append_pair("template", &location.template);
+    }
+    f.write_str(&cookie.finish())
+  }
+}
+
+impl FromStr for PendingRequest
+{
+  type Err = DecodeError;
+
+  fn from_str(cookie: &str) -> Result<Self, Self::Err>
+  {
+    let bad = || DecodeError::BadRequestId(cookie.to_owned());
+    let (mut request_id, mut transport, mut authority, mut template) = (None, None, None, None);
+    for (name, value) in url::form_urlencoded::parse(cookie.trim().as_bytes())
+    {
+      match name.as_ref()
+      {
+        "id" => request_id = Some(value.parse::<u32>().map_err(|_| bad())?),
+        "https" => transport = Some(Transport::Https(Url::parse(&value).map_err(|_| bad())?)),
+        "rpc" => transport = Some(Transport::Rpc(value.into_owned())),
+        "ca" => authority = Some(value.into_owned()),
+        "template" => template = Some(value.into_owned()),
+        _ => return Err(bad())
+      }
+    }
+    let location = match (transport, authority, template)
+    {
+      (Some(transport), Some(authority), Some(template)) => Some(RequestLocation { authority, transport, template }),
+      (None, None, None) => None,
+      _ => return Err(bad())
+    };
+    Ok(Self { request_id: request_id.ok_or_else(bad)?, location })
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct Policy
 {
   id: String,
+  forest_oid: Option<String>,
   enrollment_services: Vec<EnrollmentService>,
   templates: Vec<CertificateTemplate>,
   root_certificates: Vec<NamedCertificate>,
@@ -49,28 +406,65 @@ pub struct Policy
 impl Policy
 {
   #[instrument]
-  pub fn new(domain: String, policy_id: String, policy_endpoints: Vec<PolicyEndpoint>) -> Result<Self, AdcsError>
+  pub fn new(domain: String, policy_id: Option<String>, sysvol: Option<PathBuf>, context: EnrollmentContext, options: LdapConnectionOptions) -> Result<Self, AdcsError>
+  {
+    let mut ldap = LdapManager::new(domain.to_lowercase(), context, &options)?;
+    let policy_endpoints = Self::endpoints(&mut ldap, sysvol.as_deref());
+    Self::discover(&mut ldap, policy_id, policy_endpoints)
+  }
+
+  // enrollment policy servers pushed by group policy, then the policy held in the directory.  group
+  // policy is read from a copy of the sysvol share, such as a mount or the samba cache, and is
+  // skipped without one
+  #[instrument]
+  pub fn discover_endpoints(domain: String, sysvol: Option<PathBuf>, context: EnrollmentContext, options: LdapConnectionOptions) -> Result<Vec<PolicyEndpoint>, AdcsError>
+  {
+    Ok(Self::endpoints(&mut LdapManager::new(domain.to_lowercase(), context, &options)?, sysvol.as_deref()))
+  }
+
+  // group policy that cannot be read leaves the directory to fall back on
+  pub(crate) fn endpoints(ldap: &mut LdapManager, sysvol: Option<&Path>) -> Vec<PolicyEndpoint>
+  {
+    let group_policy = match sysvol
+    {
+      Some(sysvol) => Self::group_policy_endpoints(ldap, sysvol).unwrap_or_else(|err|
+      {
+        event!(Level::WARN, "error reading group policy: {}.  skipping", err);
+        vec![]
+      }),
+      None => vec![]
+    };
+    group_policy.into_iter().chain(ldap.get_policy_endpoints()).collect()
+  }
+
+  fn group_policy_endpoints(ldap: &mut LdapManager, sysvol: &Path) -> Result<Vec<PolicyEndpoint>, AdcsError>
+  {
+    let paths: Vec<PathBuf> = ldap
+      .get_group_policy_objects()?
+      .iter()
+      .filter_map(|group_policy_object| group_policy::registry_policy_path(sysvol, group_policy_object, ldap.get_context()))
+      .collect();
+    Ok(group_policy::group_policy_servers(&paths)?)
+  }
+
+  pub(crate) fn discover(ldap: &mut LdapManager, policy_id: Option<String>, policy_endpoints: Vec<PolicyEndpoint>) -> Result<Self, AdcsError>
   {
-    let mut ldap = LdapManager::new(domain, false)?;
     for policy_endpoint in policy_endpoints.into_iter().sorted()
     {
-      match Policy::try_create(&policy_endpoint, &mut ldap)
+      match Policy::try_create(&policy_endpoint, ldap)
       {
-        Ok(policy) =>
+        Ok(policy) => match &policy_id
         {
-          if policy.get_id() == policy_id.as_str()
-          {
-            return Ok(policy)
-          }
-          else
+          Some(policy_id) if !policy.has_id(policy_id) =>
           {
             event!(Level::INFO, "found policy from endpoint {} with id {}, which doesn't match requested id {}.  discarding", policy_endpoint, policy.get_id(), policy_id);
-          }
+          },
+          _ => return Ok(policy.for_context(ldap.get_context()))
         },
         Err(err) => event!(Level::WARN, "error while retrieving policy: {}.  skipping", err)
       }
     }
-    Err(AdcsError::NoPolicies(policy_id))
+    Err(AdcsError::NoPolicies(policy_id.unwrap_or_else(|| String::from("<any>"))))
   }
 
   fn try_create(endpoint: &PolicyEndpoint, ldap: &mut LdapManager) -> Result<Self, AdcsError>
@@ -82,7 +476,7 @@ impl Policy
       {
         if cfg!(feature = "policy_https")
         {
-          Ok(http_client::get_policy(root_certificates, &endpoint.uri)?)
+          Ok(http_client::get_policy(root_certificates, &endpoint.uri, ldap.get_principal())?)
         }
         else
         {
@@ -114,10 +508,16 @@ impl Policy
       .filter(|enrollment_service| !root_certificates.contains(enrollment_service.get_certificate()))
       .map(|enrollment_service| enrollment_service.get_certificate().to_owned())
       .collect();
-    Policy { id, enrollment_services, templates, root_certificates, intermediate_certificates }
+    Policy { id, forest_oid: None, enrollment_services, templates, root_certificates, intermediate_certificates }
   }
 
-  fn get_enrollment_services_for_template<'a>(&'a self, template: &'a CertificateTemplate) -> Result<impl Iterator<Item = &'_ EnrollmentService> + 'a, AdcsError>
+  // the ldap policy can also be asked for by the forest oid it was derived from
+  pub(crate) fn with_forest_oid(self, forest_oid: String) -> Self
+  {
+    Policy { forest_oid: Some(forest_oid), ..self }
+  }
+
+  pub(crate) fn get_enrollment_services_for_template<'a>(&'a self, template: &'a CertificateTemplate) -> Result<impl Iterator<Item = &'_ EnrollmentService> + 'a, AdcsError>
   {
     if self.templates.iter().any(|x| x.cn == template.get_name() && x.can_enroll())
     {
@@ -132,12 +532,53 @@ impl Policy
     }
   }
 
+  pub(crate) fn restricted_to(&self, enrollment_service: &EnrollmentService) -> Self
+  {
+    Policy { enrollment_services: vec![enrollment_service.clone()], ..self.clone() }
+  }
+
+  // MS-CRTD 2.4 - a machine may only enroll for templates meant for computers
+  fn for_context(mut self, context: EnrollmentContext) -> Self
+  {
+    if context == EnrollmentContext::Machine
+    {
+      self.templates.retain(|template| template.general_flags.contains(GeneralFlags::MACHINE_TYPE));
+    }
+    self
+  }
+
+  pub fn get_enrollment_services(&self) -> impl Iterator<Item = &'_ EnrollmentService>
+  {
+    self.enrollment_services.iter()
+  }
+
+  // MS-CRTD 2.22, 2.23 - None when the template can be enrolled for without an enrollment agent
+  pub fn get_agent_requirements(&self, template: &str) -> Result<Option<&'_ RaRequirements>, AdcsError>
+  {
+    let requirements = self
+      .get_template_by_name(template)
+      .ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?
+      .get_ra_requirements();
+    Ok((requirements.signatures > 0).then_some(requirements))
+  }
+
   #[inline]
   pub fn get_id(&self) -> &'_ str
   {
     &self.id
   }
 
+  // policy ids show up both bare and as braced guids depending on where they came from, and the ldap policy may
+  // be named by its forest oid instead
+  pub fn has_id(&self, policy_id: &str) -> bool
+  {
+    fn normalize(id: &str) -> String
+    {
+      id.trim().trim_start_matches('{').trim_end_matches('}').to_lowercase()
+    }
+    normalize(&self.id) == normalize(policy_id) || self.forest_oid.as_deref() == Some(policy_id.trim())
+  }
+
   #[inline]
   pub fn get_templates(&self) -> impl Iterator<Item = &'_ CertificateTemplate>
   {
@@ -169,7 +610,7 @@ impl Policy
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HttpsEndpoint
 {
   client_authentication: ClientAuthentication,
@@ -244,28 +685,78 @@ impl EnrollmentService
   }
 }
 
-#[derive(Debug, Clone)]
+// MS-CRTD 2
+#[derive(Debug, Clone, Default, Builder)]
+#[builder(pattern = "owned", setter(into), default, vis = "pub(crate)")]
 pub struct CertificateTemplate
 {
   cn: String,
   enroll: bool,
   auto_enroll: bool,
-  extensions: Vec<(Oid, Vec<AttributeValue>)>
+  extensions: Vec<(Oid, Vec<AttributeValue>)>,
+  template_oid: Option<Oid>,
+  schema_version: u32,
+  revision: u32,
+  minor_revision: u32,
+  superseded_templates: Vec<String>,
+  subject_name_flags: SubjectNameFlags,
+  enrollment_flags: EnrollmentFlags,
+  private_key_flags: PrivateKeyFlags,
+  general_flags: GeneralFlags,
+  key_requirements: KeyRequirements,
+  hash_algorithm: Option<Oid>,
+  extended_key_usages: Vec<Oid>,
+  validity_period: Option<Duration>,
+  renewal_period: Option<Duration>,
+  ra_requirements: RaRequirements,
+  key_archival: Option<KeyArchivalAttributes>
 }
 
 impl CertificateTemplate
 {
-  pub(crate) fn apply_to_request(&self, request: CertificationRequest) -> std::result::Result<Vec<u8>, EncodeError>
+  pub(crate) fn apply_to_request(&self, request: CertificationRequest, signer: &dyn KeyInfoSigner) -> std::result::Result<Vec<u8>, EncodeError>
   {
-    Ok(CmcRequestBuilder::default()
+    CmcRequestBuilder::default()
       .add_certificate(request, self.extensions.clone())
+      .request_key_signer(signer)
       .build()
-      .try_into()?)
+      .try_into()
   }
 
-  pub(crate) fn new(cn: String, enroll: bool, auto_enroll: bool, extensions: Vec<(Oid, Vec<AttributeValue>)>) -> Self
+  pub(crate) fn apply_to_unsigned_request(&self, request: CertificationRequest) -> std::result::Result<Vec<u8>, EncodeError>
   {
-    Self { cn, enroll, auto_enroll, extensions }
+    CmcRequestBuilder::default()
+      .add_certificate(request, self.extensions.clone())
+      .unsigned()
+      .build()
+      .try_into()
+  }
+
+  pub(crate) fn apply_to_renewal(&self, request: CertificationRequest, signer: &dyn KeyInfoSigner, certificate: CapturedX509Certificate) -> std::result::Result<Vec<u8>, EncodeError>
+  {
+    CmcRequestBuilder::default()
+      .add_certificate(request, self.extensions.clone())
+      .signer(signer, certificate)
+      .build()
+      .try_into()
+  }
+
+  pub(crate) fn apply_to_agent_request(&self, request: CertificationRequest, requester_name: &str, signer: &dyn KeyInfoSigner, certificate: CapturedX509Certificate) -> std::result::Result<Vec<u8>, EncodeError>
+  {
+    CmcRequestBuilder::default()
+      .add_certificate(request, self.extensions.clone())
+      .on_behalf_of(requester_name, signer, certificate)
+      .build()
+      .try_into()
+  }
+
+  pub(crate) fn apply_to_archival_request(&self, request: CertificationRequest, signer: &dyn KeyInfoSigner, archival: KeyArchival) -> std::result::Result<Vec<u8>, EncodeError>
+  {
+    CmcRequestBuilder::default()
+      .add_certificate(request, self.extensions.clone())
+      .archive_key(signer, archival)
+      .build()
+      .try_into()
   }
 
   #[inline]
@@ -285,4 +776,101 @@ impl CertificateTemplate
   {
     &self.cn
   }
+
+  #[inline]
+  pub fn get_template_oid(&self) -> Option<&'_ Oid>
+  {
+    self.template_oid.as_ref()
+  }
+
+  #[inline]
+  pub fn get_schema_version(&self) -> u32
+  {
+    self.schema_version
+  }
+
+  #[inline]
+  pub fn get_revision(&self) -> u32
+  {
+    self.revision
+  }
+
+  #[inline]
+  pub fn get_minor_revision(&self) -> u32
+  {
+    self.minor_revision
+  }
+
+  #[inline]
+  pub fn get_superseded_templates(&self) -> impl Iterator<Item = &'_ str>
+  {
+    self.superseded_templates.iter().map(String::as_str)
+  }
+
+  #[inline]
+  pub fn get_subject_name_flags(&self) -> SubjectNameFlags
+  {
+    self.subject_name_flags
+  }
+
+  #[inline]
+  pub fn get_enrollment_flags(&self) -> EnrollmentFlags
+  {
+    self.enrollment_flags
+  }
+
+  #[inline]
+  pub fn get_private_key_flags(&self) -> PrivateKeyFlags
+  {
+    self.private_key_flags
+  }
+
+  #[inline]
+  pub fn get_general_flags(&self) -> GeneralFlags
+  {
+    self.general_flags
+  }
+
+  #[inline]
+  pub fn get_key_requirements(&self) -> &'_ KeyRequirements
+  {
+    &self.key_requirements
+  }
+
+  #[inline]
+  pub fn get_hash_algorithm(&self) -> Option<&'_ Oid>
+  {
+    self.hash_algorithm.as_ref()
+  }
+
+  #[inline]
+  pub fn get_extended_key_usages(&self) -> impl Iterator<Item = &'_ Oid>
+  {
+    self.extended_key_usages.iter()
+  }
+
+  #[inline]
+  pub fn get_validity_period(&self) -> Option<Duration>
+  {
+    self.validity_period
+  }
+
+  #[inline]
+  pub fn get_renewal_period(&self) -> Option<Duration>
+  {
+    self.renewal_period
+  }
+
+  #[inline]
+  pub fn get_ra_requirements(&self) -> &'_ RaRequirements
+  {
+    &self.ra_requirements
+  }
+
+  // None unless the template requires the private key to be archived with the CA
+  #[inline]
+  pub fn get_key_archival(&self) -> Option<&'_ KeyArchivalAttributes>
+  {
+    self.key_archival.as_ref()
+  }
 }
\ No newline at end of file
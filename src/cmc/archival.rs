@@ -0,0 +1,235 @@
+use aes::{Aes128, Aes192, Aes256};
+use bcder::{decode::Constructed, encode::{self, PrimitiveContent, Values}, Captured, Mode, Oid, Tag, Unsigned};
+use bytes::Bytes;
+use cbc::{Encryptor, cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7}};
+use des::TdesEde3;
+use rand::{thread_rng, RngCore};
+use rsa::{pkcs1::DecodeRsaPublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+use thiserror::Error;
+use x509_certificate::{rfc3447::RsaPrivateKey, rfc5958::OneAsymmetricKey, X509Certificate};
+
+use crate::{csr::ID_RSA_ENCRYPTION, template::KeyArchivalAttributes};
+
+pub const ID_ENVELOPED_DATA: &[u8] = &[42, 134, 72, 134, 247, 13, 1, 7, 3];
+pub const ID_DATA: &[u8] = &[42, 134, 72, 134, 247, 13, 1, 7, 1];
+pub const ID_DES_EDE3_CBC: &[u8] = &[42, 134, 72, 134, 247, 13, 3, 7];
+pub const ID_AES128_CBC: &[u8] = &[96, 134, 72, 1, 101, 3, 4, 1, 2];
+pub const ID_AES192_CBC: &[u8] = &[96, 134, 72, 1, 101, 3, 4, 1, 22];
+pub const ID_AES256_CBC: &[u8] = &[96, 134, 72, 1, 101, 3, 4, 1, 42];
+pub const ID_ARCHIVED_KEY_ATTR: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 21, 13];
+pub const ID_ENCRYPTED_KEY_HASH: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 21, 21];
+
+// [MS-WINCRYPT] BLOBHEADER and RSAPUBKEY for a PRIVATEKEYBLOB holding an exchange key
+const PRIVATEKEYBLOB: u8 = 0x07;
+const CUR_BLOB_VERSION: u8 = 0x02;
+const CALG_RSA_KEYX: u32 = 0x0000_a400;
+const RSA2: &[u8] = b"RSA2";
+
+#[derive(Error, Debug)]
+pub enum ArchivalError
+{
+  #[error("only rsa keys can be archived")]
+  UnsupportedKeyAlgorithm,
+
+  #[error("unsupported symmetric algorithm {0}")]
+  UnsupportedSymmetricAlgorithm(String),
+
+  #[error("invalid private key: {0}")]
+  BadPrivateKey(String),
+
+  #[error("invalid exchange certificate: {0}")]
+  BadExchangeCertificate(String),
+
+  #[error("error encrypting private key: {0}")]
+  Encryption(#[from] rsa::Error)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymmetricAlgorithm
+{
+  TripleDes,
+  Aes128,
+  Aes192,
+  #[default]
+  Aes256
+}
+
+impl SymmetricAlgorithm
+{
+  // the template may leave the algorithm unspecified, in which case any the CA supports will do
+  pub fn from_template(attributes: &KeyArchivalAttributes) -> Result<Self, ArchivalError>
+  {
+    match attributes.symmetric_algorithm.as_ref().map(|oid| oid.as_ref())
+    {
+      None => Ok(Self::default()),
+      Some(ID_DES_EDE3_CBC) => Ok(Self::TripleDes),
+      Some(ID_AES128_CBC) => Ok(Self::Aes128),
+      Some(ID_AES192_CBC) => Ok(Self::Aes192),
+      Some(ID_AES256_CBC) => Ok(Self::Aes256),
+      Some(other) => Err(ArchivalError::UnsupportedSymmetricAlgorithm(Oid(Bytes::copy_from_slice(other)).to_string()))
+    }
+  }
+
+  fn oid(&self) -> Oid
+  {
+    Oid(Bytes::from_static(match self
+    {
+      Self::TripleDes => ID_DES_EDE3_CBC,
+      Self::Aes128 => ID_AES128_CBC,
+      Self::Aes192 => ID_AES192_CBC,
+      Self::Aes256 => ID_AES256_CBC
+    }))
+  }
+
+  fn key_length(&self) -> usize
+  {
+    match self
+    {
+      Self::TripleDes | Self::Aes192 => 24,
+      Self::Aes128 => 16,
+      Self::Aes256 => 32
+    }
+  }
+
+  fn iv_length(&self) -> usize
+  {
+    match self
+    {
+      Self::TripleDes => 8,
+      _ => 16
+    }
+  }
+
+  // returns the content encryption key, the iv and the padded ciphertext
+  fn encrypt(&self, plaintext: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>)
+  {
+    let mut key = vec![0; self.key_length()];
+    let mut iv = vec![0; self.iv_length()];
+    thread_rng().fill_bytes(&mut key);
+    thread_rng().fill_bytes(&mut iv);
+    let ciphertext = match self
+    {
+      Self::TripleDes => Encryptor::<TdesEde3>::new(key.as_slice().into(), iv.as_slice().into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+      Self::Aes128 => Encryptor::<Aes128>::new(key.as_slice().into(), iv.as_slice().into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+      Self::Aes192 => Encryptor::<Aes192>::new(key.as_slice().into(), iv.as_slice().into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext),
+      Self::Aes256 => Encryptor::<Aes256>::new(key.as_slice().into(), iv.as_slice().into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext)
+    };
+    (key, iv, ciphertext)
+  }
+}
+
+// a private key encrypted to the CA exchange certificate, for CAs which archive keys with a key
+// recovery agent
+pub struct KeyArchival
+{
+  exchange_certificate: X509Certificate,
+  algorithm: SymmetricAlgorithm,
+  private_key_blob: Vec<u8>
+}
+
+impl KeyArchival
+{
+  pub fn new(exchange_certificate: X509Certificate, algorithm: SymmetricAlgorithm, private_key: &[u8]) -> Result<Self, ArchivalError>
+  {
+    Ok(Self { exchange_certificate, algorithm, private_key_blob: private_key_blob(private_key)? })
+  }
+
+  // RFC 5652 6.1 - a single key transport recipient identified by issuer and serial number
+  pub(crate) fn envelope(&self) -> Result<Captured, ArchivalError>
+  {
+    let (key, iv, encrypted_content) = self.algorithm.encrypt(&self.private_key_blob);
+    let encrypted_key = RsaPublicKey::from_pkcs1_der(&self.exchange_certificate.public_key_data())
+      .map_err(|err| ArchivalError::BadExchangeCertificate(err.to_string()))?
+      .encrypt(&mut thread_rng(), Pkcs1v15Encrypt, &key)?;
+
+    Ok(encode::sequence(
+    (
+      Oid(Bytes::from_static(ID_ENVELOPED_DATA)).encode(),
+      encode::sequence_as(Tag::CTX_0, encode::sequence(
+      (
+        0u8.encode(),
+        encode::set(encode::sequence(
+        (
+          0u8.encode(),
+          encode::sequence(
+          (
+            self.exchange_certificate.issuer_name().encode_ref(),
+            self.exchange_certificate.serial_number_asn1().encode()
+          )),
+          encode::sequence((Oid(Bytes::from_static(ID_RSA_ENCRYPTION)).encode(), ().encode())),
+          encrypted_key.as_slice().encode()
+        ))),
+        encode::sequence(
+        (
+          Oid(Bytes::from_static(ID_DATA)).encode(),
+          encode::sequence((self.algorithm.oid().encode(), iv.as_slice().encode())),
+          encrypted_content.as_slice().encode_as(Tag::CTX_0)
+        ))
+      )))
+    )).to_captured(Mode::Der))
+  }
+}
+
+// the CA expects the CryptoAPI PRIVATEKEYBLOB rather than pkcs#8: the modulus and private exponent
+// at full length, the remaining CRT values at half length, all little endian
+pub(crate) fn private_key_blob(private_key: &[u8]) -> Result<Vec<u8>, ArchivalError>
+{
+  let private_key = Constructed::decode(private_key, Mode::Der, OneAsymmetricKey::take_from)
+    .map_err(|err| ArchivalError::BadPrivateKey(err.to_string()))?;
+  if private_key.private_key_algorithm.algorithm.as_ref() != ID_RSA_ENCRYPTION
+  {
+    return Err(ArchivalError::UnsupportedKeyAlgorithm);
+  }
+  let private_key = Constructed::decode(private_key.private_key.to_bytes(), Mode::Der, RsaPrivateKey::take_from)
+    .map_err(|err| ArchivalError::BadPrivateKey(err.to_string()))?;
+
+  let modulus = trim(&private_key.n);
+  let bit_length = modulus
+    .first()
+    .map(|first| (modulus.len() - 1) * 8 + (8 - first.leading_zeros() as usize))
+    .unwrap_or_default();
+  let length = (bit_length + 7) / 8;
+  let half_length = (bit_length + 15) / 16;
+  let public_exponent = trim(&private_key.e);
+  if public_exponent.len() > 4
+  {
+    return Err(ArchivalError::BadPrivateKey("public exponent does not fit in 32 bits".to_owned()));
+  }
+
+  let mut blob = vec![PRIVATEKEYBLOB, CUR_BLOB_VERSION, 0, 0];
+  blob.extend_from_slice(&CALG_RSA_KEYX.to_le_bytes());
+  blob.extend_from_slice(RSA2);
+  blob.extend_from_slice(&(bit_length as u32).to_le_bytes());
+  blob.extend(little_endian(public_exponent, 4)?);
+  for (value, value_length) in
+  [
+    (&private_key.n, length),
+    (&private_key.p, half_length),
+    (&private_key.q, half_length),
+    (&private_key.dp, half_length),
+    (&private_key.dq, half_length),
+    (&private_key.q_inv, half_length),
+    (&private_key.d, length)
+  ]
+  {
+    blob.extend(little_endian(trim(value), value_length)?);
+  }
+  Ok(blob)
+}
+
+fn trim(value: &Unsigned) -> &[u8]
+{
+  let bytes = value.as_slice();
+  &bytes[bytes.iter().take_while(|b| **b == 0).count()..]
+}
+
+fn little_endian(big_endian: &[u8], length: usize) -> Result<Vec<u8>, ArchivalError>
+{
+  if big_endian.len() > length
+  {
+    return Err(ArchivalError::BadPrivateKey("key component is longer than the modulus allows".to_owned()));
+  }
+  let mut value: Vec<u8> = big_endian.iter().rev().copied().collect();
+  value.resize(length, 0);
+  Ok(value)
+}
@@ -1,15 +1,24 @@
 pub mod rfc5272;
+pub mod archival;
 
 #[cfg(test)]
 #[allow(clippy::expect_used)]
 mod tests;
 
-use bcder::{decode::Constructed, Integer};
-use cryptographic_message_syntax::{SignedDataBuilder, Oid, Bytes, SignerBuilder, asn1::rfc5652::{SignerIdentifier, IssuerAndSerialNumber, CertificateSerialNumber, self}, CmsError, SignedData};
-use signature::Error;
-use x509_certificate::{rfc2986::CertificationRequest, KeyAlgorithm, KeyInfoSigner, Signature, Signer, Sign, SignatureAlgorithm, X509CertificateError, DigestAlgorithm, rfc3280::Name, X509Certificate};
-use self::rfc5272::{AttributeValue, PKIData, TaggedAttribute, TaggedRequest, TaggedCertificationRequest};
-use std::str::FromStr;
+use bcder::{decode::Constructed, encode::{self, PrimitiveContent, Values}, Captured, Integer, Mode, OctetString, Tag, Utf8String};
+use chrono::{DateTime, Utc};
+use cryptographic_message_syntax::{SignedDataBuilder, Oid, Bytes, SignerBuilder, asn1::rfc5652::{SignerIdentifier, self}, CmsError, SignedData};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use tracing::{event, Level};
+use x509_certificate::{rfc2986::CertificationRequest, rfc5280::{AlgorithmIdentifier, AlgorithmParameter}, KeyInfoSigner, Sign, DigestAlgorithm, X509Certificate, CapturedX509Certificate};
+use crate::{EnrollmentResponse, PendingRequest, DecodeError, EncodeError};
+use self::archival::{KeyArchival, ID_ARCHIVED_KEY_ATTR, ID_ENCRYPTED_KEY_HASH};
+use self::rfc5272::{AttributeValue, PKIData, PKIResponse, TaggedAttribute, TaggedRequest, TaggedCertificationRequest, CMCStatusInfoV2, BodyPartReference, OtherStatusInfo, PendInfo, ID_CCT_PKI_DATA, ID_CCT_PKI_RESPONSE, ID_CMC_STATUS_INFO, ID_CMC_STATUS_INFO_V2, ID_ENROLLMENT_NAME_VALUE_PAIR, ID_ALG_NO_SIGNATURE};
+
+// RFC 5272 3.2.1 - body part ids are numbered from one, as windows does.  a single request is sent,
+// so responses report on this body part
+const REQUEST_BODY_PART_ID: u32 = 1;
 
 struct AttributedCertificationRequest
 {
@@ -17,16 +26,30 @@ struct AttributedCertificationRequest
   attributes: Vec<(Oid, Vec<AttributeValue>)>
 }
 
+pub enum CmcSigner<'a>
+{
+  // RFC 5272 3.2.1.3.1 - the pkcs#10 carries its own proof of possession, so the SignerInfo holds
+  // only a digest of the content under id-alg-noSignature.  for callers without the private key
+  Unsigned,
+  // RFC 5272 3.2.1.3.2 - signed by the key being certified, identified by its subjectKeyIdentifier
+  RequestKey(&'a dyn KeyInfoSigner),
+  // renewal and enrollment agent requests are signed by an existing certificate
+  Certificate(&'a dyn KeyInfoSigner, CapturedX509Certificate)
+}
+
 #[derive(Default)]
-pub struct CmcRequest
+pub struct CmcRequest<'a>
 {
-  certificate_requests: Vec<AttributedCertificationRequest>
+  certificate_requests: Vec<AttributedCertificationRequest>,
+  signer: Option<CmcSigner<'a>>,
+  requester_name: Option<String>,
+  archival: Option<KeyArchival>
 }
 
 #[derive(Default)]
-pub struct CmcRequestBuilder(CmcRequest);
+pub struct CmcRequestBuilder<'a>(CmcRequest<'a>);
 
-impl CmcRequestBuilder
+impl<'a> CmcRequestBuilder<'a>
 {
   pub fn add_certificate(mut self, request: CertificationRequest, attributes: Vec<(Oid, Vec<AttributeValue>)>) -> Self
   {
@@ -34,21 +57,61 @@ impl CmcRequestBuilder
     self
   }
 
-  pub fn build(self) -> CmcRequest
+  // MS-WCCE 3.2.1.4.2.1.4 - renewals are signed by the certificate being renewed
+  pub fn signer(mut self, signer: &'a dyn KeyInfoSigner, certificate: CapturedX509Certificate) -> Self
+  {
+    self.0.signer = Some(CmcSigner::Certificate(signer, certificate));
+    self
+  }
+
+  pub fn request_key_signer(mut self, signer: &'a dyn KeyInfoSigner) -> Self
+  {
+    self.0.signer = Some(CmcSigner::RequestKey(signer));
+    self
+  }
+
+  // MS-WCCE 3.2.2.6.2.1.4.5.1 - an enrollment agent signs the request and names the real requester
+  pub fn on_behalf_of(mut self, requester_name: impl Into<String>, signer: &'a dyn KeyInfoSigner, certificate: CapturedX509Certificate) -> Self
+  {
+    self.0.requester_name = Some(requester_name.into());
+    self.0.signer = Some(CmcSigner::Certificate(signer, certificate));
+    self
+  }
+
+  // key archival requests must be signed by the key being archived
+  pub fn archive_key(mut self, signer: &'a dyn KeyInfoSigner, archival: KeyArchival) -> Self
+  {
+    self.0.archival = Some(archival);
+    self.0.signer = Some(CmcSigner::RequestKey(signer));
+    self
+  }
+
+  pub fn unsigned(mut self) -> Self
+  {
+    self.0.signer = Some(CmcSigner::Unsigned);
+    self
+  }
+
+  pub fn build(self) -> CmcRequest<'a>
   {
     self.0
   }
 }
 
-impl TryInto<Vec<u8>> for CmcRequest
+impl<'a> TryInto<Vec<u8>> for CmcRequest<'a>
 {
-  type Error = CmsError;
+  type Error = EncodeError;
 
   fn try_into(self) -> Result<Vec<u8>, Self::Error>
   {
+    let signer = self.signer.ok_or(EncodeError::NoSigner)?;
+    let archived_key = self.archival.as_ref().map(KeyArchival::envelope).transpose()?;
+    let request_key_identifier = self.certificate_requests
+      .first()
+      .map(|request| subject_key_identifier(&request.request.certificate_request_info.subject_public_key_info.subject_public_key.octet_bytes()));
     let mut control_sequence = Vec::new();
     let mut req_sequence = Vec::new();
-    for (body_part_id, AttributedCertificationRequest { request, attributes }) in self.certificate_requests.into_iter().enumerate()
+    for (body_part_id, AttributedCertificationRequest { request, attributes }) in (REQUEST_BODY_PART_ID..).zip(self.certificate_requests)
     {
       let body_part_id = Integer::from(body_part_id as u64);
       req_sequence.push(TaggedRequest::TaggedCertificationRequest(TaggedCertificationRequest { body_part_id: body_part_id.clone(), certification_request: request }));
@@ -56,6 +119,26 @@ impl TryInto<Vec<u8>> for CmcRequest
       {
         control_sequence.push(TaggedAttribute { body_part_id: body_part_id.clone(), attr_type, attr_values })
       }
+      if let Some(requester_name) = &self.requester_name
+      {
+        control_sequence.push(TaggedAttribute
+        {
+          body_part_id: body_part_id.clone(),
+          attr_type: Oid(Bytes::from_static(ID_ENROLLMENT_NAME_VALUE_PAIR)),
+          attr_values: vec![AttributeValue::new(name_value_pair("RequesterName", requester_name))]
+        })
+      }
+      if let Some(archived_key) = &archived_key
+      {
+        let mut digester = DigestAlgorithm::Sha1.digester();
+        digester.update(archived_key.as_slice());
+        control_sequence.push(TaggedAttribute
+        {
+          body_part_id: body_part_id.clone(),
+          attr_type: Oid(Bytes::from_static(ID_ENCRYPTED_KEY_HASH)),
+          attr_values: vec![AttributeValue::new(digester.finish().as_ref().encode().to_captured(Mode::Der).into_bytes())]
+        })
+      }
     }
 
     let pkidata = PKIData
@@ -66,20 +149,99 @@ impl TryInto<Vec<u8>> for CmcRequest
       other_msg_sequence: vec![]
     }.encode_der()?;
 
-    let subject_identifier = SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber
-      {
-        issuer: Name::default(),
-        serial_number: CertificateSerialNumber::from(0)
-      });
-    SignedDataBuilder::default()
+    let mut digester = DigestAlgorithm::Sha1.digester();
+    digester.update(&pkidata);
+    let content_digest = Bytes::copy_from_slice(digester.finish().as_ref());
+
+    let builder = SignedDataBuilder::default()
       .content_inline(pkidata)
-      .content_type(Oid(Bytes::from_static(&[43u8, 6u8, 1u8, 5u8, 5u8, 7u8, 12u8, 2u8])))
-      .signer(SignerBuilder::new_with_signer_identifier(&NullKeyInfoSigner { digest_algorithm: DigestAlgorithm::Sha256 }, subject_identifier))
-      .build_der()
+      .content_type(Oid(Bytes::from_static(ID_CCT_PKI_DATA)));
+    let signed_data = match signer
+    {
+      CmcSigner::Unsigned => match request_key_identifier
+      {
+        Some(request_key_identifier) => attach_null_signature(&builder.build_der()?, request_key_identifier, content_digest),
+        None => Ok(builder.build_der()?)
+      },
+      CmcSigner::RequestKey(signer) => builder
+        .signer(SignerBuilder::new_with_signer_identifier(signer, SignerIdentifier::SubjectKeyIdentifier(subject_key_identifier(&signer.public_key_data()))))
+        .build_der()
+        .map_err(EncodeError::from),
+      CmcSigner::Certificate(signer, certificate) => builder
+        .certificate(certificate.clone())
+        .signer(SignerBuilder::new(signer, certificate))
+        .build_der()
+        .map_err(EncodeError::from)
+    }?;
+
+    match archived_key
+    {
+      Some(archived_key) => attach_archived_key(&signed_data, archived_key),
+      None => Ok(signed_data)
+    }
+  }
+}
+
+// RFC 5280 4.2.1.2 (1)
+fn subject_key_identifier(public_key: &[u8]) -> OctetString
+{
+  let mut digester = DigestAlgorithm::Sha1.digester();
+  digester.update(public_key);
+  OctetString::new(Bytes::copy_from_slice(digester.finish().as_ref()))
+}
+
+// with no key to sign with, the SignerInfo names the requested key and carries the content digest
+// in place of a signature, as id-alg-noSignature
+fn attach_null_signature(signed_data: &[u8], request_key_identifier: OctetString, content_digest: Bytes) -> Result<Vec<u8>, EncodeError>
+{
+  let mut signed_data = rfc5652::SignedData::decode_ber(signed_data).map_err(CmsError::from)?;
+  signed_data.version = rfc5652::CmsVersion::V3;
+  signed_data.digest_algorithms.push(DigestAlgorithm::Sha1.into());
+  signed_data.signer_infos.push(rfc5652::SignerInfo
+  {
+    version: rfc5652::CmsVersion::V3,
+    sid: SignerIdentifier::SubjectKeyIdentifier(request_key_identifier),
+    digest_algorithm: DigestAlgorithm::Sha1.into(),
+    signed_attributes: None,
+    signature_algorithm: AlgorithmIdentifier
+    {
+      algorithm: Oid(Bytes::from_static(ID_ALG_NO_SIGNATURE)),
+      parameters: Some(AlgorithmParameter::from_captured(().encode().to_captured(Mode::Der)))
+    },
+    signature: OctetString::new(content_digest),
+    unsigned_attributes: None,
+    signed_attributes_data: None
+  });
+  Ok(signed_data.encode_ref().to_captured(Mode::Der).into_bytes().to_vec())
+}
+
+// the archived key is an unauthenticated attribute, so it is attached once the SignerInfo is complete
+fn attach_archived_key(signed_data: &[u8], archived_key: Captured) -> Result<Vec<u8>, EncodeError>
+{
+  let mut signed_data = rfc5652::SignedData::decode_ber(signed_data).map_err(CmsError::from)?;
+  for signer_info in signed_data.signer_infos.iter_mut()
+  {
+    signer_info.unsigned_attributes.get_or_insert_with(Default::default).push(rfc5652::Attribute
+    {
+      typ: Oid(Bytes::from_static(ID_ARCHIVED_KEY_ATTR)),
+      values: vec![rfc5652::AttributeValue::new(archived_key.clone())]
+    });
   }
+  Ok(signed_data.encode_ref().to_captured(Mode::Der).into_bytes().to_vec())
+}
+
+// MS-WCCE 2.2.2.7.10
+fn name_value_pair(name: &str, value: &str) -> Bytes
+{
+  let name: Vec<u8> = name.encode_utf16().flat_map(|c| c.to_be_bytes()).collect();
+  let value: Vec<u8> = value.encode_utf16().flat_map(|c| c.to_be_bytes()).collect();
+  encode::sequence((
+    name.as_slice().encode_as(Tag::BMP_STRING),
+    value.as_slice().encode_as(Tag::BMP_STRING)
+  )).to_captured(Mode::Der).into_bytes()
 }
 
-impl TryFrom<Vec<u8>> for CmcRequest
+impl<'a> TryFrom<Vec<u8>> for CmcRequest<'a>
 {
   type Error = CmsError;
 
@@ -109,7 +271,7 @@ impl TryFrom<Vec<u8>> for CmcRequest
         }
       }).collect();
 
-      Ok(CmcRequest { certificate_requests })
+      Ok(CmcRequest { certificate_requests, signer: None, requester_name: None, archival: None })
     }
     else
     {
@@ -118,106 +280,299 @@ impl TryFrom<Vec<u8>> for CmcRequest
   }
 }
 
-pub struct CmcResponse
+// RFC 5272 6.1.4
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum CmcFailInfo
 {
-  certificates: Vec<X509Certificate>
+  BadAlgorithm = 0,
+  BadMessageCheck = 1,
+  BadRequest = 2,
+  BadTime = 3,
+  BadCertificateId = 4,
+  UnsupportedExtension = 5,
+  MustArchiveKeys = 6,
+  BadIdentity = 7,
+  PopRequired = 8,
+  PopFailed = 9,
+  NoKeyReuse = 10,
+  InternalCaError = 11,
+  TryLater = 12,
+  AuthDataFail = 13
 }
 
-impl CmcResponse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmcStatus
 {
-  fn get_certificates(&self) -> impl Iterator<Item = &'_ X509Certificate>
+  Success,
+  Failed(Option<CmcFailInfo>),
+  Pending
   {
-    self.certificates.iter()
-  }
+    token: Vec<u8>,
+    time: Option<DateTime<Utc>>
+  },
+  NoSupport,
+  ConfirmRequired,
+  PopRequired,
+  Partial
 }
 
-impl TryInto<Vec<u8>> for CmcResponse
+impl CmcStatus
 {
-  type Error = CmsError;
-
-  fn try_into(self) -> Result<Vec<u8>, Self::Error>
+  fn new(status: &Integer, other_info: Option<OtherStatusInfo>) -> Option<Self>
   {
-    todo!()
+    match (u32::try_from(status).ok()?, other_info)
+    {
+      (0, _) => Some(Self::Success),
+      (2, Some(OtherStatusInfo::FailInfo(fail_info))) => Some(Self::Failed(u32::try_from(&fail_info).ok().and_then(CmcFailInfo::from_u32))),
+      (2, _) => Some(Self::Failed(None)),
+      (3, Some(OtherStatusInfo::PendInfo(PendInfo { pend_token, pend_time }))) => Some(Self::Pending { token: pend_token.to_bytes().to_vec(), time: Some(pend_time.into()) }),
+      (3, _) => Some(Self::Pending { token: vec![], time: None }),
+      (4, _) => Some(Self::NoSupport),
+      (5, _) => Some(Self::ConfirmRequired),
+      (6, _) => Some(Self::PopRequired),
+      (7, _) => Some(Self::Partial),
+      _ => None
+    }
   }
-}
 
-impl TryFrom<Vec<u8>> for CmcResponse
-{
-  type Error = CmsError;
+  fn code(&self) -> u32
+  {
+    match self
+    {
+      Self::Success => 0,
+      Self::Failed(_) => 2,
+      Self::Pending { .. } => 3,
+      Self::NoSupport => 4,
+      Self::ConfirmRequired => 5,
+      Self::PopRequired => 6,
+      Self::Partial => 7
+    }
+  }
 
-  fn try_from(value: Vec<u8>) -> Result<Self, Self::Error>
+  // adcs hands out the request id as the pend token, as a little endian DWORD
+  pub fn request_id(&self) -> Option<u32>
   {
-    todo!()
+    match self
+    {
+      Self::Pending { token, .. } if !token.is_empty() && token.len() <= 4 =>
+      {
+        let mut request_id = [0u8; 4];
+        request_id[..token.len()].copy_from_slice(token);
+        Some(u32::from_le_bytes(request_id))
+      },
+      _ => None
+    }
   }
 }
 
-trait SignedDataExt<'a>: TryFrom<&'a rfc5652::SignedData>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CmcStatusInfo
 {
-  fn parse_der(data: &[u8]) -> Result<Self, CmsError>;
+  pub body_parts: Vec<u32>,
+  pub status: CmcStatus,
+  pub message: Option<String>
 }
 
-impl<'a> SignedDataExt<'a> for SignedData
+impl CmcStatusInfo
 {
-  fn parse_der(data: &[u8]) -> Result<Self, CmsError>
+  fn new(status_info: CMCStatusInfoV2) -> Option<Self>
   {
-    SignedData::try_from(&Constructed::decode(data, bcder::Mode::Der, |cons| rfc5652::SignedData::decode(cons))?)
+    let body_parts = status_info.body_list
+      .iter()
+      .filter_map(|body_part| match body_part
+      {
+        BodyPartReference::BodyPartID(body_part_id) => Some(body_part_id),
+        BodyPartReference::BodyPartPath(body_part_path) => body_part_path.last()
+      })
+      .filter_map(|body_part_id| u32::try_from(body_part_id).ok())
+      .collect();
+    Some(Self
+    {
+      body_parts,
+      status: CmcStatus::new(&status_info.cmc_status, status_info.other_info)?,
+      message: status_info.status_string.map(|message| message.to_string())
+    })
+  }
+
+  fn into_status_info(self) -> CMCStatusInfoV2
+  {
+    let cmc_status = Integer::from(self.status.code() as u64);
+    let other_info = match self.status
+    {
+      CmcStatus::Failed(Some(fail_info)) => Some(OtherStatusInfo::FailInfo(Integer::from(fail_info as u64))),
+      CmcStatus::Pending { token, time } => Some(OtherStatusInfo::PendInfo(PendInfo { pend_token: OctetString::new(token.into()), pend_time: time.unwrap_or_else(Utc::now).into() })),
+      _ => None
+    };
+    CMCStatusInfoV2
+    {
+      cmc_status,
+      body_list: self.body_parts.into_iter().map(|body_part| BodyPartReference::BodyPartID(Integer::from(body_part as u64))).collect(),
+      status_string: self.message.and_then(|message| Utf8String::from_string(message).ok()),
+      other_info
+    }
   }
 }
 
-struct NullKeyInfoSigner
+#[derive(Default)]
+pub struct CmcResponse
 {
-  digest_algorithm: DigestAlgorithm
+  certificates: Vec<X509Certificate>,
+  statuses: Vec<CmcStatusInfo>
 }
 
-impl NullKeyInfoSigner
+impl CmcResponse
 {
-  fn digest(&self, data: &[u8]) -> Vec<u8>
+  pub fn new(certificates: Vec<X509Certificate>, statuses: Vec<CmcStatusInfo>) -> Self
   {
-    let mut digester = self.digest_algorithm.digester();
-    digester.update(data);
-    digester.finish().as_ref().to_vec()
+    Self { certificates, statuses }
   }
-}
 
-impl KeyInfoSigner for NullKeyInfoSigner {}
+  pub fn get_certificates(&self) -> impl Iterator<Item = &'_ X509Certificate>
+  {
+    self.certificates.iter()
+  }
 
-impl Signer<Signature> for NullKeyInfoSigner
-{
-  fn try_sign(&self, msg: &[u8]) -> Result<Signature, Error>
+  pub fn get_statuses(&self) -> impl Iterator<Item = &'_ CmcStatusInfo>
   {
-    Ok(self.digest(msg).into())
+    self.statuses.iter()
   }
-}
 
-impl Sign for NullKeyInfoSigner
-{
-  fn sign(&self, msg: &[u8]) -> Result<(Vec<u8>, SignatureAlgorithm), X509CertificateError>
+  pub fn into_certificates(self) -> Vec<X509Certificate>
   {
-    Ok((self.digest(msg), self.signature_algorithm()?))
+    self.certificates
   }
 
-  fn key_algorithm(&self) -> Option<KeyAlgorithm>
+  // the issued certificate is the one that didn't issue anything else in the bag
+  fn take_entity(&mut self) -> Option<X509Certificate>
   {
-    unimplemented!()
+    let position = self.certificates
+      .iter()
+      .position(|candidate| !self.certificates
+        .iter()
+        .any(|other| other != candidate && other.issuer_name() == candidate.subject_name()))?;
+    Some(self.certificates.remove(position))
   }
+}
+
+impl TryInto<Vec<u8>> for CmcResponse
+{
+  type Error = CmsError;
 
-  fn public_key_data(&self) -> Bytes
+  fn try_into(self) -> Result<Vec<u8>, Self::Error>
   {
-    unimplemented!()
+    let control_sequence = (1u64..)
+      .zip(self.statuses)
+      .map(|(body_part_id, status)| -> Result<TaggedAttribute, CmsError>
+      {
+        Ok(TaggedAttribute
+        {
+          body_part_id: Integer::from(body_part_id),
+          attr_type: Oid(Bytes::from_static(ID_CMC_STATUS_INFO_V2)),
+          attr_values: vec![AttributeValue::new(status.into_status_info().encode_der()?.into())]
+        })
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let pkiresponse = PKIResponse
+    {
+      control_sequence,
+      cms_sequence: vec![],
+      other_msg_sequence: vec![]
+    }.encode_der()?;
+
+    self.certificates
+      .iter()
+      .map(|certificate| CapturedX509Certificate::from_der(certificate.encode_der()?).map_err(CmsError::from))
+      .collect::<Result<Vec<_>, _>>()?
+      .into_iter()
+      .fold(SignedDataBuilder::default()
+        .content_inline(pkiresponse)
+        .content_type(Oid(Bytes::from_static(ID_CCT_PKI_RESPONSE))), |builder, certificate| builder.certificate(certificate))
+      .build_der()
   }
+}
+
+impl TryFrom<Vec<u8>> for CmcResponse
+{
+  type Error = CmsError;
 
-  fn signature_algorithm(&self) -> Result<SignatureAlgorithm, X509CertificateError>
+  fn try_from(value: Vec<u8>) -> Result<Self, Self::Error>
   {
-    Ok(SignatureAlgorithm::from_digest_algorithm(self.digest_algorithm))
+    let signed_data = SignedData::parse_ber(value.as_slice())?;
+    let certificates = signed_data.certificates().map(|certificate| (**certificate).clone()).collect();
+    let statuses = match signed_data.signed_content()
+    {
+      Some(content) => PKIResponse::decode_der(content)?.control_sequence
+        .into_iter()
+        .filter(|control| control.attr_type.as_ref() == ID_CMC_STATUS_INFO_V2 || control.attr_type.as_ref() == ID_CMC_STATUS_INFO)
+        .flat_map(|control| control.attr_values)
+        .filter_map(|value| match CMCStatusInfoV2::decode_der(value.as_slice())
+        {
+          Ok(status_info) => CmcStatusInfo::new(status_info),
+          Err(err) => { event!(Level::WARN, "invalid cmc status info: {}", err); None }
+        })
+        .collect(),
+      None => vec![]
+    };
+
+    Ok(CmcResponse { certificates, statuses })
   }
+}
+
+// RFC 5272 3.2.1: a response without any status info is a simple PKI response.  otherwise the status
+// naming the request applies, or failing that one with an empty body list, which covers every body part
+impl TryFrom<CmcResponse> for EnrollmentResponse
+{
+  type Error = DecodeError;
 
-  fn private_key_data(&self) -> Option<Vec<u8>>
+  fn try_from(mut value: CmcResponse) -> Result<Self, Self::Error>
   {
-    unimplemented!()
+    let status = match value.statuses.is_empty()
+    {
+      true => None,
+      false =>
+      {
+        let position = value.statuses
+          .iter()
+          .position(|status| status.body_parts.contains(&REQUEST_BODY_PART_ID))
+          .or_else(|| value.statuses.iter().position(|status| status.body_parts.is_empty()))
+          .ok_or(DecodeError::NoStatus(REQUEST_BODY_PART_ID))?;
+        Some(value.statuses.swap_remove(position))
+      }
+    };
+    match status
+    {
+      Some(CmcStatusInfo { status: CmcStatus::Success, .. }) | None =>
+      {
+        let entity = value.take_entity().ok_or(DecodeError::EmptyResponse)?;
+        Ok(EnrollmentResponse::Issued { entity, chain: value.certificates })
+      },
+      Some(CmcStatusInfo { status: status @ CmcStatus::Pending { .. }, message, .. }) => match status.request_id()
+      {
+        Some(request_id) => Ok(EnrollmentResponse::Pending(PendingRequest::new(request_id))),
+        None => Err(DecodeError::BadRequestId(message.unwrap_or_default()))
+      },
+      Some(CmcStatusInfo { status: CmcStatus::Failed(fail_info), message, .. }) => Ok(EnrollmentResponse::Rejected(match (message, fail_info)
+      {
+        (Some(message), _) => message,
+        (None, Some(fail_info)) => format!("{:?}", fail_info),
+        (None, None) => String::new()
+      })),
+      Some(CmcStatusInfo { status, message, .. }) => Ok(EnrollmentResponse::Rejected(format!("unsupported cmc status {:?}: {}", status, message.unwrap_or_default())))
+    }
   }
+}
+
+trait SignedDataExt<'a>: TryFrom<&'a rfc5652::SignedData>
+{
+  fn parse_der(data: &[u8]) -> Result<Self, CmsError>;
+}
 
-  fn rsa_primes(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>, X509CertificateError>
+impl<'a> SignedDataExt<'a> for SignedData
+{
+  fn parse_der(data: &[u8]) -> Result<Self, CmsError>
   {
-    unimplemented!()
+    SignedData::try_from(&Constructed::decode(data, bcder::Mode::Der, |cons| rfc5652::SignedData::decode(cons))?)
   }
-}
\ No newline at end of file
+}
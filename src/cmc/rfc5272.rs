@@ -1,10 +1,17 @@
 use std::io::Write;
 use auto_enums::auto_enum;
-use bcder::{encode::{self, PrimitiveContent}, decode::{Source, Constructed, DecodeError, IntoSource}, Tag, Mode, Integer};
+use bcder::{encode::{self, PrimitiveContent}, decode::{Source, Constructed, DecodeError, IntoSource}, Tag, Mode, Integer, OctetString, Utf8String};
 use bcder_derive::Values;
 use bytes::Bytes;
 use cryptographic_message_syntax::{Oid, asn1::rfc5652::ContentInfo};
-use x509_certificate::rfc2986::CertificationRequest;
+use x509_certificate::{rfc2986::CertificationRequest, asn1time::GeneralizedTime};
+
+pub const ID_CCT_PKI_DATA: &[u8] = &[43, 6, 1, 5, 5, 7, 12, 2];
+pub const ID_CCT_PKI_RESPONSE: &[u8] = &[43, 6, 1, 5, 5, 7, 12, 3];
+pub const ID_CMC_STATUS_INFO: &[u8] = &[43, 6, 1, 5, 5, 7, 7, 1];
+pub const ID_CMC_STATUS_INFO_V2: &[u8] = &[43, 6, 1, 5, 5, 7, 7, 25];
+pub const ID_ENROLLMENT_NAME_VALUE_PAIR: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 13, 2, 1];
+pub const ID_ALG_NO_SIGNATURE: &[u8] = &[43, 6, 1, 5, 5, 7, 6, 2];
 
 macro_rules! AnyType
 {
@@ -35,6 +42,24 @@ macro_rules! AnyType
       {
         Ok(Self(cons.capture_all()?.into_bytes()))
       }
+
+      pub fn take_opt_one<S: Source>(cons: &mut Constructed<S>) -> Result<Option<Self>, DecodeError<S::Error>>
+      {
+        let captured = cons.capture(|cons| cons.skip_one().map(|_| ()))?;
+        if captured.is_empty()
+        {
+          Ok(None)
+        }
+        else
+        {
+          Ok(Some(Self(captured.into_bytes())))
+        }
+      }
+
+      pub fn as_slice(&self) -> &[u8]
+      {
+        self.0.as_ref()
+      }
     }
     
     impl encode::Values for $name
@@ -117,6 +142,7 @@ AnyType!(AttributeValue);
 AnyType!(CertificateRequestMessage);
 AnyType!(RequestMessage);
 AnyType!(OtherMessageValue);
+AnyType!(FailInfoValue);
 
 #[derive(Clone)]
 pub struct PKIData
@@ -242,7 +268,7 @@ impl TaggedAttribute
     let attr_values = cons.take_set(|cons|
       {
         let mut attr_values = Vec::new();
-        while let Some(attr_value) = AttributeValue::opt_from_constructed(cons)?
+        while let Some(attr_value) = AttributeValue::take_opt_one(cons)?
         {
           attr_values.push(attr_value);
         }
@@ -517,46 +543,223 @@ impl PKIResponse
 
   fn take_from<S: Source>(cons: &mut Constructed<S>) -> Result<Self, DecodeError<S::Error>>
   {
-    cons.take_sequence(|cons|
+    let control_sequence = cons.take_sequence(|cons|
     {
-      let control_sequence = cons.take_sequence(|cons|
+      let mut control_sequence = Vec::new();
+      while let Some(control) = TaggedAttribute::opt_from_sequence(cons)?
       {
-        let mut control_sequence = Vec::new();
-        while let Some(control) = TaggedAttribute::opt_from_sequence(cons)?
-        {
-          control_sequence.push(control);
-        }
-        Ok(control_sequence)
-      })?;
+        control_sequence.push(control);
+      }
+      Ok(control_sequence)
+    })?;
+
+    let cms_sequence = cons.take_sequence(|cons|
+    {
+      let mut cms_sequence = Vec::new();
+      while let Some(cms) = TaggedContentInfo::opt_from_sequence(cons)?
+      {
+        cms_sequence.push(cms);
+      }
+      Ok(cms_sequence)
+    })?;
+
+    let other_msg_sequence = cons.take_sequence(|cons|
+    {
+      let mut other_msg_sequence = Vec::new();
+      while let Some(other_msg) = OtherMsg::opt_from_sequence(cons)?
+      {
+        other_msg_sequence.push(other_msg);
+      }
+      Ok(other_msg_sequence)
+    })?;
+
+    Ok(Self
+    {
+      control_sequence,
+      cms_sequence,
+      other_msg_sequence
+    })
+  }
+}
+
+DeriveValues!(PKIResponse);
+
+#[derive(Clone)]
+pub enum BodyPartReference
+{
+  BodyPartID(BodyPartID),
+  BodyPartPath(Vec<BodyPartID>)
+}
 
-      let cms_sequence = cons.take_sequence(|cons|
+impl BodyPartReference
+{
+  #[auto_enum(Values)]
+  pub fn encode_ref(&self) -> impl encode::Values + '_
+  {
+    match self
+    {
+      BodyPartReference::BodyPartID(body_part_id) => body_part_id.encode(),
+      BodyPartReference::BodyPartPath(body_part_path) => encode::sequence(encode::iter(body_part_path.iter().map(|body_part_id| body_part_id.encode())))
+    }
+  }
+
+  fn take_opt_from<S: Source>(cons: &mut Constructed<S>) -> Result<Option<Self>, DecodeError<S::Error>>
+  {
+    if let Some(body_part_id) = cons.take_opt_primitive_if(Tag::INTEGER, Integer::from_primitive)?
+    {
+      Ok(Some(Self::BodyPartID(body_part_id)))
+    }
+    else
+    {
+      cons.take_opt_sequence(|cons|
       {
-        let mut cms_sequence = Vec::new();
-        while let Some(cms) = TaggedContentInfo::opt_from_sequence(cons)?
+        let mut body_part_path = Vec::new();
+        while let Some(body_part_id) = cons.take_opt_primitive_if(Tag::INTEGER, Integer::from_primitive)?
         {
-          cms_sequence.push(cms);
+          body_part_path.push(body_part_id);
         }
-        Ok(cms_sequence)
-      })?;
+        Ok(Self::BodyPartPath(body_part_path))
+      })
+    }
+  }
+}
 
-      let other_msg_sequence = cons.take_sequence(|cons|
+impl encode::Values for BodyPartReference
+{
+  fn encoded_len(&self, mode: Mode) -> usize
+  {
+    self.encode_ref().encoded_len(mode)
+  }
+
+  fn write_encoded<W: Write>(&self, mode: Mode, target: &mut W) -> Result<(), std::io::Error>
+  {
+    self.encode_ref().write_encoded(mode, target)
+  }
+}
+
+#[derive(Clone)]
+pub struct PendInfo
+{
+  pub pend_token: OctetString,
+  pub pend_time: GeneralizedTime
+}
+
+#[derive(Clone)]
+pub enum OtherStatusInfo
+{
+  FailInfo(Integer),
+  PendInfo(PendInfo),
+  ExtendedFailInfo
+  {
+    fail_info_oid: Oid,
+    fail_info_value: FailInfoValue
+  }
+}
+
+impl OtherStatusInfo
+{
+  #[auto_enum(Values)]
+  pub fn encode_ref(&self) -> impl encode::Values + '_
+  {
+    match self
+    {
+      OtherStatusInfo::FailInfo(fail_info) => fail_info.encode(),
+      OtherStatusInfo::PendInfo(PendInfo { pend_token, pend_time }) => encode::sequence((pend_token.encode_ref(), pend_time.encode_ref())),
+      OtherStatusInfo::ExtendedFailInfo { fail_info_oid, fail_info_value } => encode::sequence((fail_info_oid.encode_ref(), fail_info_value))
+    }
+  }
+
+  // pendInfo and extendedFailInfo are both SEQUENCEs, told apart by their first member
+  fn take_opt_from<S: Source>(cons: &mut Constructed<S>) -> Result<Option<Self>, DecodeError<S::Error>>
+  {
+    if let Some(fail_info) = cons.take_opt_primitive_if(Tag::INTEGER, Integer::from_primitive)?
+    {
+      Ok(Some(Self::FailInfo(fail_info)))
+    }
+    else
+    {
+      cons.take_opt_sequence(|cons|
       {
-        let mut other_msg_sequence = Vec::new();
-        while let Some(other_msg) = OtherMsg::opt_from_sequence(cons)?
+        if let Some(pend_token) = OctetString::take_opt_from(cons)?
         {
-          other_msg_sequence.push(other_msg);
+          let pend_time = GeneralizedTime::take_from_allow_fractional_z(cons)?;
+          Ok(Self::PendInfo(PendInfo { pend_token, pend_time }))
         }
-        Ok(other_msg_sequence)
-      })?;
+        else
+        {
+          let fail_info_oid = Oid::take_from(cons)?;
+          let fail_info_value = FailInfoValue::take_from(cons)?;
+          Ok(Self::ExtendedFailInfo { fail_info_oid, fail_info_value })
+        }
+      })
+    }
+  }
+}
 
-      Ok(Self
+impl encode::Values for OtherStatusInfo
+{
+  fn encoded_len(&self, mode: Mode) -> usize
+  {
+    self.encode_ref().encoded_len(mode)
+  }
+
+  fn write_encoded<W: Write>(&self, mode: Mode, target: &mut W) -> Result<(), std::io::Error>
+  {
+    self.encode_ref().write_encoded(mode, target)
+  }
+}
+
+// RFC 5272 6.1.1, which is also a superset of the original CMCStatusInfo
+#[derive(Clone)]
+pub struct CMCStatusInfoV2
+{
+  pub cmc_status: Integer,
+  pub body_list: Vec<BodyPartReference>,
+  pub status_string: Option<Utf8String>,
+  pub other_info: Option<OtherStatusInfo>
+}
+
+impl CMCStatusInfoV2
+{
+  fn default_tag(&self) -> Tag
+  {
+    Tag::SEQUENCE
+  }
+
+  pub fn encode_ref_as(&self, tag: Tag) -> impl encode::Values + '_
+  {
+    encode::sequence_as(tag,
+    (
+      self.cmc_status.encode(),
+      encode::sequence(&self.body_list),
+      self.status_string.as_ref().map(|status_string| status_string.encode_ref()),
+      self.other_info.as_ref()
+    ))
+  }
+
+  fn take_from<S: Source>(cons: &mut Constructed<S>) -> Result<Self, DecodeError<S::Error>>
+  {
+    let cmc_status = Integer::take_from(cons)?;
+    let body_list = cons.take_sequence(|cons|
+    {
+      let mut body_list = Vec::new();
+      while let Some(body_part) = BodyPartReference::take_opt_from(cons)?
       {
-        control_sequence,
-        cms_sequence,
-        other_msg_sequence
-      })
+        body_list.push(body_part);
+      }
+      Ok(body_list)
+    })?;
+    let status_string = cons.take_opt_value_if(Tag::UTF8_STRING, |content| Utf8String::from_content(content))?;
+    let other_info = OtherStatusInfo::take_opt_from(cons)?;
+
+    Ok(Self
+    {
+      cmc_status,
+      body_list,
+      status_string,
+      other_info
     })
   }
 }
 
-DeriveValues!(PKIResponse);
\ No newline at end of file
+DeriveValues!(CMCStatusInfoV2);
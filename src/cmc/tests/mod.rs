@@ -1,9 +1,10 @@
 use base64::{engine::general_purpose, Engine};
-use x509_certificate::{X509CertificateBuilder, InMemorySigningKeyPair, KeyAlgorithm};
+use cryptographic_message_syntax::{SignedData, asn1::rfc5652};
+use x509_certificate::{X509CertificateBuilder, InMemorySigningKeyPair, KeyAlgorithm, DigestAlgorithm};
 
-use crate::cmc::CmcRequestBuilder;
+use crate::{cmc::CmcRequestBuilder, EnrollmentResponse, EncodeError, DecodeError};
 
-use super::CmcRequest;
+use super::{CmcRequest, CmcResponse, CmcStatus, CmcStatusInfo, rfc5272::{PKIResponse, ID_ENROLLMENT_NAME_VALUE_PAIR, ID_ALG_NO_SIGNATURE}, archival::{KeyArchival, SymmetricAlgorithm, ArchivalError, ID_ARCHIVED_KEY_ATTR, ID_ENCRYPTED_KEY_HASH, private_key_blob}};
 
 #[test]
 fn request_encode()
@@ -11,17 +12,246 @@ fn request_encode()
   let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
   builder.subject().append_common_name_utf8_string("tempuri.org").expect("error setting subject");
   builder.issuer().append_common_name_utf8_string("tempuri.org").expect("error setting issuer");
-  let csr = builder.create_certificate_signing_request(&InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate new key pair").0).expect("failed to generate csr");
+  let key_pair = InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate new key pair").0;
+  let csr = builder.create_certificate_signing_request(&key_pair).expect("failed to generate csr");
 
   let request = CmcRequestBuilder::default()
     .add_certificate(csr, vec![])
+    .request_key_signer(&key_pair)
     .build();
   println!("{}", general_purpose::STANDARD.encode::<Vec<u8>>(request.try_into().expect("failed to build cms message")));
 }
 
+#[test]
+fn request_encode_unsigned()
+{
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("tempuri.org").expect("error setting subject");
+  let csr = builder.create_certificate_signing_request(&InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate new key pair").0).expect("failed to generate csr");
+
+  let request: Vec<u8> = CmcRequestBuilder::default()
+    .add_certificate(csr, vec![])
+    .unsigned()
+    .build()
+    .try_into()
+    .expect("failed to build cms message");
+
+  let signed_data = rfc5652::SignedData::decode_ber(&request).expect("error parsing unsigned cmc");
+  assert_eq!(signed_data.signer_infos.len(), 1);
+  let signer_info = &signed_data.signer_infos[0];
+  assert_eq!(signer_info.signature_algorithm.algorithm.as_ref(), ID_ALG_NO_SIGNATURE);
+  let content = signed_data.content_info.content.as_ref().expect("unsigned cmc has no content").to_bytes();
+  let mut digester = DigestAlgorithm::Sha1.digester();
+  digester.update(&content);
+  assert_eq!(signer_info.signature.to_bytes().as_ref(), digester.finish().as_ref());
+}
+
+#[test]
+fn request_encode_requires_signer()
+{
+  let request: Result<Vec<u8>, EncodeError> = CmcRequestBuilder::default().build().try_into();
+  assert!(matches!(request, Err(EncodeError::NoSigner)));
+}
+
+#[test]
+fn request_encode_request_key()
+{
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("tempuri.org").expect("error setting subject");
+  let key_pair = InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate new key pair").0;
+  let csr = builder.create_certificate_signing_request(&key_pair).expect("failed to generate csr");
+
+  let request: Vec<u8> = CmcRequestBuilder::default()
+    .add_certificate(csr, vec![])
+    .request_key_signer(&key_pair)
+    .build()
+    .try_into()
+    .expect("failed to build cms message");
+
+  let signed_data = SignedData::parse_ber(&request).expect("error parsing signed cmc");
+  assert_eq!(signed_data.signers().count(), 1);
+  assert_eq!(signed_data.certificates().count(), 0);
+}
+
+#[test]
+fn request_encode_signed()
+{
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("tempuri.org").expect("error setting subject");
+  builder.issuer().append_common_name_utf8_string("tempuri.org").expect("error setting issuer");
+  let (certificate, key_pair, _) = builder.create_with_random_keypair().expect("failed to generate certificate");
+
+  let request: Vec<u8> = CmcRequestBuilder::default()
+    .signer(&key_pair, certificate.clone())
+    .build()
+    .try_into()
+    .expect("failed to build cms message");
+
+  let signed_data = SignedData::parse_ber(&request).expect("error parsing signed cmc");
+  assert!(signed_data.certificates().any(|candidate| candidate == &certificate));
+  for signer in signed_data.signers()
+  {
+    signer.verify_signature_with_signed_data(&signed_data).expect("signature on renewal request did not verify");
+  }
+}
+
+#[test]
+fn request_encode_on_behalf_of()
+{
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("agent").expect("error setting subject");
+  builder.issuer().append_common_name_utf8_string("agent").expect("error setting issuer");
+  let (certificate, key_pair, _) = builder.create_with_random_keypair().expect("failed to generate certificate");
+
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("user").expect("error setting subject");
+  let csr = builder.create_certificate_signing_request(&InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate new key pair").0).expect("failed to generate csr");
+
+  let request: Vec<u8> = CmcRequestBuilder::default()
+    .add_certificate(csr, vec![])
+    .on_behalf_of("CONTOSO\\user", &key_pair, certificate.clone())
+    .build()
+    .try_into()
+    .expect("failed to build cms message");
+
+  let signed_data = SignedData::parse_ber(&request).expect("error parsing signed cmc");
+  assert!(signed_data.certificates().any(|candidate| candidate == &certificate));
+  let request = CmcRequest::try_from(request).expect("error decoding agent cmc");
+  assert!(request.certificate_requests[0].attributes.iter().any(|(oid, _)| oid.as_ref() == ID_ENROLLMENT_NAME_VALUE_PAIR));
+}
+
+#[test]
+fn request_encode_archival()
+{
+  let private_key = general_purpose::STANDARD.decode(include_str!("../../csr/tests/rsa_key.txt")).expect("error base64 decoding known good key");
+  let key_pair = InMemorySigningKeyPair::from_pkcs8_der(&private_key).expect("error decoding known good key");
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Rsa);
+  builder.subject().append_common_name_utf8_string("exchange").expect("error setting subject");
+  builder.issuer().append_common_name_utf8_string("exchange").expect("error setting issuer");
+  let exchange_certificate = builder.create_with_key_pair(&key_pair).expect("failed to generate certificate");
+  let csr = builder.create_certificate_signing_request(&key_pair).expect("failed to generate csr");
+
+  let archival = KeyArchival::new(exchange_certificate.into(), SymmetricAlgorithm::Aes256, &private_key).expect("failed to prepare key for archival");
+  let request: Vec<u8> = CmcRequestBuilder::default()
+    .add_certificate(csr, vec![])
+    .archive_key(&key_pair, archival)
+    .build()
+    .try_into()
+    .expect("failed to build cms message");
+
+  let signed_data = rfc5652::SignedData::decode_ber(&request).expect("error parsing signed cmc");
+  let unsigned_attributes = signed_data.signer_infos[0].unsigned_attributes.as_ref().expect("archived key missing from signer info");
+  assert!(unsigned_attributes.iter().any(|attribute| attribute.typ.as_ref() == ID_ARCHIVED_KEY_ATTR));
+  let request = CmcRequest::try_from(request).expect("error decoding archival cmc");
+  assert!(request.certificate_requests[0].attributes.iter().any(|(oid, _)| oid.as_ref() == ID_ENCRYPTED_KEY_HASH));
+}
+
+#[test]
+fn archival_private_key_blob()
+{
+  let private_key = general_purpose::STANDARD.decode(include_str!("../../csr/tests/rsa_key.txt")).expect("error base64 decoding known good key");
+  let blob = private_key_blob(&private_key).expect("failed to convert known good key");
+  // BLOBHEADER and RSAPUBKEY, then the modulus and private exponent at 256 bytes and five crt values at 128
+  assert_eq!(blob.len(), 8 + 12 + 256 * 2 + 128 * 5);
+  assert_eq!(&blob[12..16], &2048u32.to_le_bytes());
+}
+
+#[test]
+fn archival_rejects_non_rsa_keys()
+{
+  let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+  builder.subject().append_common_name_utf8_string("exchange").expect("error setting subject");
+  let (exchange_certificate, _, private_key) = builder.create_with_random_keypair().expect("failed to generate certificate");
+
+  let archival = KeyArchival::new(exchange_certificate.into(), SymmetricAlgorithm::default(), private_key.as_ref());
+  assert!(matches!(archival, Err(ArchivalError::UnsupportedKeyAlgorithm)));
+}
+
 #[test]
 fn request_decode()
 {
   let request = include_str!("valid.txt");
   CmcRequest::try_from(general_purpose::STANDARD.decode(request).expect("error base64 decoding known good cmc")).expect("error decoding known good cmc");
-}
\ No newline at end of file
+}
+
+#[test]
+fn response_decode_issued()
+{
+  let response = include_str!("response_issued.txt");
+  let response = CmcResponse::try_from(general_purpose::STANDARD.decode(response).expect("error base64 decoding known good cmc")).expect("error decoding known good cmc");
+  assert_eq!(response.get_statuses().next().map(|status| &status.status), Some(&CmcStatus::Success));
+  match EnrollmentResponse::try_from(response).expect("error interpreting known good cmc")
+  {
+    EnrollmentResponse::Issued { entity, chain } =>
+    {
+      assert_eq!(entity.subject_common_name(), Some("host.contoso.com".to_owned()));
+      assert_eq!(chain.len(), 1);
+    },
+    _ => panic!("known good cmc was not issued")
+  }
+}
+
+#[test]
+fn response_decode_pending()
+{
+  let response = include_str!("response_pending.txt");
+  let response = CmcResponse::try_from(general_purpose::STANDARD.decode(response).expect("error base64 decoding known good cmc")).expect("error decoding known good cmc");
+  match EnrollmentResponse::try_from(response).expect("error interpreting known good cmc")
+  {
+    EnrollmentResponse::Pending(pending) => assert_eq!(pending.request_id(), 43),
+    _ => panic!("known good cmc was not pending")
+  }
+}
+
+#[test]
+fn response_decode_failed()
+{
+  let response = include_str!("response_failed.txt");
+  let response = CmcResponse::try_from(general_purpose::STANDARD.decode(response).expect("error base64 decoding known good cmc")).expect("error decoding known good cmc");
+  match EnrollmentResponse::try_from(response).expect("error interpreting known good cmc")
+  {
+    EnrollmentResponse::Rejected(message) => assert_eq!(message, "Denied by Policy Module"),
+    _ => panic!("known good cmc was not rejected")
+  }
+}
+
+#[test]
+fn response_round_trip()
+{
+  let status = CmcStatusInfo { body_parts: vec![1], status: CmcStatus::Pending { token: 43u32.to_le_bytes().to_vec(), time: None }, message: Some("Taken Under Submission".to_owned()) };
+  let encoded: Vec<u8> = CmcResponse::new(vec![], vec![status]).try_into().expect("failed to build cms message");
+  let decoded = CmcResponse::try_from(encoded).expect("failed to reparse cms message");
+  let status = decoded.get_statuses().next().expect("status lost in round trip");
+  assert_eq!(status.status.request_id(), Some(43));
+  assert_eq!(status.message.as_deref(), Some("Taken Under Submission"));
+}
+
+#[test]
+fn response_status_body_parts()
+{
+  let pending = |body_parts: Vec<u32>| CmcStatusInfo { body_parts, status: CmcStatus::Pending { token: 43u32.to_le_bytes().to_vec(), time: None }, message: None };
+  let failed = |body_parts: Vec<u32>| CmcStatusInfo { body_parts, status: CmcStatus::Failed(None), message: Some("Denied by Policy Module".to_owned()) };
+
+  let encoded: Vec<u8> = CmcResponse::new(vec![], vec![failed(vec![2]), pending(vec![1])]).try_into().expect("failed to build cms message");
+  let signed_data = SignedData::parse_ber(&encoded).expect("failed to reparse cms message");
+  let controls = PKIResponse::decode_der(signed_data.signed_content().expect("response has no content")).expect("failed to decode pki response").control_sequence;
+  assert_eq!(controls.len(), 2);
+  assert_ne!(controls[0].body_part_id, controls[1].body_part_id);
+  match EnrollmentResponse::try_from(CmcResponse::try_from(encoded).expect("failed to reparse cms message")).expect("error interpreting cmc")
+  {
+    EnrollmentResponse::Pending(pending) => assert_eq!(pending.request_id(), 43),
+    _ => panic!("status for another body part was used")
+  }
+
+  match EnrollmentResponse::try_from(CmcResponse::new(vec![], vec![failed(vec![2]), failed(vec![])])).expect("error interpreting cmc")
+  {
+    EnrollmentResponse::Rejected(message) => assert_eq!(message, "Denied by Policy Module"),
+    _ => panic!("status with an empty body list was not used")
+  }
+
+  match EnrollmentResponse::try_from(CmcResponse::new(vec![], vec![pending(vec![2])]))
+  {
+    Err(DecodeError::NoStatus(1)) => (),
+    _ => panic!("status for another body part was used")
+  }
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::sync::Mutex;
+
+use tracing::{event, Level};
+
+use crate::ldap::LdapError;
+
+// whose certificate is being requested.  the user context authenticates with whatever the
+// kerberos credential cache holds, while the machine context authenticates as the host itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnrollmentContext
+{
+  #[default]
+  User,
+  // kerberos credentials are taken from the environment, so the process must be started with
+  // KRB5_CLIENT_KTNAME naming the host keytab, and should have KRB5CCNAME pointing at a cache of
+  // its own.  binding fails with LdapError::NoMachineKeytab otherwise
+  Machine
+}
+
+impl EnrollmentContext
+{
+  // the host principal, as stored in the system keytab.  computer account names are the netbios
+  // name, which is at most 15 characters, followed by a $
+  pub(crate) fn principal(&self, realm: &str) -> Option<String>
+  {
+    match self
+    {
+      EnrollmentContext::User => None,
+      EnrollmentContext::Machine =>
+      {
+        let host_name = gethostname::gethostname().to_string_lossy().to_uppercase();
+        let netbios_name: String = host_name.split('.').next().unwrap_or_default().chars().take(15).collect();
+        Some(format!("{}$@{}", netbios_name, realm.trim_end_matches('.').to_uppercase()))
+      }
+    }
+  }
+
+  // gssapi offers no way to hand a keytab to a single security context, and neither the ldap bind
+  // nor the rpc transport accept credentials, so the keytab and credential cache are whatever the
+  // process was started with.  the machine context expects KRB5_CLIENT_KTNAME to name the host
+  // keytab and KRB5CCNAME a cache of its own.  as both contexts would share those credentials,
+  // a process is limited to whichever context it uses first
+  pub(crate) fn acquire_credentials(&self) -> Result<(), LdapError>
+  {
+    static PROCESS_CONTEXT: Mutex<Option<EnrollmentContext>> = Mutex::new(None);
+    let mut process_context = PROCESS_CONTEXT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match *process_context
+    {
+      Some(context) if context != *self => Err(LdapError::MixedContexts(context, *self)),
+      Some(_) => Ok(()),
+      None =>
+      {
+        if *self == EnrollmentContext::Machine
+        {
+          if env::var_os("KRB5_CLIENT_KTNAME").is_none()
+          {
+            return Err(LdapError::NoMachineKeytab);
+          }
+          if env::var_os("KRB5CCNAME").is_none()
+          {
+            event!(Level::WARN, "KRB5CCNAME is not set.  machine credentials will be stored in the default credential cache");
+          }
+        }
+        *process_context = Some(*self);
+        Ok(())
+      }
+    }
+  }
+}
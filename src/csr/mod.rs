@@ -0,0 +1,263 @@
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+use bcder::{encode::{self, Values, PrimitiveContent}, decode::Constructed, string::CharSetError, Mode, Oid, Tag, BitString, OctetString, Unsigned};
+use bytes::Bytes;
+use rand::thread_rng;
+use rsa::{pkcs8::EncodePrivateKey, RsaPrivateKey};
+use thiserror::Error;
+use x509_certificate::{rfc2986::{CertificationRequest, CertificationRequestInfo, Version}, rfc3280::Name, rfc5280::{Extension, SubjectPublicKeyInfo, AlgorithmIdentifier}, rfc5652::{Attribute, AttributeValue}, rfc5958::Attributes, KeyAlgorithm, KeyInfoSigner, EcdsaCurve, InMemorySigningKeyPair, X509CertificateError, Sign, Signer};
+
+use crate::{CertificateTemplate, template::SubjectNameFlags};
+
+pub const ID_EXTENSION_REQUEST: &[u8] = &[42, 134, 72, 134, 247, 13, 1, 9, 14];
+pub const ID_SUBJECT_ALT_NAME: &[u8] = &[85, 29, 17];
+pub const ID_NT_PRINCIPAL_NAME: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 20, 2, 3];
+pub const ID_ENROLL_CERTTYPE: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 20, 2];
+pub const ID_CERTIFICATE_TEMPLATE: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 21, 7];
+pub const ID_RSA_ENCRYPTION: &[u8] = &[42, 134, 72, 134, 247, 13, 1, 1, 1];
+pub const ID_EC_PUBLIC_KEY: &[u8] = &[42, 134, 72, 206, 61, 2, 1];
+pub const ID_ED25519: &[u8] = &[43, 101, 112];
+const DEFAULT_RSA_KEY_LENGTH: usize = 2048;
+
+#[derive(Error, Debug)]
+pub enum CsrError
+{
+  #[error("template requires a {required} bit key, but the key is {actual} bits")]
+  KeyTooSmall { required: u32, actual: u32 },
+
+  #[error("template requires key algorithm {0}")]
+  WrongKeyAlgorithm(String),
+
+  #[error("cannot generate a key satisfying the template; supply an existing key pair instead")]
+  CannotGenerateKey,
+
+  #[error("{0} keys are not supported")]
+  UnsupportedKeyAlgorithm(String),
+
+  #[error("key generation error: {0}")]
+  KeyGeneration(String),
+
+  #[error("template requires {0} in the request, which is unknown for this identity")]
+  MissingIdentity(&'static str),
+
+  #[error("certificate error: {0}")]
+  Certificate(#[from] X509CertificateError),
+
+  #[error("signing error: {0}")]
+  Signing(#[from] signature::Error),
+
+  #[error("subject contains invalid characters: {0}")]
+  CharSet(#[from] CharSetError),
+
+  #[error("encoding error: {0}")]
+  Encoding(#[from] std::io::Error)
+}
+
+// the directory attributes of the requesting principal which can appear in a request
+#[derive(Debug, Clone, Default)]
+pub struct Identity
+{
+  pub common_name: String,
+  pub dns_name: Option<String>,
+  pub user_principal_name: Option<String>,
+  pub email: Option<String>
+}
+
+pub struct CertificationRequestBuilder<'a>
+{
+  template: &'a CertificateTemplate,
+  identity: Identity
+}
+
+impl<'a> CertificationRequestBuilder<'a>
+{
+  pub fn new(template: &'a CertificateTemplate, identity: Identity) -> Self
+  {
+    Self { template, identity }
+  }
+
+  // templates without an algorithm, which includes every template read from the directory, get rsa.
+  // x509-certificate cannot generate rsa keys, so those come from the rsa crate as pkcs#8
+  pub fn generate_key_pair(&self) -> Result<(InMemorySigningKeyPair, Vec<u8>), CsrError>
+  {
+    let requirements = self.template.get_key_requirements();
+    let document = match (requirements.algorithm.as_ref(), requirements.minimal_key_length)
+    {
+      (None, length) => generate_rsa_key(length)?,
+      (Some(algorithm), length) if algorithm.as_ref() == ID_RSA_ENCRYPTION => generate_rsa_key(length)?,
+      (Some(algorithm), length) if algorithm.as_ref() == ID_EC_PUBLIC_KEY && length <= 256 => InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))?.1.as_ref().to_vec(),
+      (Some(algorithm), length) if algorithm.as_ref() == ID_EC_PUBLIC_KEY && length <= 384 => InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1))?.1.as_ref().to_vec(),
+      _ => return Err(CsrError::CannotGenerateKey)
+    };
+    Ok((InMemorySigningKeyPair::from_pkcs8_der(&document)?, document))
+  }
+
+  pub fn build(&self, signer: &dyn KeyInfoSigner) -> Result<CertificationRequest, CsrError>
+  {
+    let key_algorithm = signer.key_algorithm().ok_or_else(|| CsrError::WrongKeyAlgorithm(String::from("unknown")))?;
+    self.check_key(key_algorithm, &signer.public_key_data())?;
+
+    let mut subject = Name::default();
+    subject.append_common_name_utf8_string(&self.subject_common_name()?)?;
+
+    let mut extensions = vec![self.template_extension()];
+    if let Some(subject_alt_name) = self.subject_alt_name()?
+    {
+      extensions.push(subject_alt_name);
+    }
+    let mut attributes = Attributes::default();
+    attributes.push(Attribute
+    {
+      typ: Oid(Bytes::from_static(ID_EXTENSION_REQUEST)),
+      values: vec![AttributeValue::new(encode::sequence(encode::iter(extensions.iter().map(|extension| extension.encode_ref()))).to_captured(Mode::Der))]
+    });
+
+    let info = CertificationRequestInfo
+    {
+      version: Version::V1,
+      subject,
+      subject_public_key_info: SubjectPublicKeyInfo
+      {
+        algorithm: AlgorithmIdentifier::from(key_algorithm),
+        subject_public_key: BitString::new(0, signer.public_key_data())
+      },
+      attributes
+    };
+
+    // RFC 2986 4.2
+    let mut info_der = vec![];
+    info.write_encoded(Mode::Der, &mut info_der)?;
+    let signature = signer.try_sign(&info_der)?;
+
+    Ok(CertificationRequest
+    {
+      certificate_request_info: info,
+      signature_algorithm: signer.signature_algorithm()?.into(),
+      signature: BitString::new(0, signature.into())
+    })
+  }
+
+  fn check_key(&self, key_algorithm: KeyAlgorithm, public_key_data: &[u8]) -> Result<(), CsrError>
+  {
+    let requirements = self.template.get_key_requirements();
+    let (algorithm, size): (&[u8], u32) = match key_algorithm
+    {
+      KeyAlgorithm::Rsa => (ID_RSA_ENCRYPTION, rsa_modulus_bits(public_key_data)?),
+      KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1) => (ID_EC_PUBLIC_KEY, 256),
+      KeyAlgorithm::Ecdsa(EcdsaCurve::Secp384r1) => (ID_EC_PUBLIC_KEY, 384),
+      // active directory certificate services cannot issue for ed25519 keys
+      KeyAlgorithm::Ed25519 => return Err(CsrError::UnsupportedKeyAlgorithm(String::from("ed25519")))
+    };
+    match requirements.algorithm.as_ref()
+    {
+      Some(required) if required.as_ref() != algorithm => Err(CsrError::WrongKeyAlgorithm(required.to_string())),
+      _ if size < requirements.minimal_key_length => Err(CsrError::KeyTooSmall { required: requirements.minimal_key_length, actual: size }),
+      _ => Ok(())
+    }
+  }
+
+  // MS-WCCE 3.2.2.6.2.1.4.5.9 - the CA builds the subject from the directory unless the enrollee supplies it,
+  // so this only needs to be plausible
+  fn subject_common_name(&self) -> Result<String, CsrError>
+  {
+    if self.template.get_subject_name_flags().contains(SubjectNameFlags::SUBJECT_REQUIRE_DNS_AS_CN)
+    {
+      self.identity.dns_name.clone().ok_or(CsrError::MissingIdentity("a dns name"))
+    }
+    else
+    {
+      Ok(self.identity.common_name.clone())
+    }
+  }
+
+  fn subject_alt_name(&self) -> Result<Option<Extension>, CsrError>
+  {
+    let dns_name = self.required(SubjectNameFlags::SUBJECT_ALT_REQUIRE_DNS, &self.identity.dns_name, "a dns name")?;
+    let user_principal_name = self.required(SubjectNameFlags::SUBJECT_ALT_REQUIRE_UPN, &self.identity.user_principal_name, "a user principal name")?;
+    let email = self.required(SubjectNameFlags::SUBJECT_ALT_REQUIRE_EMAIL, &self.identity.email, "an email address")?;
+    if dns_name.is_none() && user_principal_name.is_none() && email.is_none()
+    {
+      return Ok(None)
+    }
+
+    // RFC 5280 4.2.1.6
+    let general_names = encode::sequence((
+      user_principal_name.map(|user_principal_name| encode::sequence_as(Tag::CTX_0, (
+        Oid(Bytes::from_static(ID_NT_PRINCIPAL_NAME)).encode(),
+        encode::sequence_as(Tag::CTX_0, user_principal_name.as_bytes().encode_as(Tag::UTF8_STRING))))),
+      email.map(|email| email.as_bytes().encode_as(Tag::ctx(1))),
+      dns_name.map(|dns_name| dns_name.as_bytes().encode_as(Tag::ctx(2)))
+    )).to_captured(Mode::Der);
+    Ok(Some(Extension
+    {
+      id: Oid(Bytes::from_static(ID_SUBJECT_ALT_NAME)),
+      critical: Some(false),
+      value: OctetString::new(general_names.into_bytes())
+    }))
+  }
+
+  fn required<'b>(&self, flag: SubjectNameFlags, value: &'b Option<String>, name: &'static str) -> Result<Option<&'b str>, CsrError>
+  {
+    match value
+    {
+      Some(value) if self.template.get_subject_name_flags().contains(flag) => Ok(Some(value.as_str())),
+      None if self.template.get_subject_name_flags().contains(flag) => Err(CsrError::MissingIdentity(name)),
+      _ => Ok(None)
+    }
+  }
+
+  // MS-WCCE 2.2.2.7.7 - version 1 templates are identified by name, later versions by oid
+  fn template_extension(&self) -> Extension
+  {
+    match self.template.get_template_oid()
+    {
+      Some(template_oid) if self.template.get_schema_version() > 1 => Extension
+      {
+        id: Oid(Bytes::from_static(ID_CERTIFICATE_TEMPLATE)),
+        critical: Some(false),
+        value: OctetString::new(encode::sequence((
+          template_oid.encode(),
+          self.template.get_revision().encode(),
+          self.template.get_minor_revision().encode()
+        )).to_captured(Mode::Der).into_bytes())
+      },
+      _ =>
+      {
+        let name: Vec<u8> = self.template.get_name().encode_utf16().flat_map(|c| c.to_be_bytes()).collect();
+        Extension
+        {
+          id: Oid(Bytes::from_static(ID_ENROLL_CERTTYPE)),
+          critical: Some(false),
+          value: OctetString::new(name.as_slice().encode_as(Tag::BMP_STRING).to_captured(Mode::Der).into_bytes())
+        }
+      }
+    }
+  }
+}
+
+fn rsa_modulus_bits(public_key_data: &[u8]) -> Result<u32, CsrError>
+{
+  let modulus = Constructed::decode(public_key_data, Mode::Der, |cons| cons.take_sequence(|cons|
+  {
+    let modulus = Unsigned::take_from(cons)?;
+    cons.skip_all()?;
+    Ok(modulus)
+  })).map_err(|err| CsrError::WrongKeyAlgorithm(format!("invalid rsa public key: {}", err)))?;
+  let bytes = modulus.as_slice();
+  let leading_zeroes = bytes.iter().take_while(|b| **b == 0).count();
+  Ok(bytes[leading_zeroes..]
+    .first()
+    .map(|first| (bytes.len() - leading_zeroes - 1) as u32 * 8 + (8 - first.leading_zeros()))
+    .unwrap_or_default())
+}
+
+// templates which leave the minimum key length unset, or set it low, still get a key of a reasonable size
+fn generate_rsa_key(minimal_key_length: u32) -> Result<Vec<u8>, CsrError>
+{
+  let bits = (minimal_key_length as usize).max(DEFAULT_RSA_KEY_LENGTH);
+  let private_key = RsaPrivateKey::new(&mut thread_rng(), bits).map_err(|err| CsrError::KeyGeneration(err.to_string()))?;
+  let document = private_key.to_pkcs8_der().map_err(|err| CsrError::KeyGeneration(err.to_string()))?;
+  Ok(document.as_bytes().to_vec())
+}
@@ -0,0 +1,124 @@
+use std::str::FromStr;
+
+use base64::{engine::general_purpose, Engine};
+use bcder::{Oid, decode::Constructed, Mode};
+use bytes::Bytes;
+use x509_certificate::{rfc2986::CertificationRequest, rfc3280::Name, InMemorySigningKeyPair, KeyAlgorithm, EcdsaCurve, Sign};
+
+use crate::{client::CertificateTemplateBuilder, template::{KeyRequirements, SubjectNameFlags}, CertificateTemplate};
+
+use super::{CertificationRequestBuilder, CsrError, Identity, rsa_modulus_bits, ID_EC_PUBLIC_KEY, ID_EXTENSION_REQUEST, ID_RSA_ENCRYPTION};
+
+fn template(algorithm: &'static [u8], minimal_key_length: u32, subject_name_flags: SubjectNameFlags) -> CertificateTemplate
+{
+  CertificateTemplateBuilder::default()
+    .cn("Machine")
+    .enroll(true)
+    .template_oid(Oid::<Bytes>::from_str("1.3.6.1.4.1.311.21.8.1.2").expect("bad template oid"))
+    .schema_version(2u32)
+    .revision(100u32)
+    .subject_name_flags(subject_name_flags)
+    .key_requirements(KeyRequirements
+    {
+      minimal_key_length,
+      algorithm: Some(Oid(Bytes::from_static(algorithm))),
+      ..Default::default()
+    })
+    .build()
+    .expect("failed to build template")
+}
+
+fn identity() -> Identity
+{
+  Identity
+  {
+    common_name: "HOST".to_owned(),
+    dns_name: Some("host.contoso.com".to_owned()),
+    user_principal_name: None,
+    email: None
+  }
+}
+
+#[test]
+fn request_from_template()
+{
+  let template = template(ID_EC_PUBLIC_KEY, 256, SubjectNameFlags::SUBJECT_REQUIRE_DNS_AS_CN | SubjectNameFlags::SUBJECT_ALT_REQUIRE_DNS);
+  let builder = CertificationRequestBuilder::new(&template, identity());
+  let (key_pair, _) = builder.generate_key_pair().expect("failed to generate key pair");
+  let request = builder.build(&key_pair).expect("failed to build request");
+
+  let request = Constructed::decode(request.encode_der().expect("failed to encode request").as_slice(), Mode::Der, |der| CertificationRequest::take_from(der)).expect("failed to decode request");
+  let mut subject = Name::default();
+  subject.append_common_name_utf8_string("host.contoso.com").expect("error setting subject");
+  assert_eq!(request.certificate_request_info.subject, subject);
+  assert!(request.certificate_request_info.attributes.iter().any(|attribute| attribute.typ.as_ref() == ID_EXTENSION_REQUEST));
+}
+
+#[test]
+fn request_rejects_small_key()
+{
+  let template = template(ID_EC_PUBLIC_KEY, 384, SubjectNameFlags::empty());
+  let (key_pair, _) = InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1)).expect("failed to generate key pair");
+  match CertificationRequestBuilder::new(&template, identity()).build(&key_pair)
+  {
+    Err(CsrError::KeyTooSmall { required: 384, actual: 256 }) => (),
+    _ => panic!("undersized key was accepted")
+  }
+}
+
+#[test]
+fn request_checks_rsa_modulus()
+{
+  let key_pair = InMemorySigningKeyPair::from_pkcs8_der(general_purpose::STANDARD.decode(include_str!("rsa_key.txt")).expect("error base64 decoding known good key")).expect("error decoding known good key");
+  CertificationRequestBuilder::new(&template(ID_RSA_ENCRYPTION, 2048, SubjectNameFlags::empty()), identity()).build(&key_pair).expect("2048 bit key was rejected");
+  match CertificationRequestBuilder::new(&template(ID_RSA_ENCRYPTION, 4096, SubjectNameFlags::empty()), identity()).build(&key_pair)
+  {
+    Err(CsrError::KeyTooSmall { required: 4096, actual: 2048 }) => (),
+    _ => panic!("undersized key was accepted")
+  }
+}
+
+#[test]
+fn request_requires_identity()
+{
+  let template = template(ID_EC_PUBLIC_KEY, 256, SubjectNameFlags::SUBJECT_ALT_REQUIRE_UPN);
+  let builder = CertificationRequestBuilder::new(&template, identity());
+  let (key_pair, _) = builder.generate_key_pair().expect("failed to generate key pair");
+  match builder.build(&key_pair)
+  {
+    Err(CsrError::MissingIdentity(_)) => (),
+    _ => panic!("request built without a required upn")
+  }
+}
+
+#[test]
+fn request_generates_rsa_by_default()
+{
+  let template = CertificateTemplateBuilder::default()
+    .cn("User")
+    .enroll(true)
+    .key_requirements(KeyRequirements { minimal_key_length: 1024, ..Default::default() })
+    .build()
+    .expect("failed to build template");
+  let builder = CertificationRequestBuilder::new(&template, identity());
+  let (key_pair, _) = builder.generate_key_pair().expect("failed to generate key pair");
+  assert_eq!(key_pair.key_algorithm(), Some(KeyAlgorithm::Rsa));
+  assert_eq!(rsa_modulus_bits(&key_pair.public_key_data()).expect("generated key has no modulus"), 2048);
+  builder.build(&key_pair).expect("generated key was rejected");
+}
+
+#[test]
+fn request_rejects_ed25519()
+{
+  let template = CertificateTemplateBuilder::default()
+    .cn("User")
+    .enroll(true)
+    .build()
+    .expect("failed to build template");
+  let (key_pair, _) = InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ed25519).expect("failed to generate key pair");
+  match CertificationRequestBuilder::new(&template, identity()).build(&key_pair)
+  {
+    Err(CsrError::UnsupportedKeyAlgorithm(_)) => (),
+    _ => panic!("ed25519 key was accepted")
+  }
+}
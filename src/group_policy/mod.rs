@@ -0,0 +1,335 @@
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use num_traits::FromPrimitive;
+use thiserror::Error;
+use tracing::{event, Level};
+use url::Url;
+
+use crate::{ClientAuthentication, EnrollmentContext, PolicyEndpoint};
+
+const SIGNATURE: &[u8] = b"PReg";
+const VERSION: u32 = 1;
+const REG_SZ: u32 = 1;
+const REG_EXPAND_SZ: u32 = 2;
+const REG_DWORD: u32 = 4;
+const POLICY_SERVERS: &str = "software\\policies\\microsoft\\cryptography\\policyservers\\";
+// options of a link in gPLink
+const LINK_DISABLED: u32 = 1;
+const LINK_ENFORCED: u32 = 2;
+// gPOptions of a container
+const BLOCK_INHERITANCE: u32 = 1;
+// flags of a group policy object
+const USER_DISABLED: u32 = 1;
+const MACHINE_DISABLED: u32 = 2;
+
+#[derive(Error, Debug)]
+pub enum GroupPolicyError
+{
+  #[error("not a registry policy file")]
+  BadSignature,
+
+  #[error("unsupported registry policy version {0}")]
+  UnsupportedVersion(u32),
+
+  #[error("malformed registry policy entry at offset {0}")]
+  MalformedEntry(usize),
+
+  #[error("could not read {0}: {1}")]
+  UnreadableFile(String, String)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RegistryValue
+{
+  String(String),
+  Dword(u32),
+  Other
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RegistryEntry
+{
+  pub(crate) key: String,
+  pub(crate) value: String,
+  pub(crate) data: RegistryValue
+}
+
+struct Reader<'a>
+{
+  input: &'a [u8],
+  position: usize
+}
+
+impl<'a> Reader<'a>
+{
+  fn take(&mut self, length: usize) -> Result<&'a [u8], GroupPolicyError>
+  {
+    let bytes = self.position
+      .checked_add(length)
+      .and_then(|end| self.input.get(self.position..end))
+      .ok_or(GroupPolicyError::MalformedEntry(self.position))?;
+    self.position += length;
+    Ok(bytes)
+  }
+
+  fn u32(&mut self) -> Result<u32, GroupPolicyError>
+  {
+    let bytes = self.take(4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  fn character(&mut self, expected: char) -> Result<(), GroupPolicyError>
+  {
+    let position = self.position;
+    let bytes = self.take(2)?;
+    if u16::from_le_bytes([bytes[0], bytes[1]]) == expected as u16 { Ok(()) } else { Err(GroupPolicyError::MalformedEntry(position)) }
+  }
+
+  // null terminated utf-16
+  fn string(&mut self) -> Result<String, GroupPolicyError>
+  {
+    let mut units = Vec::new();
+    loop
+    {
+      let bytes = self.take(2)?;
+      match u16::from_le_bytes([bytes[0], bytes[1]])
+      {
+        0 => return Ok(String::from_utf16_lossy(&units)),
+        unit => units.push(unit)
+      }
+    }
+  }
+}
+
+// strings in values carry their own terminator, which is dropped along with anything after it
+fn utf16(data: &[u8]) -> String
+{
+  let units: Vec<u16> = data.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).take_while(|unit| *unit != 0).collect();
+  String::from_utf16_lossy(&units)
+}
+
+// a Registry.pol file - a signature and version, then entries of the form
+// [key;value;type;size;data] with the punctuation and names in utf-16
+pub(crate) fn parse(input: &[u8]) -> Result<Vec<RegistryEntry>, GroupPolicyError>
+{
+  let mut reader = Reader { input, position: 0 };
+  if reader.take(4).ok() != Some(SIGNATURE)
+  {
+    return Err(GroupPolicyError::BadSignature);
+  }
+  match reader.u32()?
+  {
+    VERSION => (),
+    version => return Err(GroupPolicyError::UnsupportedVersion(version))
+  }
+  let mut entries = Vec::new();
+  while reader.position < input.len()
+  {
+    reader.character('[')?;
+    let key = reader.string()?;
+    reader.character(';')?;
+    let value = reader.string()?;
+    reader.character(';')?;
+    let data_type = reader.u32()?;
+    reader.character(';')?;
+    let size = reader.u32()? as usize;
+    reader.character(';')?;
+    let position = reader.position;
+    let data = reader.take(size)?;
+    reader.character(']')?;
+    let data = match data_type
+    {
+      REG_SZ | REG_EXPAND_SZ => RegistryValue::String(utf16(data)),
+      REG_DWORD => RegistryValue::Dword(u32::from_le_bytes(data.try_into().map_err(|_| GroupPolicyError::MalformedEntry(position))?)),
+      _ => RegistryValue::Other
+    };
+    entries.push(RegistryEntry { key, value, data });
+  }
+  Ok(entries)
+}
+
+// enrollment policy servers pushed by group policy, one subkey of PolicyServers each.  values
+// whose names start with ** are instructions to delete, not settings
+pub fn policy_servers(registry_policy: &[u8]) -> Result<Vec<PolicyEndpoint>, GroupPolicyError>
+{
+  let mut servers: Vec<(String, HashMap<String, RegistryValue>)> = Vec::new();
+  for entry in parse(registry_policy)?
+  {
+    let key = entry.key.to_lowercase();
+    let server = match key.strip_prefix(POLICY_SERVERS)
+    {
+      Some(server) if !server.is_empty() && !server.contains('\\') && !entry.value.starts_with("**") => server.to_owned(),
+      _ => continue
+    };
+    let index = match servers.iter().position(|(name, _)| *name == server)
+    {
+      Some(index) => index,
+      None =>
+      {
+        servers.push((server, HashMap::new()));
+        servers.len() - 1
+      }
+    };
+    servers[index].1.insert(entry.value.to_lowercase(), entry.data);
+  }
+
+  Ok(servers.into_iter().filter_map(|(server, values)|
+  {
+    let uri = match values.get("url")
+    {
+      Some(RegistryValue::String(uri)) => match Url::parse(uri)
+      {
+        Ok(uri) => uri,
+        Err(err) => { event!(Level::WARN, "invalid url for policy server {}: {}", server, err); return None; }
+      },
+      _ => { event!(Level::WARN, "policy server {} has no url", server); return None; }
+    };
+    let client_authentication = match values.get("authflags")
+    {
+      Some(RegistryValue::Dword(flags)) => ClientAuthentication::from_u32(*flags).unwrap_or_default(),
+      _ => ClientAuthentication::default()
+    };
+    let cost = match values.get("cost")
+    {
+      Some(RegistryValue::Dword(cost)) => *cost,
+      _ => u32::MAX
+    };
+    event!(Level::INFO, "found policy server {} at {}", server, uri);
+    Some(PolicyEndpoint::new(uri, client_authentication, cost.into()))
+  }).collect())
+}
+
+// a container that group policy objects may be linked to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Container
+{
+  pub(crate) links: Vec<(String, u32)>,
+  pub(crate) options: u32
+}
+
+// a group policy object, as its path in sysvol and its flags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GroupPolicyObject
+{
+  pub(crate) file_system_path: String,
+  pub(crate) flags: u32
+}
+
+// the distinguished names of the containers above an object, from the domain down to its parent.
+// escaped commas are part of a name, not a separator
+pub(crate) fn containers(distinguished_name: &str) -> Vec<String>
+{
+  let mut separators = Vec::new();
+  let mut escaped = false;
+  for (offset, character) in distinguished_name.char_indices()
+  {
+    match character
+    {
+      '\\' if !escaped => escaped = true,
+      ',' if !escaped => separators.push(offset),
+      _ => escaped = false
+    }
+  }
+  let mut containers = Vec::new();
+  for separator in separators
+  {
+    let container = distinguished_name[separator + 1..].trim_start();
+    containers.push(container.to_owned());
+    if container.split(',').all(|name| name.trim_start().get(..3).map(|prefix| prefix.eq_ignore_ascii_case("DC=")).unwrap_or(false))
+    {
+      break;
+    }
+  }
+  containers.reverse();
+  containers
+}
+
+// gPLink - [LDAP://distinguished name;options] for each linked object, lowest precedence first
+pub(crate) fn links(gp_link: &str) -> Vec<(String, u32)>
+{
+  gp_link
+    .split(']')
+    .filter_map(|link| link.trim().strip_prefix('['))
+    .filter_map(|link|
+    {
+      let (path, options) = link.rsplit_once(';')?;
+      let distinguished_name = path.get(..7).filter(|scheme| scheme.eq_ignore_ascii_case("LDAP://")).and(path.get(7..))?;
+      Some((distinguished_name.to_owned(), options.trim().parse().unwrap_or_default()))
+    })
+    .collect()
+}
+
+// links of the domain and then each organizational unit down to the account,
+// in the order they apply, so later objects take precedence.  blocking inheritance drops links from
+// above, except enforced ones, which apply after all others with the highest ones winning
+pub(crate) fn applied_links(containers: &[Container]) -> Vec<String>
+{
+  let inherited_from = containers.iter().rposition(|container| container.options & BLOCK_INHERITANCE != 0).unwrap_or(0);
+  let enabled = |container: &Container, enforced: bool| container.links
+    .iter()
+    .filter(move |(_, options)| options & LINK_DISABLED == 0 && (options & LINK_ENFORCED != 0) == enforced)
+    .map(|(distinguished_name, _)| distinguished_name.to_owned())
+    .collect::<Vec<_>>();
+  containers[inherited_from..]
+    .iter()
+    .flat_map(|container| enabled(container, false))
+    .chain(containers.iter().rev().flat_map(|container| enabled(container, true)))
+    .collect()
+}
+
+// the Registry.pol of a group policy object under a copy of the sysvol share, such as a mount or the
+// samba cache.  the share is case insensitive, while copies of it may not keep the case
+pub(crate) fn registry_policy_path(sysvol: &Path, group_policy_object: &GroupPolicyObject, context: EnrollmentContext) -> Option<PathBuf>
+{
+  let disabled = match context
+  {
+    EnrollmentContext::Machine => MACHINE_DISABLED,
+    EnrollmentContext::User => USER_DISABLED
+  };
+  if group_policy_object.flags & disabled != 0
+  {
+    return None;
+  }
+  // \\server\share\domain\Policies\{guid}
+  let components = group_policy_object.file_system_path
+    .split('\\')
+    .filter(|component| !component.is_empty())
+    .skip(2)
+    .chain([match context { EnrollmentContext::Machine => "Machine", EnrollmentContext::User => "User" }, "Registry.pol"]);
+  components.fold(Some(sysvol.to_owned()), |path, component|
+  {
+    let path = path?;
+    match path.join(component).exists()
+    {
+      true => Some(path.join(component)),
+      false => fs::read_dir(&path)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(component))
+        .map(|entry| entry.path())
+    }
+  })
+}
+
+// policy servers from each Registry.pol in the order the objects apply.  a server set again by a
+// later object replaces the earlier setting
+pub(crate) fn group_policy_servers(paths: &[PathBuf]) -> Result<Vec<PolicyEndpoint>, GroupPolicyError>
+{
+  let mut endpoints: Vec<PolicyEndpoint> = Vec::new();
+  for path in paths
+  {
+    let registry_policy = fs::read(path).map_err(|err| GroupPolicyError::UnreadableFile(path.display().to_string(), err.to_string()))?;
+    for endpoint in policy_servers(&registry_policy)?
+    {
+      endpoints.retain(|candidate| candidate.uri != endpoint.uri);
+      endpoints.push(endpoint);
+    }
+  }
+  Ok(endpoints)
+}
@@ -0,0 +1,145 @@
+use std::{env, fs};
+
+use crate::{ClientAuthentication, EnrollmentContext};
+
+use super::{parse, policy_servers, containers, links, applied_links, registry_policy_path, group_policy_servers, Container, GroupPolicyObject, GroupPolicyError, RegistryEntry, RegistryValue};
+
+const KEY: &str = "Software\\Policies\\Microsoft\\Cryptography\\PolicyServers\\37c9dc30f207f27f61a2f7c3aed598a6e2920b54";
+
+fn utf16(text: &str) -> Vec<u8>
+{
+  text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+}
+
+fn character(c: char) -> [u8; 2]
+{
+  (c as u16).to_le_bytes()
+}
+
+fn entry(key: &str, value: &str, data_type: u32, data: &[u8]) -> Vec<u8>
+{
+  [
+    &character('[')[..], &utf16(key), &character(';'),
+    &utf16(value), &character(';'),
+    &data_type.to_le_bytes(), &character(';'),
+    &(data.len() as u32).to_le_bytes(), &character(';'),
+    data, &character(']')
+  ].concat()
+}
+
+fn registry_policy(entries: &[Vec<u8>]) -> Vec<u8>
+{
+  [&b"PReg"[..], &1u32.to_le_bytes(), &entries.concat()].concat()
+}
+
+#[test]
+fn parse_entries()
+{
+  let input = registry_policy(&[
+    entry(KEY, "URL", 1, &utf16("https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP")),
+    entry(KEY, "Cost", 4, &0x7fff_fffdu32.to_le_bytes()),
+    entry(KEY, "Data", 3, &[1, 2, 3])
+  ]);
+  assert_eq!(parse(&input).expect("failed to parse registry policy"), vec![
+    RegistryEntry { key: KEY.to_owned(), value: "URL".to_owned(), data: RegistryValue::String("https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP".to_owned()) },
+    RegistryEntry { key: KEY.to_owned(), value: "Cost".to_owned(), data: RegistryValue::Dword(0x7fff_fffd) },
+    RegistryEntry { key: KEY.to_owned(), value: "Data".to_owned(), data: RegistryValue::Other }
+  ]);
+  assert!(parse(&registry_policy(&[])).expect("failed to parse empty registry policy").is_empty());
+}
+
+#[test]
+fn parse_malformed()
+{
+  assert!(matches!(parse(b"PRe"), Err(GroupPolicyError::BadSignature)));
+  assert!(matches!(parse(b"REGF\x01\0\0\0"), Err(GroupPolicyError::BadSignature)));
+  assert!(matches!(parse(b"PReg\x02\0\0\0"), Err(GroupPolicyError::UnsupportedVersion(2))));
+  let input = registry_policy(&[entry(KEY, "Cost", 4, &1u32.to_le_bytes())]);
+  for length in 8..input.len()
+  {
+    assert!(matches!(parse(&input[..length]), Err(GroupPolicyError::MalformedEntry(_))), "truncated to {}", length);
+  }
+  assert!(matches!(parse(&registry_policy(&[entry(KEY, "Cost", 4, &[1, 0])])), Err(GroupPolicyError::MalformedEntry(_))));
+}
+
+#[test]
+fn policy_servers_from_registry_policy()
+{
+  let other = "Software\\Policies\\Microsoft\\Cryptography\\PolicyServers\\0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c";
+  let input = registry_policy(&[
+    entry("Software\\Policies\\Microsoft\\Cryptography\\PolicyServers", "Flags", 4, &0u32.to_le_bytes()),
+    entry(KEY, "URL", 1, &utf16("https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP")),
+    entry(KEY, "AuthFlags", 4, &2u32.to_le_bytes()),
+    entry(KEY, "Cost", 4, &10u32.to_le_bytes()),
+    entry(KEY, "**del.FriendlyName", 1, &utf16(" ")),
+    entry(&other.to_uppercase(), "url", 2, &utf16("https://cep.fabrikam.com/ADPolicyProvider_CEP_UsernamePassword/service.svc/CEP")),
+    entry(&other.to_uppercase(), "authflags", 4, &4u32.to_le_bytes()),
+    entry("Software\\Policies\\Microsoft\\Cryptography\\PolicyServers\\broken", "Cost", 4, &1u32.to_le_bytes()),
+    entry("Software\\Policies\\Microsoft\\SystemCertificates", "URL", 1, &utf16("https://unrelated.contoso.com"))
+  ]);
+  let servers = policy_servers(&input).expect("failed to read policy servers");
+  assert_eq!(servers.len(), 2);
+  assert_eq!(servers[0].to_string(), "https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP");
+  assert_eq!(servers[0].client_authentication, ClientAuthentication::TransportKerberos);
+  assert_eq!(servers[0].cost, 10);
+  assert_eq!(servers[1].to_string(), "https://cep.fabrikam.com/ADPolicyProvider_CEP_UsernamePassword/service.svc/CEP");
+  assert_eq!(servers[1].client_authentication, ClientAuthentication::SoapUsernamePassword);
+  assert_eq!(servers[1].cost, u32::MAX as u64);
+}
+
+#[test]
+fn containers_above_account()
+{
+  assert_eq!(containers("CN=host\\, one,OU=Servers,OU=Contoso,DC=contoso,DC=com"), vec![
+    "DC=contoso,DC=com".to_owned(),
+    "OU=Contoso,DC=contoso,DC=com".to_owned(),
+    "OU=Servers,OU=Contoso,DC=contoso,DC=com".to_owned()
+  ]);
+  assert_eq!(containers("CN=host,DC=contoso,DC=com"), vec!["DC=contoso,DC=com".to_owned()]);
+}
+
+#[test]
+fn parse_links()
+{
+  let gp_link = "[LDAP://cn={31B2F340-016D-11D2-945F-00C04FB984F9},cn=policies,cn=system,DC=contoso,DC=com;0][ldap://cn={6AC1786C-016F-11D2-945F-00C04FB984F9},cn=policies,cn=system,DC=contoso,DC=com;2][bogus;0]";
+  assert_eq!(links(gp_link), vec![
+    ("cn={31B2F340-016D-11D2-945F-00C04FB984F9},cn=policies,cn=system,DC=contoso,DC=com".to_owned(), 0),
+    ("cn={6AC1786C-016F-11D2-945F-00C04FB984F9},cn=policies,cn=system,DC=contoso,DC=com".to_owned(), 2)
+  ]);
+  assert!(links(" ").is_empty());
+}
+
+#[test]
+fn link_precedence()
+{
+  let container = |links: &[(&str, u32)], options| Container { links: links.iter().map(|(name, options)| (name.to_string(), *options)).collect(), options };
+  let domain = container(&[("domain", 0), ("domain enforced", 2), ("domain disabled", 1)], 0);
+  let organizational_unit = container(&[("ou", 0), ("ou enforced", 2)], 0);
+  assert_eq!(applied_links(&[domain.clone(), organizational_unit.clone()]), vec!["domain", "ou", "ou enforced", "domain enforced"]);
+  // blocking inheritance keeps only enforced links from above
+  let blocked = Container { options: 1, ..organizational_unit };
+  assert_eq!(applied_links(&[domain, blocked]), vec!["ou", "ou enforced", "domain enforced"]);
+  assert!(applied_links(&[]).is_empty());
+}
+
+#[test]
+fn read_sysvol()
+{
+  let sysvol = env::temp_dir().join(format!("libadcs-sysvol-{}", std::process::id()));
+  let machine = sysvol.join("CONTOSO.COM").join("POLICIES").join("{31B2F340-016D-11D2-945F-00C04FB984F9}").join("MACHINE");
+  fs::create_dir_all(&machine).expect("failed to create sysvol");
+  fs::write(machine.join("REGISTRY.POL"), registry_policy(&[
+    entry(KEY, "URL", 1, &utf16("https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP")),
+    entry(KEY, "Cost", 4, &1u32.to_le_bytes())
+  ])).expect("failed to write registry policy");
+
+  let group_policy_object = GroupPolicyObject { file_system_path: "\\\\contoso.com\\SysVol\\contoso.com\\Policies\\{31B2F340-016D-11D2-945F-00C04FB984F9}".to_owned(), flags: 0 };
+  let path = registry_policy_path(&sysvol, &group_policy_object, EnrollmentContext::Machine).expect("registry policy not found");
+  assert!(registry_policy_path(&sysvol, &group_policy_object, EnrollmentContext::User).is_none());
+  assert!(registry_policy_path(&sysvol, &GroupPolicyObject { flags: 2, ..group_policy_object }, EnrollmentContext::Machine).is_none());
+  // a server set by two objects is only listed once
+  let servers = group_policy_servers(&[path.clone(), path]).expect("failed to read registry policy");
+  fs::remove_dir_all(&sysvol).expect("failed to remove sysvol");
+  assert_eq!(servers.len(), 1);
+  assert_eq!(servers[0].to_string(), "https://cep.contoso.com/ADPolicyProvider_CEP_Kerberos/service.svc/CEP");
+}
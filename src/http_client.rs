@@ -1,25 +1,139 @@
 use reqwest::Url;
+#[cfg(feature = "enrollment_https")]
+use tracing::{event, Level, instrument};
 
-use crate::{soap::{SoapClient, HeaderBuilder, SoapHttpError}, soap_operations::{xcep::{GetPoliciesRequest, GetPoliciesResponse}, wstrust::{RequestSecurityToken, RequestSecurityTokenResponseCollection}}, client::{Policy, EnrollmentResponse}, NamedCertificate};
+use crate::{soap::{SoapClient, HeaderBuilder, SoapHttpError}, soap_operations::xcep::{GetPoliciesRequest, GetPoliciesResponse}, client::Policy, NamedCertificate};
+#[cfg(feature = "enrollment_https")]
+use crate::{soap_operations::wstrust::{RequestSecurityToken, RequestSecurityTokenResponseCollection}, client::{EnrollmentClient, EnrollmentResponse, EnrollmentService, RequestLocation, Transport}, AdcsError, ClientAuthentication};
+#[cfg(feature = "enrollment_https")]
+use x509_certificate::X509Certificate;
 
-pub fn get_policy(root_certificates: Vec<NamedCertificate>, endpoint: &Url) -> Result<Policy, SoapHttpError>
+pub fn get_policy(root_certificates: Vec<NamedCertificate>, endpoint: &Url, principal: Option<&str>) -> Result<Policy, SoapHttpError>
 {
   let header = HeaderBuilder::default()
     .to(endpoint.to_string())
     .action("http://schemas.microsoft.com/windows/pki/2009/01/enrollmentpolicy/IPolicy/GetPolicies")
     .build()?;
-  let client = SoapClient::new();
+  let client = SoapClient::new(principal.map(str::to_owned));
   let response: GetPoliciesResponse = client.invoke(&header, &GetPoliciesRequest::default())?;
   Ok(response.into_policy(root_certificates))
 }
 
-fn submit(request: &[u8], endpoint: Url) -> Result<EnrollmentResponse, SoapHttpError>
+#[cfg(feature = "enrollment_https")]
+pub struct HttpsEnrollmentClient
+{
+  principal: Option<String>
+}
+
+#[cfg(feature = "enrollment_https")]
+impl HttpsEnrollmentClient
+{
+  pub fn new(principal: Option<String>) -> Self
+  {
+    Self { principal }
+  }
+
+  fn soap_client(&self) -> SoapClient
+  {
+    SoapClient::new(self.principal.clone())
+  }
+}
+
+#[cfg(feature = "enrollment_https")]
+impl EnrollmentClient for HttpsEnrollmentClient
+{
+  fn submit(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let template = policy.get_template_by_name(template).ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    for enrollment_service in policy.get_enrollment_services_for_template(template)?
+    {
+      for endpoint in enrollment_service.find_https_endpoints(ClientAuthentication::TransportKerberos, false)
+      {
+        match request_security_token(&self.soap_client(), &RequestSecurityToken::new(&request, None), endpoint.clone())
+        {
+          Ok(response) => return Ok(response.located(&enrollment_service.get_certificate().nickname, Transport::Https(endpoint.clone()), template.get_name())),
+          Err(err) => event!(Level::WARN, "error submitting to https endpoint {}: {}.  skipping", endpoint, err)
+        }
+      }
+    }
+    Err(AdcsError::NoEnrollmentEndpoint(template.get_name().to_owned()))
+  }
+
+  fn poll(&self, request_id: u32, location: &RequestLocation) -> Result<EnrollmentResponse, AdcsError>
+  {
+    match &location.transport
+    {
+      Transport::Https(endpoint) =>
+      {
+        let response = request_security_token(&self.soap_client(), &RequestSecurityToken::query(request_id), endpoint.clone())?;
+        Ok(response.located(&location.authority, location.transport.clone(), &location.template))
+      },
+      Transport::Rpc(_) => Err(AdcsError::NoEnrollmentEndpoint(location.template.clone()))
+    }
+  }
+
+  fn handles(&self, transport: &Transport) -> bool
+  {
+    matches!(transport, Transport::Https(_))
+  }
+
+  // MS-XCEP 3.1.4.1.3.5 - renewal may also use endpoints which authenticate by the cms signature alone
+  fn renew(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let template = policy.get_template_by_name(template).ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    for enrollment_service in policy.get_enrollment_services_for_template(template)?
+    {
+      for (client, endpoint) in enrollment_service
+        .find_https_endpoints(ClientAuthentication::TransportKerberos, true)
+        .map(|endpoint| (self.soap_client(), endpoint))
+        .chain(enrollment_service
+          .find_https_endpoints(ClientAuthentication::CmsSignature, true)
+          .map(|endpoint| (SoapClient::without_negotiate(), endpoint)))
+      {
+        match request_security_token(&client, &RequestSecurityToken::renew(&request), endpoint.clone())
+        {
+          Ok(response) => return Ok(response.located(&enrollment_service.get_certificate().nickname, Transport::Https(endpoint.clone()), template.get_name())),
+          Err(err) => event!(Level::WARN, "error renewing with https endpoint {}: {}.  skipping", endpoint, err)
+        }
+      }
+    }
+    Err(AdcsError::NoEnrollmentEndpoint(template.get_name().to_owned()))
+  }
+
+  fn exchange_certificate(&self, enrollment_service: &EnrollmentService) -> Result<X509Certificate, AdcsError>
+  {
+    for endpoint in enrollment_service.find_https_endpoints(ClientAuthentication::TransportKerberos, false)
+    {
+      match key_exchange_token(&self.soap_client(), endpoint.clone())
+      {
+        Ok(certificate) => return Ok(certificate),
+        Err(err) => event!(Level::WARN, "error retrieving exchange certificate from https endpoint {}: {}.  skipping", endpoint, err)
+      }
+    }
+    Err(AdcsError::NoExchangeCertificate(enrollment_service.get_certificate().nickname.clone()))
+  }
+}
+
+#[cfg(feature = "enrollment_https")]
+#[instrument(skip(client, request))]
+fn request_security_token(client: &SoapClient, request: &RequestSecurityToken, endpoint: Url) -> Result<EnrollmentResponse, AdcsError>
 {
-  let client = SoapClient::new();
   let header = HeaderBuilder::default()
     .to(endpoint.to_string())
     .action("http://schemas.microsoft.com/windows/pki/2009/01/enrollment/RST/wstep")
     .build()?;
-  let response: RequestSecurityTokenResponseCollection = client.invoke(&header, &RequestSecurityToken::new(request, None))?;
-  Ok(response.into())
-}
\ No newline at end of file
+  let response: RequestSecurityTokenResponseCollection = client.invoke(&header, request)?;
+  Ok(response.try_into()?)
+}
+
+#[cfg(feature = "enrollment_https")]
+#[instrument(skip(client))]
+fn key_exchange_token(client: &SoapClient, endpoint: Url) -> Result<X509Certificate, AdcsError>
+{
+  let header = HeaderBuilder::default()
+    .to(endpoint.to_string())
+    .action("http://docs.oasis-open.org/ws-sx/ws-trust/200512/RST/KET")
+    .build()?;
+  let response: RequestSecurityTokenResponseCollection = client.invoke(&header, &RequestSecurityToken::key_exchange_token())?;
+  Ok(response.into_key_exchange_token()?)
+}
@@ -0,0 +1,425 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use derive_builder::Builder;
+use ldap3::controls::{RawControl, Control, ControlType, PagedResults};
+use ldap3::exop::{WhoAmI, WhoAmIResp};
+use ldap3::{Scope, SearchEntry, LdapConn, LdapConnSettings, get_url_params};
+use native_tls::{TlsConnector, Certificate, Identity};
+use tracing::{event, Level, instrument};
+use url::Url;
+use x509_certificate::certificate::X509Certificate;
+
+use crate::EnrollmentContext;
+use crate::service::{ServiceLocator, ServiceLocatorError, DnsServiceLocator};
+use super::LdapError;
+
+// global catalog ports
+const GC_PORT: u16 = 3268;
+const GC_TLS_PORT: u16 = 3269;
+// referrals name domain controllers rather than global catalogs
+const LDAP_PORT: u16 = 389;
+const LDAPS_PORT: u16 = 636;
+// below the default MaxPageSize of 1000, so servers never shorten pages on their own
+const PAGE_SIZE: i32 = 500;
+// how many referrals deep a search follows before giving up on a branch
+const MAXIMUM_REFERRAL_HOPS: usize = 4;
+
+#[derive(Clone, Default)]
+pub enum LdapBind
+{
+  // kerberos, as the principal of the enrollment context
+  #[default]
+  Gssapi,
+  Simple
+  {
+    bind_dn: String,
+    password: String
+  },
+  // authenticated by a tls client certificate.  both files are pem, with the key in pkcs8
+  External
+  {
+    certificate: PathBuf,
+    private_key: PathBuf
+  }
+}
+
+// the password stays out of the logs
+impl Debug for LdapBind
+{
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+  {
+    match self
+    {
+      LdapBind::Gssapi => f.write_str("Gssapi"),
+      LdapBind::Simple { bind_dn, .. } => f.debug_struct("Simple").field("bind_dn", bind_dn).finish_non_exhaustive(),
+      LdapBind::External { certificate, private_key } => f.debug_struct("External").field("certificate", certificate).field("private_key", private_key).finish()
+    }
+  }
+}
+
+// how to find and authenticate to a global catalog.  the defaults discover servers for the realm
+// from dns, and bind with kerberos over plain ldap
+#[derive(Debug, Clone, Default, Builder)]
+#[builder(pattern = "owned", setter(into), default)]
+pub struct LdapConnectionOptions
+{
+  // tried in order instead of discovering servers from dns
+  servers: Vec<String>,
+  // replaces the port from dns, or the global catalog port for fixed servers
+  port: Option<u16>,
+  bind: LdapBind,
+  // ldaps.  starttls is ignored when set
+  tls: bool,
+  starttls: bool,
+  // pem bundle of certificate authorities trusted in addition to the system store
+  ca_bundle: Option<PathBuf>,
+  connect_timeout: Option<Duration>,
+  operation_timeout: Option<Duration>,
+  // asked for srv records instead of the nameservers of the host
+  nameservers: Vec<SocketAddr>,
+  // replaces dns altogether.  nameservers are ignored when set
+  #[builder(setter(custom))]
+  service_locator: Option<Arc<dyn ServiceLocator>>
+}
+
+impl LdapConnectionOptionsBuilder
+{
+  pub fn service_locator(mut self, service_locator: impl ServiceLocator + 'static) -> Self
+  {
+    self.service_locator = Some(Some(Arc::new(service_locator)));
+    self
+  }
+}
+
+impl LdapConnectionOptions
+{
+  pub(crate) fn servers(&self) -> &[String]
+  {
+    &self.servers
+  }
+
+  pub(crate) fn scheme(&self) -> &'static str
+  {
+    if self.tls { "ldaps" } else { "ldap" }
+  }
+
+  pub(crate) fn port(&self, discovered: Option<u16>) -> u16
+  {
+    self.port.or(discovered).unwrap_or(if self.tls { GC_TLS_PORT } else { GC_PORT })
+  }
+
+  pub(crate) fn is_tls(&self) -> bool
+  {
+    self.tls
+  }
+
+  // where servers are discovered, so that a server found through one locator is not reused with
+  // another
+  pub(crate) fn locator_identity(&self) -> String
+  {
+    match &self.service_locator
+    {
+      Some(service_locator) => format!("{:p}", Arc::as_ptr(service_locator)),
+      None => format!("dns {:?}", self.nameservers)
+    }
+  }
+
+  pub(crate) fn service_locator(&self) -> Result<Arc<dyn ServiceLocator>, ServiceLocatorError>
+  {
+    Ok(match &self.service_locator
+    {
+      Some(service_locator) => service_locator.clone(),
+      None if self.nameservers.is_empty() => Arc::new(DnsServiceLocator::system()?),
+      None => Arc::new(DnsServiceLocator::with_nameservers(&self.nameservers)?)
+    })
+  }
+
+  fn settings(&self) -> Result<LdapConnSettings, LdapError>
+  {
+    let mut settings = LdapConnSettings::new().set_starttls(self.starttls && !self.tls);
+    if let Some(timeout) = self.connect_timeout
+    {
+      settings = settings.set_conn_timeout(timeout);
+    }
+    if self.tls || self.starttls
+    {
+      settings = settings.set_connector(self.tls_connector()?);
+    }
+    else if matches!(self.bind, LdapBind::External { .. })
+    {
+      return Err(LdapError::ExternalBindWithoutTls);
+    }
+    Ok(settings)
+  }
+
+  fn tls_connector(&self) -> Result<TlsConnector, LdapError>
+  {
+    let mut builder = TlsConnector::builder();
+    if let Some(ca_bundle) = &self.ca_bundle
+    {
+      let certificates = X509Certificate::from_pem_multiple(read(ca_bundle)?)
+        .map_err(|err| LdapError::UnreadableFile(ca_bundle.display().to_string(), err.to_string()))?;
+      for certificate in certificates
+      {
+        let der = certificate.encode_der().map_err(|err| LdapError::UnreadableFile(ca_bundle.display().to_string(), err.to_string()))?;
+        builder.add_root_certificate(Certificate::from_der(&der)?);
+      }
+    }
+    if let LdapBind::External { certificate, private_key } = &self.bind
+    {
+      builder.identity(Identity::from_pkcs8(&read(certificate)?, &read(private_key)?)?);
+    }
+    Ok(builder.build()?)
+  }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, LdapError>
+{
+  fs::read(path).map_err(|err| LdapError::UnreadableFile(path.display().to_string(), err.to_string()))
+}
+
+// a bound connection.  ldap3 forgets controls and timeouts after each operation, so they are
+// applied again every time.  the options and context are kept for connecting to referred servers
+#[derive(Debug)]
+pub(crate) struct Connection
+{
+  ldap: LdapConn,
+  fqdn: String,
+  options: LdapConnectionOptions,
+  context: EnrollmentContext
+}
+
+// one page of a search.  referrals are either continuation references to the parts of the tree
+// held elsewhere, or, when the whole search was referred, where to send it instead
+struct Page
+{
+  entries: Vec<SearchEntry>,
+  referrals: Vec<String>,
+  referred: bool,
+  cookie: Vec<u8>
+}
+
+impl Connection
+{
+  #[instrument(skip(options))]
+  pub(crate) fn new(fqdn: &str, port: u16, options: &LdapConnectionOptions, context: EnrollmentContext) -> Result<Self, LdapError>
+  {
+    let fqdn = fqdn.trim_end_matches('.');
+    let mut ldap = LdapConn::with_settings(options.settings()?, &format!("{}://{}:{}", options.scheme(), fqdn, port))?;
+    if let Some(timeout) = options.operation_timeout
+    {
+      ldap.with_timeout(timeout);
+    }
+    match &options.bind
+    {
+      LdapBind::Gssapi =>
+      {
+        context.acquire_credentials()?;
+        ldap.sasl_gssapi_bind(fqdn)?.success()?
+      },
+      LdapBind::Simple { bind_dn, password } => ldap.simple_bind(bind_dn, password)?.success()?,
+      LdapBind::External { .. } => ldap.sasl_external_bind()?.success()?
+    };
+    event!(Level::INFO, "selected {} on port {}", fqdn, port);
+    Ok(Self { ldap, fqdn: fqdn.to_owned(), options: options.clone(), context })
+  }
+
+  // a global catalog holds only part of each domain, but it is also a domain controller for its
+  // own domain, which holds all of it
+  pub(crate) fn domain_controller(&self) -> Result<Self, LdapError>
+  {
+    Self::new(&self.fqdn, if self.options.is_tls() { LDAPS_PORT } else { LDAP_PORT }, &self.options, self.context)
+  }
+
+  fn prepare(&mut self)
+  {
+    if let Some(timeout) = self.options.operation_timeout
+    {
+      self.ldap.with_timeout(timeout);
+    }
+  }
+
+  fn page(&mut self, base: &str, scope: Scope, filter: &str, attributes: &[String], cookie: Vec<u8>) -> Result<Page, LdapError>
+  {
+    self.prepare();
+    self.ldap.with_controls(search_controls(attributes, cookie));
+    let (rs, result) = self.ldap.search(base, scope, filter, attributes)?.non_error()?;
+    let cookie = result
+      .ctrls
+      .iter()
+      .find_map(|control| match control
+      {
+        Control(Some(ControlType::PagedResults), raw) => Some(raw.parse::<PagedResults>().cookie),
+        _ => None
+      })
+      .unwrap_or_default();
+    Ok(Page
+    {
+      entries: rs.into_iter().map(SearchEntry::construct).collect(),
+      referrals: result.refs,
+      referred: result.rc == 10,
+      cookie
+    })
+  }
+
+  // entries are fetched a page at a time as the iterator is advanced, so large result sets never
+  // run into the size limit of the server
+  pub(crate) fn entries<'a>(&'a mut self, base: &str, scope: Scope, filter: &str, attributes: &[&str]) -> Entries<'a>
+  {
+    Entries::new(Source::Borrowed(self), base.to_owned(), scope, filter.to_owned(), attributes.iter().map(|attribute| attribute.to_string()).collect(), 0)
+  }
+
+  pub(crate) fn search(&mut self, base: &str, scope: Scope, filter: &str, attributes: Vec<&str>) -> Result<Vec<SearchEntry>, LdapError>
+  {
+    self.entries(base, scope, filter, &attributes).collect()
+  }
+
+  pub(crate) fn who_am_i(&mut self) -> Result<String, LdapError>
+  {
+    self.prepare();
+    let (rs, _) = self.ldap.extended(WhoAmI)?.success()?;
+    Ok(rs.parse::<WhoAmIResp>().authzid)
+  }
+}
+
+// the security descriptor flags control asks for the owner, group and dacl, which are all that
+// may be read without SeSecurityPrivilege.  it is only sent with searches for the security
+// descriptor, and not marked critical, so other servers may ignore it
+pub(super) fn search_controls(attributes: &[String], cookie: Vec<u8>) -> Vec<RawControl>
+{
+  let security_descriptor_flag_control = attributes
+    .iter()
+    .any(|attribute| attribute.eq_ignore_ascii_case("nTSecurityDescriptor"))
+    .then(|| RawControl
+    {
+      ctype: "1.2.840.113556.1.4.801".to_owned(),
+      crit: false,
+      val: Some(vec![0x30, 0x03, 0x02, 0x01, 0x07])
+    });
+  security_descriptor_flag_control.into_iter().chain([PagedResults { size: PAGE_SIZE, cookie }.into()]).collect()
+}
+
+// the server, port and base a referral points at.  the base of the referring search is kept when
+// the url has none
+pub(crate) fn referral_target(referral: &str, base: &str, tls: bool) -> Option<(String, u16, String)>
+{
+  let url = Url::parse(referral).ok()?;
+  let host_name = url.host_str().filter(|host_name| !host_name.is_empty())?.to_owned();
+  let port = url.port().unwrap_or(if tls { LDAPS_PORT } else { LDAP_PORT });
+  let referred_base = get_url_params(&url).ok()?.base;
+  Some((host_name, port, if referred_base.is_empty() { base.to_owned() } else { referred_base.into_owned() }))
+}
+
+enum Source<'a>
+{
+  Borrowed(&'a mut Connection),
+  Owned(Box<Connection>)
+}
+
+impl Source<'_>
+{
+  fn get(&mut self) -> &mut Connection
+  {
+    match self
+    {
+      Source::Borrowed(connection) => connection,
+      Source::Owned(connection) => connection
+    }
+  }
+}
+
+pub(crate) struct Entries<'a>
+{
+  source: Source<'a>,
+  base: String,
+  scope: Scope,
+  filter: String,
+  attributes: Vec<String>,
+  // the cookie for the next page, or None once the last page is in
+  cookie: Option<Vec<u8>>,
+  buffer: VecDeque<SearchEntry>,
+  referrals: VecDeque<(String, Scope)>,
+  referred: Option<Box<Entries<'static>>>,
+  hops: usize
+}
+
+impl<'a> Entries<'a>
+{
+  fn new(source: Source<'a>, base: String, scope: Scope, filter: String, attributes: Vec<String>, hops: usize) -> Self
+  {
+    Self { source, base, scope, filter, attributes, cookie: Some(vec![]), buffer: VecDeque::new(), referrals: VecDeque::new(), referred: None, hops }
+  }
+
+  // servers that cannot be reached are skipped, as the rest of the results are still good
+  fn chase(&mut self, referral: &str, scope: Scope) -> Option<Box<Entries<'static>>>
+  {
+    if self.hops >= MAXIMUM_REFERRAL_HOPS
+    {
+      event!(Level::WARN, "not following referral to {} after {} hops", referral, self.hops);
+      return None;
+    }
+    let connection = self.source.get();
+    let (host_name, port, base) = match referral_target(referral, &self.base, connection.options.is_tls())
+    {
+      Some(target) => target,
+      None => { event!(Level::WARN, "invalid referral {}", referral); return None; }
+    };
+    match Connection::new(&host_name, port, &connection.options, connection.context)
+    {
+      Ok(referred) =>
+      {
+        event!(Level::DEBUG, "following referral to {}", referral);
+        Some(Box::new(Entries::new(Source::Owned(Box::new(referred)), base, scope, self.filter.clone(), self.attributes.clone(), self.hops + 1)))
+      },
+      Err(err) => { event!(Level::WARN, "error following referral to {} ({})", referral, err); None }
+    }
+  }
+}
+
+impl Iterator for Entries<'_>
+{
+  type Item = Result<SearchEntry, LdapError>;
+
+  fn next(&mut self) -> Option<Self::Item>
+  {
+    loop
+    {
+      if let Some(entry) = self.buffer.pop_front()
+      {
+        return Some(Ok(entry));
+      }
+      if let Some(referred) = &mut self.referred
+      {
+        match referred.next()
+        {
+          Some(entry) => return Some(entry),
+          None => self.referred = None
+        }
+        continue;
+      }
+      if let Some(cookie) = self.cookie.take()
+      {
+        let page = match self.source.get().page(&self.base, self.scope, &self.filter, &self.attributes, cookie)
+        {
+          Ok(page) => page,
+          Err(err) => return Some(Err(err))
+        };
+        // RFC 4511 4.5.3 - continuation references below a one level search name the entries
+        // themselves, so they are searched with base scope
+        let scope = if !page.referred && self.scope == Scope::OneLevel { Scope::Base } else { self.scope };
+        self.buffer.extend(page.entries);
+        self.referrals.extend(page.referrals.into_iter().map(|referral| (referral, scope)));
+        self.cookie = Some(page.cookie).filter(|cookie| !cookie.is_empty());
+        continue;
+      }
+      let (referral, scope) = self.referrals.pop_front()?;
+      self.referred = self.chase(&referral, scope);
+    }
+  }
+}
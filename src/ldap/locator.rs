@@ -0,0 +1,405 @@
+use std::net::{UdpSocket, IpAddr, SocketAddr};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitflags::bitflags;
+use rand::prelude::*;
+use tracing::{event, Level, instrument};
+
+use crate::EnrollmentContext;
+use crate::service::{ServiceLocator, SrvRecord};
+use super::{LdapError, LdapConnectionOptions};
+use super::connection::Connection;
+
+const CLDAP_PORT: u16 = 389;
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+// NETLOGON_NT_VERSION_5 | NETLOGON_NT_VERSION_5EX
+const NT_VERSION: u32 = 0x0000_0006;
+const LOGON_SAM_LOGON_RESPONSE_EX: u16 = 23;
+const LOGON_SAM_USER_UNKNOWN_EX: u16 = 25;
+// the most compression pointers followed for a single name
+const MAXIMUM_POINTERS: usize = 16;
+
+// the last domain controller connected to for each realm, tried before looking any further
+static SELECTED: Mutex<Vec<(Selection, DomainController)>> = Mutex::new(Vec::new());
+
+bitflags!
+{
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+  pub(crate) struct DsFlags: u32
+  {
+    const PDC      = 0x0000_0001;
+    const GC       = 0x0000_0004;
+    const LDAP     = 0x0000_0008;
+    const DS       = 0x0000_0010;
+    const KDC      = 0x0000_0020;
+    const CLOSEST  = 0x0000_0080;
+    const WRITABLE = 0x0000_0100;
+  }
+}
+
+// what a selection was made for.  the same realm reached over another scheme or port, or through
+// another locator, may need a different server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Selection
+{
+  pub(crate) realm: String,
+  pub(crate) scheme: &'static str,
+  pub(crate) port: u16,
+  pub(crate) locator: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DomainController
+{
+  pub(crate) host_name: String,
+  pub(crate) port: u16
+}
+
+// MS-ADTS 6.3.1.9 - only the fields the locator needs are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NetlogonResponse
+{
+  pub(crate) flags: DsFlags,
+  pub(crate) dns_forest_name: String,
+  pub(crate) dns_host_name: String,
+  pub(crate) dc_site_name: String,
+  pub(crate) client_site_name: String
+}
+
+impl NetlogonResponse
+{
+  pub(crate) fn new(input: &[u8]) -> Result<Self, LdapError>
+  {
+    let bad = |message: &str| LdapError::BadNetlogonResponse(message.to_owned());
+    let opcode = u16::from_le_bytes(input.get(0..2).and_then(|v| v.try_into().ok()).ok_or_else(|| bad("truncated opcode"))?);
+    if opcode != LOGON_SAM_LOGON_RESPONSE_EX && opcode != LOGON_SAM_USER_UNKNOWN_EX
+    {
+      return Err(bad(&format!("unexpected opcode {}", opcode)));
+    }
+    let flags = DsFlags::from_bits_retain(u32::from_le_bytes(input.get(4..8).and_then(|v| v.try_into().ok()).ok_or_else(|| bad("truncated flags"))?));
+    // the domain guid is skipped
+    let mut position = 24;
+    let mut name = || -> Result<String, LdapError>
+    {
+      let (name, next) = compressed_name(input, position).ok_or_else(|| bad("malformed name"))?;
+      position = next;
+      Ok(name)
+    };
+    let dns_forest_name = name()?;
+    let _dns_domain_name = name()?;
+    let dns_host_name = name()?;
+    let _netbios_domain_name = name()?;
+    let _netbios_computer_name = name()?;
+    let _user_name = name()?;
+    let dc_site_name = name()?;
+    let client_site_name = name()?;
+    Ok(Self { flags, dns_forest_name, dns_host_name, dc_site_name, client_site_name })
+  }
+}
+
+// RFC 1035 4.1.4 - a name is a run of length prefixed labels, ending either with an empty label or
+// with a pointer to the rest of the name elsewhere in the message.  returns the name and the
+// position after it
+fn compressed_name(input: &[u8], mut position: usize) -> Option<(String, usize)>
+{
+  let mut labels = Vec::new();
+  let mut next = None;
+  for _ in 0..=MAXIMUM_POINTERS
+  {
+    loop
+    {
+      let length = *input.get(position)? as usize;
+      match length
+      {
+        0 =>
+        {
+          return Some((labels.join("."), next.unwrap_or(position + 1)));
+        },
+        length if length & 0xc0 == 0xc0 =>
+        {
+          let pointer = ((length & 0x3f) << 8) | *input.get(position + 1)? as usize;
+          next.get_or_insert(position + 2);
+          position = pointer;
+          break;
+        },
+        length if length > 63 => return None,
+        length =>
+        {
+          labels.push(String::from_utf8_lossy(input.get(position + 1..position + 1 + length)?).into_owned());
+          position += 1 + length;
+        }
+      }
+    }
+  }
+  None
+}
+
+// just enough ber for the ldap ping.  lengths are always written in long form, which is valid ber
+fn ber(tag: u8, contents: &[u8]) -> Vec<u8>
+{
+  [&[tag, 0x84][..], &(contents.len() as u32).to_be_bytes(), contents].concat()
+}
+
+// splits the first tag off the input, returning the tag, its contents and whatever follows
+fn ber_split(input: &[u8]) -> Option<(u8, &[u8], &[u8])>
+{
+  let tag = *input.first()?;
+  let first = *input.get(1)? as usize;
+  let (length, offset) = if first & 0x80 == 0
+  {
+    (first, 2)
+  }
+  else
+  {
+    let octets = first & 0x7f;
+    if octets > 4
+    {
+      return None;
+    }
+    let length = input.get(2..2 + octets)?.iter().fold(0usize, |length, octet| (length << 8) | *octet as usize);
+    (length, 2 + octets)
+  };
+  let contents = input.get(offset..offset.checked_add(length)?)?;
+  Some((tag, contents, &input[offset + length..]))
+}
+
+// MS-ADTS 6.3.3 - a search of the rootdse over udp, filtered on the domain and the response
+// version wanted, for the Netlogon attribute
+pub(crate) fn ping_request(message_id: u32, domain: &str) -> Vec<u8>
+{
+  let equality = |attribute: &str, value: &[u8]| ber(0xa3, &[ber(0x04, attribute.as_bytes()), ber(0x04, value)].concat());
+  let filter = ber(0xa0, &[equality("DnsDomain", domain.as_bytes()), equality("NtVer", &NT_VERSION.to_le_bytes())].concat());
+  let search = ber(0x63, &[
+    ber(0x04, b""),
+    ber(0x0a, &[0]),
+    ber(0x0a, &[0]),
+    ber(0x02, &[0]),
+    ber(0x02, &[0]),
+    ber(0x01, &[0]),
+    filter,
+    ber(0x30, &ber(0x04, b"Netlogon"))
+  ].concat());
+  ber(0x30, &[ber(0x02, &integer(message_id)), search].concat())
+}
+
+// X.690 8.3.2 - integers take as few octets as keep the sign
+fn integer(value: u32) -> Vec<u8>
+{
+  let bytes = value.to_be_bytes();
+  let start = (0..3).find(|&i| bytes[i] != 0 || bytes[i + 1] & 0x80 != 0).unwrap_or(3);
+  bytes[start..].to_vec()
+}
+
+// the first message of the reply is a search result entry holding the single Netlogon value
+pub(crate) fn ping_response(input: &[u8]) -> Result<NetlogonResponse, LdapError>
+{
+  let bad = || LdapError::BadNetlogonResponse("malformed ldap message".to_owned());
+  let (_, message, _) = ber_split(input).ok_or_else(bad)?;
+  let (_, _, message) = ber_split(message).ok_or_else(bad)?;
+  let (tag, entry, _) = ber_split(message).ok_or_else(bad)?;
+  if tag != 0x64
+  {
+    return Err(LdapError::BadNetlogonResponse(format!("unexpected response 0x{:02x}", tag)));
+  }
+  let (_, _, entry) = ber_split(entry).ok_or_else(bad)?;
+  let (_, attributes, _) = ber_split(entry).ok_or_else(bad)?;
+  let (_, attribute, _) = ber_split(attributes).ok_or_else(bad)?;
+  let (_, _, attribute) = ber_split(attribute).ok_or_else(bad)?;
+  let (_, values, _) = ber_split(attribute).ok_or_else(bad)?;
+  let (_, value, _) = ber_split(values).ok_or_else(bad)?;
+  NetlogonResponse::new(value)
+}
+
+#[instrument]
+fn ping(address: SocketAddr, domain: &str) -> Result<NetlogonResponse, LdapError>
+{
+  let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).map_err(LdapError::Ping)?;
+  socket.set_read_timeout(Some(PING_TIMEOUT)).map_err(LdapError::Ping)?;
+  socket.connect(address).map_err(LdapError::Ping)?;
+  socket.send(&ping_request(thread_rng().gen_range(1..0x7fff_ffff), domain)).map_err(LdapError::Ping)?;
+  let mut buffer = vec![0; 65536];
+  let length = socket.recv(&mut buffer).map_err(LdapError::Ping)?;
+  ping_response(&buffer[..length])
+}
+
+// RFC 2782 - lower priorities first.  within a priority, each record is picked at random with a
+// chance proportional to its weight from those not yet picked, with zero weight records only
+// likely once nothing else is left
+pub(crate) fn weighted_order(mut records: Vec<SrvRecord>, rng: &mut impl Rng) -> Vec<SrvRecord>
+{
+  records.sort_by_key(|record| (record.priority, record.weight != 0));
+  let mut result = Vec::with_capacity(records.len());
+  while let Some(priority) = records.first().map(|record| record.priority)
+  {
+    let mut group: Vec<SrvRecord> = records.iter().take_while(|record| record.priority == priority).cloned().collect();
+    records.drain(..group.len());
+    while !group.is_empty()
+    {
+      let total: u32 = group.iter().map(|record| record.weight as u32).sum();
+      let selection = rng.gen_range(0..=total);
+      let mut running = 0;
+      let index = group
+        .iter()
+        .position(|record|
+        {
+          running += record.weight as u32;
+          running >= selection
+        })
+        .unwrap_or(group.len() - 1);
+      result.push(group.remove(index));
+    }
+  }
+  result
+}
+
+pub(crate) struct Locator
+{
+  service_locator: Arc<dyn ServiceLocator>,
+  ping_port: u16
+}
+
+impl Locator
+{
+  pub(crate) fn new(service_locator: Arc<dyn ServiceLocator>) -> Self
+  {
+    Self { service_locator, ping_port: CLDAP_PORT }
+  }
+
+  // lets tests answer pings from an unprivileged port
+  #[cfg(test)]
+  pub(crate) fn with_ping_port(mut self, ping_port: u16) -> Self
+  {
+    self.ping_port = ping_port;
+    self
+  }
+
+  fn lookup(&self, name: &str) -> Vec<SrvRecord>
+  {
+    self.service_locator.srv_lookup(name).unwrap_or_else(|err| { event!(Level::DEBUG, "error resolving {}: {}", name, err); vec![] })
+  }
+
+  fn addresses(&self, host_name: &str) -> Vec<IpAddr>
+  {
+    self.service_locator.ip_lookup(host_name).unwrap_or_else(|err| { event!(Level::DEBUG, "error resolving {}: {}", host_name, err); vec![] })
+  }
+
+  // the site comes from pinging any global catalog of the domain; the one that answers says which
+  // site the client is in, regardless of its own.  site records live under the forest root.  all of
+  // them are pinged at once and the first answer wins, so waiting is bounded by a single timeout
+  fn client_site(&self, domain: &str, records: &[SrvRecord]) -> Option<(String, String)>
+  {
+    let (sender, receiver) = mpsc::channel();
+    for address in records.iter().flat_map(|record| self.addresses(record.target.trim_end_matches('.')))
+    {
+      let address = SocketAddr::new(address, self.ping_port);
+      let sender = sender.clone();
+      let domain = domain.to_owned();
+      thread::spawn(move ||
+      {
+        let _ = sender.send((address, ping(address, &domain)));
+      });
+    }
+    drop(sender);
+    let deadline = Instant::now() + PING_TIMEOUT;
+    while let Ok((address, response)) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+      match response
+      {
+        Ok(response) =>
+        {
+          event!(Level::INFO, "{} is in site {} (closest: {}), client is in site {}", response.dns_host_name, response.dc_site_name, response.flags.contains(DsFlags::CLOSEST), response.client_site_name);
+          if !response.client_site_name.is_empty()
+          {
+            return Some((response.dns_forest_name, response.client_site_name));
+          }
+        },
+        Err(err) => event!(Level::DEBUG, "no ldap ping response from {}: {}", address, err)
+      }
+    }
+    None
+  }
+
+  // global catalogs in the client's site come first, followed by the rest of the forest.  ports
+  // from dns are for plain ldap, so ldaps uses the global catalog tls port unless told otherwise
+  pub(crate) fn candidates(&self, domain: &str, options: &LdapConnectionOptions) -> Vec<DomainController>
+  {
+    let mut rng = thread_rng();
+    let records = weighted_order(self.lookup(&format!("_gc._tcp.{}", domain)), &mut rng);
+    let site_records = match self.client_site(domain, &records)
+    {
+      Some((forest, site)) => weighted_order(self.lookup(&format!("_gc._tcp.{}._sites.{}", site, forest)), &mut rng),
+      None => vec![]
+    };
+    let mut candidates: Vec<DomainController> = Vec::new();
+    for record in site_records.into_iter().chain(records)
+    {
+      let candidate = DomainController
+      {
+        host_name: record.target.trim_end_matches('.').to_owned(),
+        port: if options.is_tls() { options.port(None) } else { options.port(Some(record.port)) }
+      };
+      if !candidates.contains(&candidate)
+      {
+        candidates.push(candidate);
+      }
+    }
+    candidates
+  }
+
+  // the realm and each parent domain are searched in turn
+  #[instrument(skip(self, options))]
+  pub(crate) fn locate(&self, realm: &str, options: &LdapConnectionOptions, context: EnrollmentContext) -> Option<Connection>
+  {
+    let realm = realm.trim_end_matches('.').to_lowercase();
+    let selection = Selection { realm: realm.clone(), scheme: options.scheme(), port: options.port(None), locator: options.locator_identity() };
+    if let Some(selected) = cached(&selection)
+    {
+      match Connection::new(&selected.host_name, selected.port, options, context)
+      {
+        Ok(conn) => return Some(conn),
+        Err(err) => event!(Level::WARN, "error connecting to previously selected {} ({}).  locating another", selected.host_name, err)
+      }
+    }
+
+    let names: Vec<_> = realm.split('.').collect();
+    for i in 0..names.len()
+    {
+      let domain = names[i..].join(".");
+      for candidate in self.candidates(&domain, options)
+      {
+        match Connection::new(&candidate.host_name, candidate.port, options, context)
+        {
+          Ok(conn) =>
+          {
+            cache(&selection, candidate);
+            return Some(conn);
+          },
+          Err(err) => event!(Level::WARN, "error connecting to {} on port {} ({})", candidate.host_name, candidate.port, err)
+        }
+      }
+    }
+    None
+  }
+}
+
+pub(crate) fn cached(selection: &Selection) -> Option<DomainController>
+{
+  SELECTED
+    .lock()
+    .ok()?
+    .iter()
+    .find(|(candidate, _)| candidate == selection)
+    .map(|(_, selected)| selected.clone())
+}
+
+pub(crate) fn cache(selection: &Selection, selected: DomainController)
+{
+  if let Ok(mut cache) = SELECTED.lock()
+  {
+    cache.retain(|(candidate, _)| candidate != selection);
+    cache.push((selection.clone(), selected));
+  }
+}
@@ -0,0 +1,745 @@
+mod connection;
+mod locator;
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::time::Duration;
+
+use itertools::Itertools;
+use ldap3::{Scope, SearchEntry};
+use thiserror::Error;
+use tracing::{event, Level, instrument};
+use bcder::Oid;
+use uuid::Uuid;
+use bytes::Bytes;
+use num_traits::FromPrimitive;
+use url::Url;
+use crate::client::{EnrollmentService, CertificateTemplateBuilder, HttpsEndpoint};
+use crate::template::{SubjectNameFlags, EnrollmentFlags, PrivateKeyFlags, GeneralFlags, KeyRequirements, RaRequirements, KeySpec, KeyArchivalAttributes};
+use crate::cmc::archival::{ID_DES_EDE3_CBC, ID_AES128_CBC, ID_AES192_CBC, ID_AES256_CBC};
+use crate::{NamedCertificate, PolicyEndpoint, ClientAuthentication};
+use crate::group_policy::{self, Container, GroupPolicyObject};
+use crate::csr::Identity;
+use crate::CertificateTemplate;
+use crate::EnrollmentContext;
+use crate::service::ServiceLocatorError;
+use crate::sddl::{SDDL, AccessMask, AUTO_ENROLL, ENROLL, SID};
+use x509_certificate::certificate::X509Certificate;
+use connection::Connection;
+use locator::Locator;
+
+pub use connection::{LdapConnectionOptions, LdapConnectionOptionsBuilder, LdapBind};
+
+
+#[derive(Error, Debug)]
+pub enum LdapError
+{
+  #[error("ldap connection error: {0}")]
+  LdapConnectionFailed(#[from] ldap3::LdapError),
+
+  #[error("could not locate global catalog server")]
+  NoGlobalCatalogServer,
+
+  #[error("service discovery error: {0}")]
+  ServiceLocator(#[from] ServiceLocatorError),
+
+  #[error("tls error: {0}")]
+  Tls(#[from] native_tls::Error),
+
+  #[error("could not read {0}: {1}")]
+  UnreadableFile(String, String),
+
+  #[error("sasl external bind requires ldaps or starttls")]
+  ExternalBindWithoutTls,
+
+  #[error("ldap ping failed: {0}")]
+  Ping(std::io::Error),
+
+  #[error("bad ldap ping response: {0}")]
+  BadNetlogonResponse(String),
+
+  #[error("no rootdse (is this active directory???)")]
+  NoRootDSE,
+
+  #[error("could not locate ourselves in global catalog")]
+  NoMyself,
+
+  #[error("{0} is not a computer account")]
+  NotMachineAccount(String),
+
+  #[error("cannot enroll in the {1:?} context from a process already using the {0:?} context")]
+  MixedContexts(EnrollmentContext, EnrollmentContext),
+
+  #[error("KRB5_CLIENT_KTNAME must name the host keytab to enroll in the machine context")]
+  NoMachineKeytab,
+
+  #[error("could not read forest oid from {0}")]
+  NoForestOid(String),
+
+  #[error("could not read policy id from {0}")]
+  NoPolicyId(String)
+}
+
+#[derive(Debug)]
+struct RootDSE
+{
+  configuration_naming_context: String,
+  root_domain_naming_context: String,
+  default_naming_context: String,
+  certificate_templates: String,
+  certification_authorities: String,
+  enrollment_services: String,
+  oid: String
+}
+
+impl RootDSE
+{
+  fn new(ldap: &mut Connection) -> Result<Option<Self>, LdapError>
+  {
+    let rs = ldap.search("", Scope::Base, "(objectClass=*)", vec!["configurationNamingContext", "rootDomainNamingContext", "defaultNamingContext"])?;
+    if let Some(rootdse) = rs.into_iter().next()
+    {
+      match
+      (
+        rootdse.attrs.get("configurationNamingContext").and_then(|v| v.iter().next()),
+        rootdse.attrs.get("rootDomainNamingContext").and_then(|v| v.iter().next()),
+        rootdse.attrs.get("defaultNamingContext").and_then(|v| v.iter().next())
+      )
+      {
+        (
+          Some(configuration_naming_context),
+          Some(root_domain_naming_context),
+          Some(default_naming_context)
+        ) => Ok(Some(Self
+        {
+          configuration_naming_context: configuration_naming_context.to_string(),
+          root_domain_naming_context: root_domain_naming_context.to_string(),
+          default_naming_context: default_naming_context.to_string(),
+          certificate_templates: format!("CN=Certificate Templates,CN=Public Key Services,CN=Services,{}", configuration_naming_context),
+          certification_authorities: format!("CN=Certification Authorities,CN=Public Key Services,CN=Services,{}", configuration_naming_context),
+          enrollment_services: format!("CN=Enrollment Services,CN=Public Key Services,CN=Services,{}", configuration_naming_context),
+          oid: format!("CN=OID,CN=Public Key Services,CN=Services,{}", configuration_naming_context)
+        })),
+        (_, _, _) => Ok(None)
+      }
+    }
+    else { Ok(None) }
+  }
+}
+
+#[instrument]
+fn myself(ldap: &mut Connection, rootdse: &RootDSE) -> Result<Option<LdapPrincipal>, LdapError>
+{
+  let authzid = ldap.who_am_i()?;
+  let netbios_name = authzid.split(':').nth(1);
+  let sam_account_name = netbios_name.and_then(|netbios_name| netbios_name.split('\\').nth(1));
+
+  event!(Level::INFO, netbios_name = netbios_name, sam_account_name = sam_account_name);
+
+  if let (Some(netbios_name), Some(sam_account_name)) = (netbios_name, sam_account_name)
+  {
+    // stops reading as soon as the account turns up
+    for user in LdapPrincipal::from_query(ldap, &rootdse.root_domain_naming_context, Scope::Subtree, &format!("(sAMAccountName={})", sam_account_name))
+    {
+      let user = user?;
+      if user.principal_name == netbios_name
+      {
+        return Ok(Some(user));
+      }
+    }
+    Ok(None)
+  }
+  else
+  {
+    Ok(None)
+  }
+}
+
+// tokenGroups is constructed, so it can only be read from the object itself with a base search.
+// on a global catalog tokenGroupsGlobalAndUniversal also carries universal groups from other
+// domains.  the sids every logon token carries are added here, as they are not stored anywhere
+#[instrument]
+fn token_groups(ldap: &mut Connection, me: &LdapPrincipal) -> Result<HashSet<SID>, LdapError>
+{
+  let rs = ldap.search(&me.distinguished_name, Scope::Base, "(objectClass=*)", vec!["tokenGroups", "tokenGroupsGlobalAndUniversal"])?;
+  let mut token: HashSet<SID> = rs
+    .into_iter()
+    .flat_map(|rs| rs.bin_attrs
+      .into_values()
+      .flatten()
+      .chain(rs.attrs.into_values().flatten().map(String::into_bytes))
+      .collect::<Vec<_>>())
+    .filter_map(|bytes| match SID::new(&bytes)
+    {
+      Ok(sid) => Some(sid),
+      Err(err) => { event!(Level::WARN, "invalid sid in token groups: {}", err); None }
+    })
+    .collect();
+  event!(Level::INFO, "found {} groups in token", token.len());
+
+  token.insert(me.object_sid.clone());
+  // everyone, authenticated users and this organization
+  token.insert(SID::from_components(1, &[0]));
+  token.insert(SID::from_components(5, &[11]));
+  token.insert(SID::from_components(5, &[15]));
+  if me.is_computer
+  {
+    // domain computers
+    if let Some(domain) = me.object_sid.domain()
+    {
+      token.insert(domain.with_rid(515));
+    }
+  }
+  Ok(token)
+}
+
+// fixed servers are tried in order, otherwise the domain controller locator picks one
+#[instrument(skip(options))]
+fn try_all_ldap_servers(realm: &str, options: &LdapConnectionOptions, context: EnrollmentContext) -> Result<Option<Connection>, LdapError>
+{
+  if options.servers().is_empty()
+  {
+    Ok(Locator::new(options.service_locator()?).locate(realm, options, context))
+  }
+  else
+  {
+    Ok(options.servers().iter().find_map(|server| match Connection::new(server, options.port(None), options, context)
+    {
+      Ok(conn) => Some(conn),
+      Err(err) => { event!(Level::WARN, "error connecting to {} on port {} ({})", server, options.port(None), err); None }
+    }))
+  }
+}
+
+pub struct LdapManager
+{
+  ldap: Connection,
+  rootdse: RootDSE,
+  me: LdapPrincipal,
+  token: HashSet<SID>,
+  context: EnrollmentContext,
+  realm: String,
+  principal: Option<String>
+}
+
+impl LdapManager
+{
+  #[instrument]
+  pub fn new(realm: String, context: EnrollmentContext, options: &LdapConnectionOptions) -> Result<Self, LdapError>
+  {
+    if let Some(mut ldap) = try_all_ldap_servers(&realm, options, context)?
+    {
+      event!(Level::INFO, "found ldap global catalog for realm {} (using tls: {})", realm, options.is_tls());
+      if let Some(rootdse) = RootDSE::new(&mut ldap)?
+      {
+        event!(Level::INFO, "found rootdse");
+        if let Some(me) = myself(&mut ldap, &rootdse)?
+        {
+          event!(Level::INFO, "found myself in ldap");
+          // permissions are checked against the computer object, so binding as anyone else is an error
+          if context == EnrollmentContext::Machine && !me.is_computer
+          {
+            return Err(LdapError::NotMachineAccount(me.principal_name));
+          }
+          let token = token_groups(&mut ldap, &me)?;
+          Ok(Self
+            {
+              ldap,
+              rootdse,
+              me,
+              token,
+              context,
+              principal: context.principal(&realm),
+              realm
+            })
+        }
+        else
+        {
+          Err(LdapError::NoMyself)
+        }
+      }
+      else
+      {
+        Err(LdapError::NoRootDSE)
+      }
+    }
+    else
+    {
+      Err(LdapError::NoGlobalCatalogServer)
+    }
+  }
+
+  // templates are converted as they arrive.  ones that cannot be read are skipped
+  #[instrument(skip(self))]
+  pub fn certificate_templates(&mut self) -> impl Iterator<Item = Result<CertificateTemplate, LdapError>> + '_
+  {
+    let token = &self.token;
+    self.ldap.entries(&self.rootdse.certificate_templates, Scope::OneLevel, "(objectClass=pKICertificateTemplate)", &[
+      "cn",
+      "nTSecurityDescriptor",
+      "msPKI-Certificate-Name-Flag",
+      "msPKI-Enrollment-Flag",
+      "msPKI-Private-Key-Flag",
+      "msPKI-Minimal-Key-Size",
+      "pKIExtendedKeyUsage",
+      "msPKI-Cert-Template-OID",
+      "msPKI-Template-Schema-Version",
+      "pKIExpirationPeriod",
+      "pKIOverlapPeriod",
+      "msPKI-RA-Signature",
+      "msPKI-RA-Policies",
+      "msPKI-RA-Application-Policies",
+      "msPKI-Template-Minor-Revision",
+      "msPKI-Supersede-Templates",
+      "pKIDefaultKeySpec",
+      "pKIDefaultCSPs",
+      "revision",
+      "flags"
+    ]).filter_map(move |result| result.map(|result| certificate_template(&result, token)).transpose())
+  }
+
+  pub fn get_certificate_templates(&mut self) -> Result<Vec<CertificateTemplate>, LdapError>
+  {
+    self.certificate_templates().collect()
+  }
+
+  #[instrument(skip(self))]
+  pub fn get_root_certificates(&mut self) -> Result<Vec<NamedCertificate>, LdapError>
+  {
+    let rs = self.ldap.search(&self.rootdse.certification_authorities, Scope::OneLevel, "(objectClass=certificationAuthority)", vec!["cACertificate", "cn"])?;
+    Ok(rs.into_iter().filter_map(|result|
+    {
+      match
+      (
+        result.attrs.get("cn").and_then(|v| v.iter().next()),
+        result.bin_attrs.get("cACertificate").and_then(|v| v.iter().next().and_then(|v| match X509Certificate::from_der(v)
+        {
+          Ok(certificate) => Some(certificate),
+          Err(err) => { event!(Level::WARN, "invalid root certificate: {}", err); None }
+        }))
+      )
+      {
+        (Some(cn), Some(certificate)) =>
+        {
+          event!(Level::INFO, "found root cert {}", cn);
+          Some(NamedCertificate { nickname: cn.to_owned(), certificate })
+        },
+        _ => None
+      }
+    }).collect::<Vec<_>>())
+  }
+
+  #[instrument(skip(self))]
+  pub fn enrollment_services(&mut self) -> impl Iterator<Item = Result<EnrollmentService, LdapError>> + '_
+  {
+    self.ldap
+      .entries(&self.rootdse.enrollment_services, Scope::OneLevel, "(objectClass=pKIEnrollmentService)", &["cn", "dNSHostName", "cACertificate", "certificateTemplates", "msPKI-Enrollment-Servers"])
+      .filter_map(|rs| rs.map(enrollment_service).transpose())
+  }
+
+  pub fn get_enrollment_service(&mut self) -> Result<Vec<EnrollmentService>, LdapError>
+  {
+    self.enrollment_services().collect()
+  }
+
+  // with no enrollment policy servers configured, policy comes from the public key services
+  // container of the forest.  it is given the highest cost so that configured servers are tried first
+  pub fn get_policy_endpoints(&self) -> Vec<PolicyEndpoint>
+  {
+    let public_key_services = format!("CN=Public Key Services,CN=Services,{}", self.rootdse.configuration_naming_context);
+    match Url::parse(&format!("ldap://{}/{}", self.realm, public_key_services))
+    {
+      Ok(uri) => vec![PolicyEndpoint::new(uri, ClientAuthentication::TransportKerberos, u64::MAX)],
+      Err(err) => { event!(Level::WARN, "invalid policy location {} in {}: {}", public_key_services, self.realm, err); vec![] }
+    }
+  }
+
+  // the group policy objects that apply to the account, in the order they apply.  neither gPLink
+  // nor gPCFileSysPath is in the global catalog, so they are read from a domain controller
+  #[instrument(skip(self))]
+  pub fn get_group_policy_objects(&mut self) -> Result<Vec<GroupPolicyObject>, LdapError>
+  {
+    let mut ldap = self.ldap.domain_controller()?;
+    let containers = group_policy::containers(&self.me.distinguished_name)
+      .iter()
+      .map(|container| Ok(ldap
+        .search(container, Scope::Base, "(objectClass=*)", vec!["gPLink", "gPOptions"])?
+        .into_iter()
+        .next()
+        .map(|entry| Container
+        {
+          links: entry.attrs.get("gPLink").and_then(|v| v.first()).map(|gp_link| group_policy::links(gp_link)).unwrap_or_default(),
+          options: entry.attrs.get("gPOptions").and_then(|v| v.first()).and_then(|options| options.parse().ok()).unwrap_or_default()
+        })
+        .unwrap_or(Container { links: vec![], options: 0 })))
+      .collect::<Result<Vec<_>, LdapError>>()?;
+    let mut group_policy_objects = Vec::new();
+    for distinguished_name in group_policy::applied_links(&containers)
+    {
+      let entry = match ldap.search(&distinguished_name, Scope::Base, "(objectClass=groupPolicyContainer)", vec!["gPCFileSysPath", "flags"])
+      {
+        Ok(rs) => rs.into_iter().next(),
+        Err(err) => { event!(Level::WARN, "error reading group policy object {}: {}", distinguished_name, err); None }
+      };
+      match entry.as_ref().and_then(|entry| entry.attrs.get("gPCFileSysPath")).and_then(|v| v.first())
+      {
+        Some(file_system_path) =>
+        {
+          event!(Level::INFO, "found group policy object {}", distinguished_name);
+          let flags = entry.as_ref().and_then(|entry| entry.attrs.get("flags")).and_then(|v| v.first()).and_then(|flags| flags.parse().ok()).unwrap_or_default();
+          group_policy_objects.push(GroupPolicyObject { file_system_path: file_system_path.to_owned(), flags });
+        },
+        None => event!(Level::WARN, "group policy object {} has no path in sysvol", distinguished_name)
+      }
+    }
+    Ok(group_policy_objects)
+  }
+
+  pub fn get_identity(&self) -> Identity
+  {
+    self.me.identity.clone()
+  }
+
+  pub fn get_context(&self) -> EnrollmentContext
+  {
+    self.context
+  }
+
+  // the principal other kerberos authenticated connections should use, or None for the default
+  pub fn get_principal(&self) -> Option<&str>
+  {
+    self.principal.as_deref()
+  }
+
+  // MS-CAESO 4.4.5.3.2.3 - the ldap policy is identified by the objectGUID of the forest root domain nc, which is
+  // the policy id group policy and cep servers report for it
+  #[instrument(skip(self))]
+  pub fn get_id(&mut self) -> Result<String, LdapError>
+  {
+    let rs = self.ldap.search(&self.rootdse.root_domain_naming_context, Scope::Base, "(objectClass=*)", vec!["objectGUID"])?;
+    match rs
+      .into_iter()
+      .next()
+      .and_then(|rs| rs.bin_attrs
+        .get("objectGUID")
+        .and_then(|v| v.first().cloned())
+        .or_else(|| rs.attrs.get("objectGUID").and_then(|v| v.first()).map(|v| v.as_bytes().to_vec())))
+      .and_then(|object_guid| policy_id(&object_guid))
+    {
+      Some(policy_id) =>
+      {
+        event!(Level::INFO, "found policy id {}", policy_id);
+        Ok(policy_id)
+      },
+      None => Err(LdapError::NoPolicyId(self.rootdse.root_domain_naming_context.clone()))
+    }
+  }
+
+  // the forest oid stored on the OID container, which callers may also identify the ldap policy by
+  #[instrument(skip(self))]
+  pub fn get_forest_oid(&mut self) -> Result<String, LdapError>
+  {
+    let rs = self.ldap.search(&self.rootdse.oid, Scope::Base, "(objectClass=msPKI-Enterprise-Oid)", vec!["msPKI-Cert-Template-OID"])?;
+    match rs
+      .into_iter()
+      .next()
+      .and_then(|rs| rs.attrs.get("msPKI-Cert-Template-OID").and_then(|v| v.first().map(|v| v.to_owned())))
+    {
+      Some(forest_oid) =>
+      {
+        event!(Level::INFO, "found forest oid {}", forest_oid);
+        Ok(forest_oid)
+      },
+      None => Err(LdapError::NoForestOid(self.rootdse.oid.clone()))
+    }
+  }
+}
+
+fn certificate_template(result: &SearchEntry, token: &HashSet<SID>) -> Option<CertificateTemplate>
+{
+  let permissions = match result.bin_attrs.get("nTSecurityDescriptor").and_then(|v| v.iter().next())
+  {
+    Some(security_descriptor) =>
+    {
+      match SDDL::new(security_descriptor)
+      {
+        Ok(sddl) =>
+        {
+          let check = |object_type: &Uuid|
+          {
+            match sddl.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(object_type), |sid| Ok::<_, Infallible>(token.contains(sid)))
+            {
+              Ok(decision) =>
+              {
+                event!(Level::DEBUG, "{} on {}: {:?}", object_type, result.dn, decision);
+                decision.is_allowed()
+              },
+              Err(never) => match never {}
+            }
+          };
+          Some((check(&ENROLL), check(&AUTO_ENROLL)))
+        },
+        Err(err) => { event!(Level::WARN, "invalid sddl: {}", err); None }
+      }
+    }
+    None => None,
+  };
+  let cn = result.attrs.get("cn").and_then(|v| v.iter().next().map(|v| v.to_owned()));
+
+  match (cn, permissions)
+  {
+    (Some(cn), Some((enroll, auto_enroll))) => CertificateTemplateBuilder::default()
+      .cn(cn)
+      .enroll(enroll)
+      .auto_enroll(auto_enroll)
+      .template_oid(result.attrs.get("msPKI-Cert-Template-OID").and_then(|v| v.first()).and_then(|v| Oid::from_str(v).ok()))
+      .schema_version(integer_attribute(result, "msPKI-Template-Schema-Version").unwrap_or(1))
+      .revision(integer_attribute(result, "revision").unwrap_or_default())
+      .minor_revision(integer_attribute(result, "msPKI-Template-Minor-Revision").unwrap_or_default())
+      .superseded_templates(result.attrs.get("msPKI-Supersede-Templates").cloned().unwrap_or_default())
+      .subject_name_flags(SubjectNameFlags::from_bits_retain(integer_attribute(result, "msPKI-Certificate-Name-Flag").unwrap_or_default()))
+      .enrollment_flags(EnrollmentFlags::from_bits_retain(integer_attribute(result, "msPKI-Enrollment-Flag").unwrap_or_default()))
+      .private_key_flags(PrivateKeyFlags::from_bits_retain(integer_attribute(result, "msPKI-Private-Key-Flag").unwrap_or_default()))
+      .general_flags(GeneralFlags::from_bits_retain(integer_attribute(result, "flags").unwrap_or_default()))
+      .key_requirements(KeyRequirements
+      {
+        minimal_key_length: integer_attribute(result, "msPKI-Minimal-Key-Size").unwrap_or_default(),
+        algorithm: None,
+        key_spec: integer_attribute(result, "pKIDefaultKeySpec").and_then(KeySpec::from_u32),
+        crypto_providers: crypto_providers(result)
+      })
+      .extended_key_usages(oid_attribute(result, "pKIExtendedKeyUsage"))
+      .validity_period(period_attribute(result, "pKIExpirationPeriod"))
+      .renewal_period(period_attribute(result, "pKIOverlapPeriod"))
+      .ra_requirements(ra_requirements(result))
+      .key_archival(PrivateKeyFlags::from_bits_retain(integer_attribute(result, "msPKI-Private-Key-Flag").unwrap_or_default())
+        .contains(PrivateKeyFlags::REQUIRE_PRIVATE_KEY_ARCHIVAL)
+        .then(|| key_archival_attributes(result)))
+      .build()
+      .map_err(|err| event!(Level::WARN, "invalid template {}: {}", result.dn, err))
+      .ok(),
+    _ => None
+  }
+}
+
+fn enrollment_service(rs: SearchEntry) -> Option<EnrollmentService>
+{
+  match
+  (
+    rs.attrs.get("cn").and_then(|v| v.first().map(|v| v.to_owned())),
+    rs.attrs.get("dNSHostName").and_then(|v| v.first().map(|v| v.to_owned())),
+    rs.bin_attrs.get("cACertificate").and_then(|v| v.first().and_then(|v| match X509Certificate::from_der(v)
+    {
+      Ok(certificate) => Some(certificate),
+      Err(err) => { event!(Level::WARN, "invalid enrollment service certificate: {}", err); None }
+    })),
+    rs.attrs.get("certificateTemplates").map(|v| v.to_vec())
+  )
+  {
+    (Some(cn), Some(host_name), Some(certificate), Some(templates)) =>
+    {
+      event!(Level::INFO, "found enrollment service {}", cn);
+      let https_endpoints = rs.attrs.get("msPKI-Enrollment-Servers").map(|v| enrollment_servers(v)).unwrap_or_default();
+      Some(EnrollmentService::new(NamedCertificate { nickname: cn, certificate }, templates, https_endpoints, Some(host_name)))
+    },
+    _ => None
+  }
+}
+
+// each value holds one or more enrollment web services as four lines: priority, authentication
+// type, whether it is for renewal only, and the url
+pub(crate) fn enrollment_servers(values: &[String]) -> Vec<HttpsEndpoint>
+{
+  values
+    .iter()
+    .flat_map(|value|
+    {
+      let lines: Vec<&str> = value.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+      if lines.len() % 4 != 0
+      {
+        event!(Level::WARN, "ignoring trailing lines of enrollment server list {:?}", value);
+      }
+      lines
+        .chunks_exact(4)
+        .filter_map(|server| match
+        (
+          server[0].parse::<u32>().ok(),
+          server[1].parse::<u32>().ok().and_then(ClientAuthentication::from_u32),
+          server[2].parse::<u32>().ok(),
+          Url::parse(server[3]).ok()
+        )
+        {
+          (Some(priority), Some(client_authentication), Some(renewal_only), Some(uri)) => Some(HttpsEndpoint::new(client_authentication, renewal_only != 0, uri, priority)),
+          _ => { event!(Level::WARN, "invalid enrollment server {:?}", server); None }
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+// flag attributes are stored as signed 32 bit integers, so the high bit shows up as a negative number
+fn integer_attribute(entry: &SearchEntry, name: &str) -> Option<u32>
+{
+  entry.attrs
+    .get(name)
+    .and_then(|v| v.first())
+    .and_then(|v| v.parse::<i64>().ok())
+    .map(|v| v as u32)
+}
+
+fn oid_attribute(entry: &SearchEntry, name: &str) -> Vec<Oid>
+{
+  entry.attrs
+    .get(name)
+    .map(|v| v.iter().filter_map(|oid| Oid::from_str(oid).ok()).collect())
+    .unwrap_or_default()
+}
+
+// MS-CRTD 2.13 - each value is "<priority>,<provider name>"
+fn crypto_providers(entry: &SearchEntry) -> Vec<String>
+{
+  entry.attrs
+    .get("pKIDefaultCSPs")
+    .map(|v| v
+      .iter()
+      .filter_map(|csp| csp.split_once(','))
+      .sorted_by_key(|(priority, _)| priority.trim().parse::<u32>().unwrap_or(u32::MAX))
+      .map(|(_, name)| name.to_owned())
+      .collect())
+    .unwrap_or_default()
+}
+
+// MS-CRTD 2.23 - schema 4 templates pack the archival algorithm, and the application policies
+// themselves, into msPKI-RA-Application-Policies as backtick separated name`type`value triples
+fn packed_properties(entry: &SearchEntry) -> Vec<(&str, &str)>
+{
+  entry.attrs
+    .get("msPKI-RA-Application-Policies")
+    .into_iter()
+    .flatten()
+    .filter(|value| value.contains('`'))
+    .flat_map(|value| value.split('`').tuples().map(|(name, _, value)| (name, value)))
+    .collect()
+}
+
+fn ra_requirements(entry: &SearchEntry) -> RaRequirements
+{
+  RaRequirements
+  {
+    signatures: integer_attribute(entry, "msPKI-RA-Signature").unwrap_or_default(),
+    application_policies: ra_application_policies(entry),
+    issuance_policies: oid_attribute(entry, "msPKI-RA-Policies")
+  }
+}
+
+// earlier schemas store one oid per value
+fn ra_application_policies(entry: &SearchEntry) -> Vec<Oid>
+{
+  let packed = packed_properties(entry)
+    .into_iter()
+    .filter(|(name, _)| *name == "msPKI-RA-Application-Policies")
+    .map(|(_, value)| value);
+  entry.attrs
+    .get("msPKI-RA-Application-Policies")
+    .into_iter()
+    .flatten()
+    .filter(|value| !value.contains('`'))
+    .map(String::as_str)
+    .chain(packed)
+    .filter_map(|oid| Oid::from_str(oid).ok())
+    .collect()
+}
+
+fn key_archival_attributes(entry: &SearchEntry) -> KeyArchivalAttributes
+{
+  let properties = packed_properties(entry);
+  let property = |name: &str| properties.iter().find(|(candidate, _)| *candidate == name).map(|(_, value)| *value);
+  let symmetric_key_length = property("msPKI-Symmetric-Key-Length").and_then(|length| length.parse().ok()).unwrap_or_default();
+  let symmetric_algorithm = property("msPKI-Symmetric-Algorithm")
+    .and_then(|algorithm| match (algorithm.to_uppercase().as_str(), symmetric_key_length)
+    {
+      ("3DES", _) => Some(ID_DES_EDE3_CBC),
+      ("AES", 128) => Some(ID_AES128_CBC),
+      ("AES", 192) => Some(ID_AES192_CBC),
+      ("AES", _) => Some(ID_AES256_CBC),
+      (algorithm, _) =>
+      {
+        event!(Level::WARN, "unknown symmetric algorithm {}.  skipping", algorithm);
+        None
+      }
+    })
+    .map(|oid| Oid(Bytes::from_static(oid)));
+  KeyArchivalAttributes { symmetric_algorithm, symmetric_key_length }
+}
+
+// objectGUID is stored little endian, policy ids are written as upper case braced guids
+pub(crate) fn policy_id(object_guid: &[u8]) -> Option<String>
+{
+  Uuid::from_slice_le(object_guid).ok().map(|uuid| format!("{:X}", uuid.braced()))
+}
+
+// MS-CRTD 2.4, 2.9 - periods are negative FILETIME intervals (100ns ticks), little endian
+fn period_attribute(entry: &SearchEntry, name: &str) -> Option<Duration>
+{
+  let bytes = entry.bin_attrs
+    .get(name)
+    .and_then(|v| v.first().cloned())
+    .or_else(|| entry.attrs.get(name).and_then(|v| v.first()).map(|v| v.as_bytes().to_vec()))?;
+  let ticks = i64::from_le_bytes(bytes.try_into().ok()?);
+  ticks.unsigned_abs().checked_mul(100).map(Duration::from_nanos)
+}
+
+#[derive(Debug)]
+struct LdapPrincipal
+{
+  object_sid: SID,
+  principal_name: String,
+  distinguished_name: String,
+  is_computer: bool,
+  identity: Identity
+}
+
+impl LdapPrincipal
+{
+  #[instrument]
+  fn from_query<'a>(ldap: &'a mut Connection, base: &str, scope: Scope, filter: &str) -> impl Iterator<Item = Result<Self, LdapError>> + 'a
+  {
+    ldap
+      .entries(base, scope, filter, &["objectSid", "objectClass", "msDS-PrincipalName", "distinguishedName", "cn", "dNSHostName", "userPrincipalName", "mail"])
+      .filter_map(|rs| rs.map(Self::from_entry).transpose())
+  }
+
+  fn from_entry(rs: SearchEntry) -> Option<Self>
+  {
+    match
+    (
+      rs.bin_attrs.get("objectSid").and_then(|v|
+        v.first().and_then(|bytes| match SID::new(bytes)
+        {
+          Ok(sid) => Some(sid),
+          Err(err) => { event!(Level::WARN, "invalid sid: {}", err); None }
+        })),
+      rs.attrs.get("msDS-PrincipalName").and_then(|v| v.first().map(|v| v.to_owned())),
+      rs.attrs.get("distinguishedName").and_then(|v| v.first().map(|v| v.to_owned())),
+    )
+    {
+      (Some(object_sid), Some(principal_name), Some(distinguished_name)) =>
+      {
+        let identity = Identity
+        {
+          common_name: rs.attrs.get("cn").and_then(|v| v.first().map(|v| v.to_owned())).unwrap_or_default(),
+          dns_name: rs.attrs.get("dNSHostName").and_then(|v| v.first().map(|v| v.to_owned())),
+          user_principal_name: rs.attrs.get("userPrincipalName").and_then(|v| v.first().map(|v| v.to_owned())),
+          email: rs.attrs.get("mail").and_then(|v| v.first().map(|v| v.to_owned()))
+        };
+        let is_computer = rs.attrs.get("objectClass").map(|v| v.iter().any(|class| class.eq_ignore_ascii_case("computer"))).unwrap_or(false);
+        Some(Self { object_sid, principal_name, distinguished_name, is_computer, identity })
+      },
+      _ => None
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+use bcder::Oid;
+use ldap3::SearchEntry;
+use bytes::Bytes;
+use x509_certificate::{KeyAlgorithm, X509CertificateBuilder};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::service::{ServiceLocator, SrvRecord, StaticServiceLocator};
+use url::Url;
+
+use crate::{AdcsError, ClientAuthentication};
+use crate::certificate::{check_agent, ID_EXTENDED_KEY_USAGE};
+use crate::client::{HttpsEndpoint, Policy};
+use super::connection::{referral_target, search_controls};
+use super::locator::{DsFlags, NetlogonResponse, Locator, Selection, DomainController, weighted_order, ping_request, ping_response, cache, cached};
+use super::{LdapError, LdapConnectionOptions, LdapConnectionOptionsBuilder, enrollment_servers, policy_id, ra_application_policies, ra_requirements, key_archival_attributes};
+
+fn record(priority: u16, weight: u16, target: &str) -> SrvRecord
+{
+  SrvRecord { priority, weight, port: 3268, target: target.to_owned() }
+}
+
+// short and two octet length forms, unlike the long form the request is written in
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8>
+{
+  let length = if contents.len() < 0x80 { vec![contents.len() as u8] } else { vec![0x82, (contents.len() >> 8) as u8, contents.len() as u8] };
+  [&[tag][..], &length, contents].concat()
+}
+
+fn labels(name: &str) -> Vec<u8>
+{
+  name.split('.').flat_map(|label| [&[label.len() as u8][..], label.as_bytes()].concat()).collect()
+}
+
+// contoso.com, with the domain and host names compressed against the forest name and the client
+// site against the dc site
+fn netlogon() -> Vec<u8>
+{
+  let mut result = Vec::new();
+  result.extend_from_slice(&23u16.to_le_bytes());
+  result.extend_from_slice(&[0, 0]);
+  result.extend_from_slice(&0x0000_019cu32.to_le_bytes());
+  result.extend_from_slice(&[0xaa; 16]);
+  result.extend(labels("contoso.com"));
+  result.push(0);
+  result.extend_from_slice(&[0xc0, 24]);
+  result.extend(labels("dc1"));
+  result.extend_from_slice(&[0xc0, 24]);
+  result.extend(labels("CONTOSO"));
+  result.push(0);
+  result.extend(labels("DC1"));
+  result.push(0);
+  result.push(0);
+  let site = result.len() as u8;
+  result.extend(labels("Default-First-Site-Name"));
+  result.push(0);
+  result.extend_from_slice(&[0xc0, site]);
+  result.extend_from_slice(&6u32.to_le_bytes());
+  result.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+  result
+}
+
+fn search_result_entry(value: &[u8]) -> Vec<u8>
+{
+  let attribute = tlv(0x30, &[tlv(0x04, b"netlogon"), tlv(0x31, &tlv(0x04, value))].concat());
+  let entry = tlv(0x64, &[tlv(0x04, b""), tlv(0x30, &attribute)].concat());
+  let done = tlv(0x30, &[tlv(0x02, &[1]), tlv(0x65, &[tlv(0x0a, &[0]), tlv(0x04, b""), tlv(0x04, b"")].concat())].concat());
+  [tlv(0x30, &[tlv(0x02, &[1]), entry].concat()), done].concat()
+}
+
+#[test]
+fn weighted_order_priority()
+{
+  let records = vec![record(10, 0, "c"), record(0, 50, "a"), record(20, 5, "d"), record(0, 50, "b")];
+  for seed in 0..32
+  {
+    let ordered = weighted_order(records.clone(), &mut StdRng::seed_from_u64(seed));
+    let targets: Vec<&str> = ordered.iter().map(|record| record.target.as_str()).collect();
+    assert!(targets == ["a", "b", "c", "d"] || targets == ["b", "a", "c", "d"]);
+  }
+}
+
+#[test]
+fn weighted_order_weights()
+{
+  let records = vec![record(0, 0, "never"), record(0, 90, "heavy"), record(0, 10, "light")];
+  let mut rng = StdRng::seed_from_u64(2782);
+  let (mut heavy, mut never) = (0, 0);
+  for _ in 0..1000
+  {
+    let ordered = weighted_order(records.clone(), &mut rng);
+    assert_eq!(ordered.len(), 3);
+    match ordered[0].target.as_str()
+    {
+      "heavy" => heavy += 1,
+      "never" => never += 1,
+      _ => ()
+    }
+  }
+  assert!((850..=950).contains(&heavy), "heavy was first {} times", heavy);
+  assert!(never < 50, "never was first {} times", never);
+  assert!(weighted_order(vec![], &mut rng).is_empty());
+  assert_eq!(weighted_order(vec![record(0, 0, "only")], &mut rng), vec![record(0, 0, "only")]);
+}
+
+#[test]
+fn netlogon_response()
+{
+  let response = NetlogonResponse::new(&netlogon()).expect("failed to parse netlogon response");
+  assert_eq!(response, NetlogonResponse
+  {
+    flags: DsFlags::GC | DsFlags::LDAP | DsFlags::DS | DsFlags::CLOSEST | DsFlags::WRITABLE,
+    dns_forest_name: "contoso.com".to_owned(),
+    dns_host_name: "dc1.contoso.com".to_owned(),
+    dc_site_name: "Default-First-Site-Name".to_owned(),
+    client_site_name: "Default-First-Site-Name".to_owned()
+  });
+  assert_eq!(ping_response(&search_result_entry(&netlogon())).expect("failed to parse ping response"), response);
+}
+
+#[test]
+fn netlogon_response_malformed()
+{
+  let netlogon = netlogon();
+  for length in 0..netlogon.len() - 8
+  {
+    assert!(NetlogonResponse::new(&netlogon[..length]).is_err(), "truncated to {}", length);
+  }
+  let mut wrong_opcode = netlogon.clone();
+  wrong_opcode[0] = 19;
+  assert!(matches!(NetlogonResponse::new(&wrong_opcode), Err(LdapError::BadNetlogonResponse(_))));
+  // a name pointing at itself
+  let mut looped = netlogon.clone();
+  looped.truncate(24);
+  looped.extend_from_slice(&[0xc0, 24]);
+  assert!(NetlogonResponse::new(&looped).is_err());
+  let mut oversized = search_result_entry(&netlogon);
+  oversized[1] = 0x84;
+  assert!(ping_response(&oversized).is_err());
+  assert!(ping_response(&[]).is_err());
+}
+
+#[test]
+fn ping_request_encoding()
+{
+  let request = ping_request(0x80, "contoso.com");
+  // sequence, four octet length, then the message id with a leading zero to keep it positive
+  assert_eq!(&request[..9], &[0x30, 0x84, 0, 0, 0, (request.len() - 6) as u8, 0x02, 0x84, 0]);
+  assert_eq!(&request[9..14], &[0, 0, 2, 0x00, 0x80]);
+  assert_eq!(request[14], 0x63);
+  let contains = |needle: &[u8]| request.windows(needle.len()).any(|window| window == needle);
+  assert!(contains(b"DnsDomain"));
+  assert!(contains(b"contoso.com"));
+  assert!(contains(&[b'N', b't', b'V', b'e', b'r', 0x04, 0x84, 0, 0, 0, 4, 6, 0, 0, 0]));
+  assert!(contains(b"Netlogon"));
+}
+
+#[test]
+fn static_service_locator()
+{
+  let mut locator = StaticServiceLocator::default();
+  locator
+    .add_service("_gc._tcp.contoso.com.", record(0, 100, "dc1.contoso.com."))
+    .add_host("DC1.contoso.com", IpAddr::V4(Ipv4Addr::LOCALHOST));
+  assert_eq!(locator.srv_lookup("_gc._tcp.CONTOSO.com").expect("lookup failed"), vec![record(0, 100, "dc1.contoso.com.")]);
+  assert_eq!(locator.ip_lookup("dc1.contoso.com.").expect("lookup failed"), vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+  assert!(locator.srv_lookup("_gc._tcp.fabrikam.com").expect("lookup failed").is_empty());
+  assert!(locator.ip_lookup("dc2.contoso.com").expect("lookup failed").is_empty());
+}
+
+#[test]
+fn candidates_without_site()
+{
+  // no addresses, so nothing can be pinged and the site is unknown
+  let mut locator = StaticServiceLocator::default();
+  locator
+    .add_service("_gc._tcp.contoso.com", record(10, 0, "dc2.contoso.com."))
+    .add_service("_gc._tcp.contoso.com", record(0, 0, "dc1.contoso.com."));
+  let locator = Locator::new(Arc::new(locator));
+  let candidates = locator.candidates("contoso.com", &LdapConnectionOptions::default());
+  let hosts: Vec<(&str, u16)> = candidates.iter().map(|candidate| (candidate.host_name.as_str(), candidate.port)).collect();
+  assert_eq!(hosts, [("dc1.contoso.com", 3268), ("dc2.contoso.com", 3268)]);
+
+  let options = LdapConnectionOptionsBuilder::default().tls(true).build().expect("failed to build options");
+  assert!(locator.candidates("contoso.com", &options).iter().all(|candidate| candidate.port == 3269));
+  assert!(locator.candidates("fabrikam.com", &options).is_empty());
+}
+
+#[test]
+fn candidates_in_site()
+{
+  // answers a single ldap ping, placing the client in Default-First-Site-Name
+  let responder = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).expect("failed to bind responder");
+  let port = responder.local_addr().expect("responder has no address").port();
+  let answer = thread::spawn(move ||
+  {
+    let mut buffer = vec![0; 65536];
+    let (length, peer) = responder.recv_from(&mut buffer).expect("no ldap ping");
+    responder.send_to(&search_result_entry(&netlogon()), peer).expect("failed to answer ldap ping");
+    buffer.truncate(length);
+    buffer
+  });
+
+  let mut locator = StaticServiceLocator::default();
+  locator
+    .add_service("_gc._tcp.contoso.com", record(0, 0, "dc2.contoso.com."))
+    .add_service("_gc._tcp.Default-First-Site-Name._sites.contoso.com", record(0, 0, "dc1.contoso.com."))
+    .add_host("dc2.contoso.com", IpAddr::V4(Ipv4Addr::LOCALHOST));
+  let locator = Locator::new(Arc::new(locator)).with_ping_port(port);
+  let candidates = locator.candidates("contoso.com", &LdapConnectionOptions::default());
+  let hosts: Vec<&str> = candidates.iter().map(|candidate| candidate.host_name.as_str()).collect();
+  assert_eq!(hosts, ["dc1.contoso.com", "dc2.contoso.com"]);
+
+  let request = answer.join().expect("responder panicked");
+  assert!(request.windows(b"contoso.com".len()).any(|window| window == b"contoso.com"));
+}
+
+#[test]
+fn selection_cache()
+{
+  let plain = Selection { realm: "cache.contoso.com".to_owned(), scheme: "ldap", port: 3268, locator: "dns []".to_owned() };
+  let tls = Selection { scheme: "ldaps", port: 3269, ..plain.clone() };
+  let other_locator = Selection { locator: "dns [192.0.2.1:53]".to_owned(), ..plain.clone() };
+  cache(&plain, DomainController { host_name: "dc1.contoso.com".to_owned(), port: 3268 });
+  assert_eq!(cached(&plain), Some(DomainController { host_name: "dc1.contoso.com".to_owned(), port: 3268 }));
+  assert_eq!(cached(&tls), None);
+  assert_eq!(cached(&other_locator), None);
+  cache(&plain, DomainController { host_name: "dc2.contoso.com".to_owned(), port: 3268 });
+  assert_eq!(cached(&plain).map(|selected| selected.host_name), Some("dc2.contoso.com".to_owned()));
+}
+
+#[test]
+fn referral_targets()
+{
+  let base = "CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=com";
+  assert_eq!(
+    referral_target("ldap://child.contoso.com/DC=child,DC=contoso,DC=com", base, false),
+    Some(("child.contoso.com".to_owned(), 389, "DC=child,DC=contoso,DC=com".to_owned())));
+  assert_eq!(
+    referral_target("ldap://dc1.child.contoso.com:3268/CN=Some%20User,CN=Users,DC=child,DC=contoso,DC=com", base, true),
+    Some(("dc1.child.contoso.com".to_owned(), 3268, "CN=Some User,CN=Users,DC=child,DC=contoso,DC=com".to_owned())));
+  assert_eq!(referral_target("ldap://dc1.contoso.com/", base, true), Some(("dc1.contoso.com".to_owned(), 636, base.to_owned())));
+  assert_eq!(referral_target("not a url", base, false), None);
+  assert_eq!(referral_target("ldap:///DC=contoso,DC=com", base, false), None);
+}
+
+#[test]
+fn enrollment_server_list()
+{
+  let url = |url: &str| Url::parse(url).expect("bad url");
+  let values = vec![
+    "1\n2\n0\nhttps://ca.contoso.com/Contoso-CA_CES_Kerberos/service.svc/CES".to_owned(),
+    "2\r\n4\r\n1\r\nhttps://ca.contoso.com/Contoso-CA_CES_UsernamePassword/service.svc/CES\r\n3\r\n8\r\n0\r\nhttps://ca.contoso.com/Contoso-CA_CES_Certificate/service.svc/CES\r\n".to_owned(),
+    "x\n2\n0\nhttps://ca.contoso.com/bad-priority".to_owned(),
+    "1\n3\n0\nhttps://ca.contoso.com/bad-authentication".to_owned(),
+    "1\n2\n0\nnot a url\n4\n2\n0\nhttps://ca.contoso.com/after-bad-url\n5".to_owned()
+  ];
+  assert_eq!(enrollment_servers(&values), vec![
+    HttpsEndpoint::new(ClientAuthentication::TransportKerberos, false, url("https://ca.contoso.com/Contoso-CA_CES_Kerberos/service.svc/CES"), 1),
+    HttpsEndpoint::new(ClientAuthentication::SoapUsernamePassword, true, url("https://ca.contoso.com/Contoso-CA_CES_UsernamePassword/service.svc/CES"), 2),
+    HttpsEndpoint::new(ClientAuthentication::CmsSignature, false, url("https://ca.contoso.com/Contoso-CA_CES_Certificate/service.svc/CES"), 3),
+    HttpsEndpoint::new(ClientAuthentication::TransportKerberos, false, url("https://ca.contoso.com/after-bad-url"), 4)
+  ]);
+  assert!(enrollment_servers(&[]).is_empty());
+}
+
+#[test]
+fn ldap_policy_id()
+{
+  let forest_oid = "1.3.6.1.4.1.311.21.8.3800100.3166153.13323660.9808540.8334961.78";
+  let object_guid = [0x11, 0x70, 0x3c, 0x08, 0x0a, 0x1d, 0x55, 0x48, 0x88, 0x5d, 0xac, 0x94, 0x51, 0x84, 0x65, 0x8c];
+  let id = policy_id(&object_guid).expect("no policy id");
+  assert_eq!(id, "{083C7011-1D0A-4855-885D-AC945184658C}");
+  assert_eq!(policy_id(&object_guid[1..]), None);
+
+  let policy = Policy::new_inner(id, vec![], vec![], vec![]).with_forest_oid(forest_oid.to_owned());
+  assert!(policy.has_id("{083C7011-1D0A-4855-885D-AC945184658C}"));
+  assert!(policy.has_id("083c7011-1d0a-4855-885d-ac945184658c"));
+  assert!(policy.has_id(forest_oid));
+  assert!(!policy.has_id("1.3.6.1.4.1.311.21.8.3800100"));
+  assert!(!policy.has_id("{00000000-1D0A-4855-885D-AC945184658C}"));
+}
+
+fn template_entry(ra_application_policies: &[&str]) -> SearchEntry
+{
+  SearchEntry
+  {
+    dn: "CN=Enrollment Agent,CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=com".to_owned(),
+    attrs: HashMap::from([("msPKI-RA-Application-Policies".to_owned(), ra_application_policies.iter().map(|value| (*value).to_owned()).collect())]),
+    bin_attrs: HashMap::new()
+  }
+}
+
+#[test]
+fn ra_application_policy_forms()
+{
+  let oid = |oid: &str| Oid::<Bytes>::from_str(oid).expect("bad oid");
+  let version_2 = template_entry(&["1.3.6.1.4.1.311.20.2.1", "1.3.6.1.4.1.311.10.3.1"]);
+  assert_eq!(ra_application_policies(&version_2), vec![oid("1.3.6.1.4.1.311.20.2.1"), oid("1.3.6.1.4.1.311.10.3.1")]);
+
+  let version_4 = template_entry(&["msPKI-Asymmetric-Algorithm`PZPWSTR`RSA`msPKI-Hash-Algorithm`PZPWSTR`SHA256`msPKI-Key-Usage`DWORD`16777215`msPKI-Symmetric-Algorithm`PZPWSTR`AES`msPKI-Symmetric-Key-Length`DWORD`192`msPKI-RA-Application-Policies`PZPWSTR`1.3.6.1.4.1.311.20.2.1`"]);
+  assert_eq!(ra_application_policies(&version_4), vec![oid("1.3.6.1.4.1.311.20.2.1")]);
+  let archival = key_archival_attributes(&version_4);
+  assert_eq!(archival.symmetric_algorithm, Some(oid("2.16.840.1.101.3.4.1.22")));
+  assert_eq!(archival.symmetric_key_length, 192);
+
+  assert!(ra_application_policies(&template_entry(&["msPKI-Asymmetric-Algorithm`PZPWSTR`RSA`"])).is_empty());
+}
+
+#[test]
+fn version_4_agent_requirements()
+{
+  let mut entry = template_entry(&["msPKI-Asymmetric-Algorithm`PZPWSTR`RSA`msPKI-RA-Application-Policies`PZPWSTR`1.3.6.1.4.1.311.20.2.1`"]);
+  entry.attrs.insert("msPKI-RA-Signature".to_owned(), vec!["1".to_owned()]);
+  let requirements = ra_requirements(&entry);
+  assert_eq!(requirements.signatures, 1);
+
+  // client authentication, then the enrollment agent
+  let agent = |usage: &[u8]|
+  {
+    let mut builder = X509CertificateBuilder::new(KeyAlgorithm::Ed25519);
+    builder.subject().append_common_name_utf8_string("agent").expect("error setting subject");
+    builder.add_extension_der_data(Oid(Bytes::from_static(ID_EXTENDED_KEY_USAGE)), false, [&[0x30, usage.len() as u8][..], usage].concat());
+    builder.create_with_random_keypair().expect("failed to generate certificate").0
+  };
+  let client_authentication = agent(&[0x06, 0x08, 43, 6, 1, 5, 5, 7, 3, 2]);
+  match check_agent("Enrollment Agent", &requirements, &client_authentication)
+  {
+    Err(AdcsError::AgentPolicyMissing(policy, template)) =>
+    {
+      assert_eq!(policy, "1.3.6.1.4.1.311.20.2.1");
+      assert_eq!(template, "Enrollment Agent");
+    },
+    _ => panic!("agent without the enrollment agent policy was accepted")
+  }
+  let enrollment_agent = agent(&[0x06, 0x0a, 43, 6, 1, 4, 1, 130, 55, 20, 2, 1]);
+  assert!(check_agent("Enrollment Agent", &requirements, &enrollment_agent).is_ok());
+}
+
+#[test]
+fn security_descriptor_control()
+{
+  let attributes = |attributes: &[&str]| attributes.iter().map(|attribute| (*attribute).to_owned()).collect::<Vec<_>>();
+  let controls = search_controls(&attributes(&["cn", "ntsecuritydescriptor"]), vec![]);
+  assert_eq!(controls.len(), 2);
+  assert_eq!(controls[0].ctype, "1.2.840.113556.1.4.801");
+  assert!(!controls[0].crit);
+
+  let controls = search_controls(&attributes(&["cn", "msPKI-Cert-Template-OID"]), vec![]);
+  assert_eq!(controls.len(), 1);
+  assert_eq!(controls[0].ctype, "1.2.840.113556.1.4.319");
+}
@@ -9,24 +9,45 @@ mod cmc;
 mod soap;
 mod soap_operations;
 mod client;
+mod template;
+mod csr;
+mod certificate;
+mod context;
+mod service;
+mod group_policy;
 
 #[cfg(feature = "policy_ldap")]
 mod ldap_client;
-#[cfg(feature = "policy_https")]
+#[cfg(any(feature = "policy_https", feature = "enrollment_https"))]
 mod http_client;
+#[cfg(feature = "enrollment_rpc")]
+mod rpc_client;
 
 use num_derive::FromPrimitive;
 use client::ConfigurationError;
 use soap::SoapHttpError;
 use std::{fmt::{Display, Formatter}, cmp::Ordering};
 use ldap::LdapError;
+#[cfg(feature = "enrollment_rpc")]
+use rpc_client::RpcError;
 use thiserror::Error;
 use x509_certificate::X509Certificate;
 
 pub use reqwest::Url;
 pub use client::EnrollmentResponse;
+pub use client::{PendingRequest, RequestLocation, Transport};
 pub use client::CertificateTemplate;
 pub use client::Policy;
+pub use client::CertificateServicesClient;
+pub use client::EnrollmentService;
+pub use csr::{CertificationRequestBuilder, Identity, CsrError};
+pub use context::EnrollmentContext;
+pub use ldap::{LdapConnectionOptions, LdapConnectionOptionsBuilder, LdapBind};
+pub use group_policy::{policy_servers, GroupPolicyError};
+pub use service::{ServiceLocator, ServiceLocatorError, SrvRecord, DnsServiceLocator, StaticServiceLocator};
+pub use template::{SubjectNameFlags, EnrollmentFlags, PrivateKeyFlags, GeneralFlags, KeySpec, KeyRequirements, RaRequirements, KeyArchivalAttributes};
+pub use cmc::archival::{ArchivalError, SymmetricAlgorithm};
+pub use sddl::{SDDL, SDDLError, SID, ACL, ACE, ACEType, ACEFlags, AccessMask, AccessObjectFlags, ControlFlags, Access, AccessObject, CallbackAccess, CallbackAccessObject, AccessDecision, ClaimsContext, ClaimValue, Condition, ResourceAttribute, AttributeSource, UnaryOperator, BinaryOperator};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NamedCertificate
@@ -53,9 +74,16 @@ pub enum AdcsError
   #[error("ldap error: {0}")]
   Ldap(#[from] LdapError),
 
+  #[error("group policy error: {0}")]
+  GroupPolicy(#[from] GroupPolicyError),
+
   #[error("soap error: {0}")]
   Soap(#[from] SoapHttpError),
 
+  #[cfg(feature = "enrollment_rpc")]
+  #[error("rpc error: {0}")]
+  Rpc(#[from] RpcError),
+
   #[error("policy id not found: {0}")]
   PolicyIdNotFound(String),
 
@@ -65,8 +93,29 @@ pub enum AdcsError
   #[error("no such template {0}")]
   TemplateNotFound(String),
 
+  #[error("no enrollment endpoint accepted the request for template {0}")]
+  NoEnrollmentEndpoint(String),
+
+  #[error("could not retrieve the exchange certificate of {0}")]
+  NoExchangeCertificate(String),
+
+  #[error("the enrollment agent certificate lacks application policy {0} required by template {1}")]
+  AgentPolicyMissing(String, String),
+
+  #[error("template {0} requires {1} enrollment agent signatures, but only one can be supplied")]
+  TooManyAgentSignatures(String, u32),
+
   #[error("error in client configuration: {0}")]
-  ConfigurationError(#[from] ConfigurationError)
+  ConfigurationError(#[from] ConfigurationError),
+
+  #[error("error decoding response: {0}")]
+  Decode(#[from] DecodeError),
+
+  #[error("error encoding request: {0}")]
+  Encode(#[from] EncodeError),
+
+  #[error("error building certification request: {0}")]
+  Csr(#[from] CsrError)
 }
 
 pub type Result<T> = std::result::Result<T, AdcsError>;
@@ -91,6 +140,14 @@ pub struct PolicyEndpoint
   cost: u64
 }
 
+impl PolicyEndpoint
+{
+  pub fn new(uri: Url, client_authentication: ClientAuthentication, cost: u64) -> Self
+  {
+    Self { uri, client_authentication, cost }
+  }
+}
+
 impl Display for PolicyEndpoint
 {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
@@ -145,12 +202,30 @@ pub enum DecodeError
   BadDer(#[from] x509_certificate::X509CertificateError),
 
   #[error("bad cms: {0}")]
-  BadCms(#[from] cryptographic_message_syntax::CmsError)
+  BadCms(#[from] cryptographic_message_syntax::CmsError),
+
+  #[error("response contained no tokens")]
+  EmptyResponse,
+
+  #[error("bad request id: {0}")]
+  BadRequestId(String),
+
+  #[error("response has no status for body part {0}")]
+  NoStatus(u32)
 }
 
 #[derive(Error, Debug)]
 pub enum EncodeError
 {
   #[error("bad cms: {0}")]
-  BadCms(#[from] cryptographic_message_syntax::CmsError)
+  BadCms(#[from] cryptographic_message_syntax::CmsError),
+
+  #[error("error encoding pki data: {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("error archiving private key: {0}")]
+  Archival(#[from] ArchivalError),
+
+  #[error("cmc request has no signer")]
+  NoSigner
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+use libdcerpc::{CertPassage, CertificateServerResponse, DWFlags, Protocol};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use thiserror::Error;
+use tracing::{event, Level, instrument};
+use x509_certificate::X509Certificate;
+
+use crate::{client::{EnrollmentClient, EnrollmentResponse, EnrollmentService, Policy, PendingRequest, RequestLocation, Transport}, cmc::CmcResponse, AdcsError, DecodeError};
+
+#[derive(Error, Debug)]
+pub enum RpcError
+{
+  #[error("error binding to {0}: {1}")]
+  Bind(String, String),
+
+  #[error("CertServerRequest to {0} failed: {1}")]
+  Request(String, String)
+}
+
+// MS-ICPR 3.2.4.1.1
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+enum Disposition
+{
+  Incomplete = 0,
+  Error = 1,
+  Denied = 2,
+  Issued = 3,
+  IssuedOutOfBand = 4,
+  UnderSubmission = 5
+}
+
+pub struct RpcEnrollmentClient;
+
+impl EnrollmentClient for RpcEnrollmentClient
+{
+  fn submit(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    let template = policy.get_template_by_name(template).ok_or_else(|| AdcsError::TemplateNotFound(template.to_owned()))?;
+    for enrollment_service in policy.get_enrollment_services_for_template(template)?
+    {
+      if let Some(endpoint) = enrollment_service.find_rpc_endpoint()
+      {
+        let authority = &enrollment_service.get_certificate().nickname;
+        let attributes = format!("CertificateTemplate:{}", template.get_name());
+        match cert_server_request(endpoint, authority, DWFlags::REQUEST_TYPE_CMC | DWFlags::CMC_FULL_PKI_RESPONSE, None, &attributes, &request)
+        {
+          Ok(response) => return Ok(response.located(authority, Transport::Rpc(endpoint.to_owned()), template.get_name())),
+          Err(err) => event!(Level::WARN, "error submitting to rpc endpoint {}: {}.  skipping", endpoint, err)
+        }
+      }
+    }
+    Err(AdcsError::NoEnrollmentEndpoint(template.get_name().to_owned()))
+  }
+
+  // MS-ICPR has no separate renewal call; the CA identifies a renewal by the signing certificate
+  fn renew(&self, policy: &Policy, request: Vec<u8>, template: &str) -> Result<EnrollmentResponse, AdcsError>
+  {
+    self.submit(policy, request, template)
+  }
+
+  // MS-ICPR 3.2.4.1.1 - pending requests are retrieved by request id with an empty request body
+  fn poll(&self, request_id: u32, location: &RequestLocation) -> Result<EnrollmentResponse, AdcsError>
+  {
+    match &location.transport
+    {
+      Transport::Rpc(endpoint) =>
+      {
+        let response = cert_server_request(endpoint, &location.authority, DWFlags::RETRIEVE_PENDING | DWFlags::CMC_FULL_PKI_RESPONSE, Some(request_id), "", &[])?;
+        Ok(response.located(&location.authority, location.transport.clone(), &location.template))
+      },
+      Transport::Https(_) => Err(AdcsError::NoEnrollmentEndpoint(location.template.clone()))
+    }
+  }
+
+  fn handles(&self, transport: &Transport) -> bool
+  {
+    matches!(transport, Transport::Rpc(_))
+  }
+
+  // MS-WCCE - the CAExchange pseudo template with an empty request returns the exchange certificate
+  fn exchange_certificate(&self, enrollment_service: &EnrollmentService) -> Result<X509Certificate, AdcsError>
+  {
+    let authority = &enrollment_service.get_certificate().nickname;
+    if let Some(endpoint) = enrollment_service.find_rpc_endpoint()
+    {
+      match cert_server_request(endpoint, authority, DWFlags::empty(), None, "CertificateTemplate:CAExchange", &[])
+      {
+        Ok(EnrollmentResponse::Issued { entity, .. }) => return Ok(entity),
+        Ok(_) => event!(Level::WARN, "rpc endpoint {} did not return an exchange certificate.  skipping", endpoint),
+        Err(err) => event!(Level::WARN, "error retrieving exchange certificate from rpc endpoint {}: {}.  skipping", endpoint, err)
+      }
+    }
+    Err(AdcsError::NoExchangeCertificate(authority.to_owned()))
+  }
+}
+
+#[instrument(skip(request))]
+fn cert_server_request(endpoint: &str, authority: &str, flags: DWFlags, request_id: Option<u32>, attributes: &str, request: &[u8]) -> Result<EnrollmentResponse, AdcsError>
+{
+  let spn = format!("host/{}", endpoint);
+  event!(Level::TRACE, "trying to connect to rpc endpoint {} with spn {}", endpoint, spn);
+  let mut client = CertPassage::new(Protocol::Tcp, endpoint, &spn)
+    .map_err(|err| RpcError::Bind(endpoint.to_owned(), err.to_string()))?;
+  let response = client.cert_server_request(flags, authority, request_id, attributes, request)
+    .map_err(|err| RpcError::Request(endpoint.to_owned(), err.to_string()))?;
+  Ok(decode_response(response)?)
+}
+
+fn decode_response(response: CertificateServerResponse) -> Result<EnrollmentResponse, DecodeError>
+{
+  let disposition_message = response.disposition_message.unwrap_or_default();
+  match response.disposition.and_then(Disposition::from_u32)
+  {
+    Some(Disposition::Issued) | Some(Disposition::IssuedOutOfBand) => match (response.entity_certificate, response.certificate_chain)
+    {
+      (Some(entity), chain) =>
+      {
+        let entity = X509Certificate::from_der(entity)?;
+        let chain = match chain
+        {
+          Some(chain) => CmcResponse::try_from(chain)?.into_certificates(),
+          None => vec![]
+        };
+        let chain = chain
+          .into_iter()
+          .filter(|certificate| *certificate != entity)
+          .collect();
+        Ok(EnrollmentResponse::Issued { entity, chain })
+      },
+      (None, Some(chain)) => CmcResponse::try_from(chain)?.try_into(),
+      (None, None) => Err(DecodeError::EmptyResponse)
+    },
+    Some(Disposition::UnderSubmission) => match response.request_id
+    {
+      Some(request_id) => Ok(EnrollmentResponse::Pending(PendingRequest::new(request_id))),
+      None => Err(DecodeError::BadRequestId(disposition_message))
+    },
+    Some(disposition) => Ok(EnrollmentResponse::Rejected(format!("rejected ({:?}): {}", disposition, disposition_message))),
+    None => Ok(EnrollmentResponse::Rejected(format!("rejected ({:?}): {}", response.disposition, disposition_message)))
+  }
+}
@@ -0,0 +1,109 @@
+use base64::{engine::general_purpose, Engine};
+use libdcerpc::CertificateServerResponse;
+
+use crate::{cmc::CmcResponse, EnrollmentResponse, DecodeError};
+
+use super::decode_response;
+
+fn issued_chain() -> Vec<u8>
+{
+  general_purpose::STANDARD.decode(include_str!("../../cmc/tests/response_issued.txt")).expect("error base64 decoding known good cmc")
+}
+
+fn issued_entity() -> Vec<u8>
+{
+  match EnrollmentResponse::try_from(CmcResponse::try_from(issued_chain()).expect("error decoding known good cmc")).expect("error interpreting known good cmc")
+  {
+    EnrollmentResponse::Issued { entity, .. } => entity.encode_der().expect("failed to encode entity certificate"),
+    _ => panic!("known good cmc was not issued")
+  }
+}
+
+fn response(disposition: Option<u32>, request_id: Option<u32>, entity_certificate: Option<Vec<u8>>, certificate_chain: Option<Vec<u8>>, disposition_message: Option<&str>) -> CertificateServerResponse
+{
+  CertificateServerResponse
+  {
+    request_id,
+    disposition,
+    certificate_chain,
+    entity_certificate,
+    disposition_message: disposition_message.map(str::to_owned)
+  }
+}
+
+#[test]
+fn decode_issued()
+{
+  for disposition in [3, 4]
+  {
+    match decode_response(response(Some(disposition), Some(43), Some(issued_entity()), Some(issued_chain()), Some("Issued"))).expect("error decoding issued response")
+    {
+      EnrollmentResponse::Issued { entity, chain } =>
+      {
+        assert_eq!(entity.subject_common_name(), Some("host.contoso.com".to_owned()));
+        assert_eq!(chain.len(), 1);
+        assert!(!chain.contains(&entity));
+      },
+      _ => panic!("issued response was not issued")
+    }
+  }
+}
+
+#[test]
+fn decode_issued_without_chain()
+{
+  match decode_response(response(Some(3), Some(43), Some(issued_entity()), None, None)).expect("error decoding issued response")
+  {
+    EnrollmentResponse::Issued { entity, chain } =>
+    {
+      assert_eq!(entity.subject_common_name(), Some("host.contoso.com".to_owned()));
+      assert!(chain.is_empty());
+    },
+    _ => panic!("issued response was not issued")
+  }
+  match decode_response(response(Some(3), Some(43), None, Some(issued_chain()), None)).expect("error decoding issued response")
+  {
+    EnrollmentResponse::Issued { entity, .. } => assert_eq!(entity.subject_common_name(), Some("host.contoso.com".to_owned())),
+    _ => panic!("issued response was not issued")
+  }
+  match decode_response(response(Some(3), Some(43), None, None, None))
+  {
+    Err(DecodeError::EmptyResponse) => (),
+    _ => panic!("issued response without certificates was accepted")
+  }
+}
+
+#[test]
+fn decode_under_submission()
+{
+  match decode_response(response(Some(5), Some(43), None, None, Some("Taken Under Submission"))).expect("error decoding pending response")
+  {
+    EnrollmentResponse::Pending(pending) => assert_eq!(pending.request_id(), 43),
+    _ => panic!("pending response was not pending")
+  }
+  match decode_response(response(Some(5), None, None, None, Some("Taken Under Submission")))
+  {
+    Err(DecodeError::BadRequestId(message)) => assert_eq!(message, "Taken Under Submission"),
+    _ => panic!("pending response without a request id was accepted")
+  }
+}
+
+#[test]
+fn decode_denied()
+{
+  match decode_response(response(Some(2), Some(43), None, None, Some("Denied by Policy Module"))).expect("error decoding denied response")
+  {
+    EnrollmentResponse::Rejected(message) => assert_eq!(message, "rejected (Denied): Denied by Policy Module"),
+    _ => panic!("denied response was not rejected")
+  }
+}
+
+#[test]
+fn decode_unknown_disposition()
+{
+  match decode_response(response(Some(9), Some(43), Some(issued_entity()), None, Some("Unknown"))).expect("error decoding unknown response")
+  {
+    EnrollmentResponse::Rejected(message) => assert_eq!(message, "rejected (Some(9)): Unknown"),
+    _ => panic!("unknown disposition was not rejected")
+  }
+}
@@ -0,0 +1,162 @@
+use uuid::Uuid;
+
+use super::{SDDL, SID, ACL, ACE, ACEType, ACEFlags, AccessMask, AccessObject, ClaimsContext};
+
+// the outcome of an access check, along with the ace that decided it.  there is no deciding ace
+// when the dacl is absent or nothing was requested (allowed), or when the acl ran out before every
+// requested right was granted (an implicit deny)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AccessDecision<'a>
+{
+  Allowed(Option<&'a ACE>),
+  Denied(Option<&'a ACE>)
+}
+
+impl AccessDecision<'_>
+{
+  pub fn is_allowed(&self) -> bool
+  {
+    matches!(self, Self::Allowed(_))
+  }
+}
+
+impl AccessMask
+{
+  // the generic mapping for directory service objects
+  pub fn map_generic(&self) -> Self
+  {
+    let mut result = self.difference(Self::GENERIC_ALL | Self::GENERIC_EXECUTE | Self::GENERIC_WRITE | Self::GENERIC_READ);
+    if self.contains(Self::GENERIC_READ)
+    {
+      result |= Self::READ_CONTROL | Self::ADS_RIGHT_ACTRL_DS_LIST | Self::ADS_RIGHT_DS_READ_PROP | Self::ADS_RIGHT_DS_LIST_OBJECT;
+    }
+    if self.contains(Self::GENERIC_WRITE)
+    {
+      result |= Self::READ_CONTROL | Self::ADS_RIGHT_DS_SELF | Self::ADS_RIGHT_DS_WRITE_PROP;
+    }
+    if self.contains(Self::GENERIC_EXECUTE)
+    {
+      result |= Self::READ_CONTROL | Self::ADS_RIGHT_ACTRL_DS_LIST;
+    }
+    if self.contains(Self::GENERIC_ALL)
+    {
+      result |= Self::STANDARD_RIGHTS_REQUIRED | Self::from_bits_retain(0x0000_01FF);
+    }
+    result
+  }
+}
+
+impl AccessObject
+{
+  // an object ace without an object type covers every property, extended right and child class
+  fn applies_to(&self, object_type: Option<&Uuid>) -> bool
+  {
+    match self.object_type
+    {
+      None => true,
+      Some(ace_object_type) => object_type.map(|o| *o == ace_object_type).unwrap_or(false)
+    }
+  }
+}
+
+impl ACL
+{
+  // MS-DTYP 2.5.3.2 - aces are evaluated in order, so a deny only wins when it precedes the allows
+  // covering the same rights.  the inherited object type only governs inheritance, and plays no
+  // part in the check
+  pub fn access_check<'a, E>(&'a self, desired: AccessMask, object_type: Option<&Uuid>, does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<AccessDecision<'a>, E>
+  {
+    self.access_check_with_claims(desired, object_type, &ClaimsContext::default(), does_identify)
+  }
+
+  // callback aces apply when their condition holds.  an allow needs it to be true, while a deny
+  // applies unless it is false, so a condition over claims that are not at hand denies rather than
+  // grants
+  pub fn access_check_with_claims<'a, E>(&'a self, desired: AccessMask, object_type: Option<&Uuid>, claims: &ClaimsContext, mut does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<AccessDecision<'a>, E>
+  {
+    let mut remaining = desired.map_generic();
+    if remaining.is_empty()
+    {
+      return Ok(AccessDecision::Allowed(None));
+    }
+    for ace in &self.0
+    {
+      if ace.flags.contains(ACEFlags::INHERIT_ONLY)
+      {
+        continue;
+      }
+      let (allow, access_mask, subject) = match &ace.ace_type
+      {
+        ACEType::AccessAllowed(access) => (true, &access.access_mask, &access.subject),
+        ACEType::AccessDenied(access) => (false, &access.access_mask, &access.subject),
+        ACEType::AccessAllowedObject(object) if object.applies_to(object_type) => (true, &object.access_mask, &object.subject),
+        ACEType::AccessDeniedObject(object) if object.applies_to(object_type) => (false, &object.access_mask, &object.subject),
+        ACEType::AccessAllowedCallback(callback) => (true, &callback.access_mask, &callback.subject),
+        ACEType::AccessDeniedCallback(callback) => (false, &callback.access_mask, &callback.subject),
+        ACEType::AccessAllowedCallbackObject(callback) if callback.object.applies_to(object_type) => (true, &callback.object.access_mask, &callback.object.subject),
+        ACEType::AccessDeniedCallbackObject(callback) if callback.object.applies_to(object_type) => (false, &callback.object.access_mask, &callback.object.subject),
+        _ => continue
+      };
+      let access_mask = access_mask.map_generic();
+      if !access_mask.intersects(remaining.clone()) || !does_identify(subject)?
+      {
+        continue;
+      }
+      if ace.ace_type.application_data().is_some()
+      {
+        // application data which is not a valid condition can never be shown to hold
+        let condition = match ace.ace_type.condition()
+        {
+          Some(Ok(condition)) => condition.evaluate(claims, &mut does_identify)?,
+          _ => None
+        };
+        if condition != Some(true) && (allow || condition == Some(false))
+        {
+          continue;
+        }
+      }
+      if !allow
+      {
+        return Ok(AccessDecision::Denied(Some(ace)));
+      }
+      remaining.remove(access_mask);
+      if remaining.is_empty()
+      {
+        return Ok(AccessDecision::Allowed(Some(ace)));
+      }
+    }
+    Ok(AccessDecision::Denied(None))
+  }
+
+  pub fn has_object_permission<E>(&self, object_type: &Uuid, does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<bool, E>
+  {
+    Ok(self.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(object_type), does_identify)?.is_allowed())
+  }
+}
+
+impl SDDL
+{
+  // a descriptor without a dacl, or with a null dacl, grants everyone everything
+  pub fn access_check<'a, E>(&'a self, desired: AccessMask, object_type: Option<&Uuid>, does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<AccessDecision<'a>, E>
+  {
+    self.access_check_with_claims(desired, object_type, &ClaimsContext::default(), does_identify)
+  }
+
+  // the resource attributes in the sacl are added to the claims, unless the caller supplied them
+  pub fn access_check_with_claims<'a, E>(&'a self, desired: AccessMask, object_type: Option<&Uuid>, claims: &ClaimsContext, does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<AccessDecision<'a>, E>
+  {
+    match &self.dacl
+    {
+      Some(dacl) =>
+      {
+        let mut claims = claims.clone();
+        for attribute in self.resource_attributes()
+        {
+          claims.resource.entry(attribute.name).or_insert(attribute.values);
+        }
+        dacl.access_check_with_claims(desired, object_type, &claims, does_identify)
+      },
+      None => Ok(AccessDecision::Allowed(None))
+    }
+  }
+}
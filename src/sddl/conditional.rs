@@ -0,0 +1,640 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use super::{SDDL, SDDLError, SID, ACEType, clone_into_array, field, field_subslice_length, le_field_u16, le_field_u32};
+
+// MS-DTYP 2.4.4.17.4 - the application data of a callback ace holds a conditional expression when it
+// starts with this signature
+const CONDITIONAL_SIGNATURE: &[u8] = b"artx";
+pub(super) const MAXIMUM_DEPTH: usize = 64;
+
+// MS-DTYP 2.4.10.1
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_INT64: u16 = 0x0001;
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_UINT64: u16 = 0x0002;
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_STRING: u16 = 0x0003;
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_SID: u16 = 0x0005;
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_BOOLEAN: u16 = 0x0006;
+const CLAIM_SECURITY_ATTRIBUTE_TYPE_OCTET_STRING: u16 = 0x0010;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClaimValue
+{
+  Int64(i64),
+  Uint64(u64),
+  String(String),
+  Sid(SID),
+  Boolean(bool),
+  OctetString(Vec<u8>)
+}
+
+impl ClaimValue
+{
+  fn integer(&self) -> Option<i128>
+  {
+    match self
+    {
+      ClaimValue::Int64(value) => Some(*value as i128),
+      ClaimValue::Uint64(value) => Some(*value as i128),
+      ClaimValue::Boolean(value) => Some(*value as i128),
+      _ => None
+    }
+  }
+
+  // MS-DTYP 2.4.4.17.5 - strings compare without regard to case, sids only for equality
+  fn compare(&self, other: &Self) -> Option<Ordering>
+  {
+    match (self, other)
+    {
+      (ClaimValue::String(a), ClaimValue::String(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+      (ClaimValue::Sid(a), ClaimValue::Sid(b)) => Some(a.cmp(b)),
+      (ClaimValue::OctetString(a), ClaimValue::OctetString(b)) => Some(a.cmp(b)),
+      (a, b) => Some(a.integer()?.cmp(&b.integer()?))
+    }
+  }
+
+  fn equals(&self, other: &Self) -> bool
+  {
+    self.compare(other) == Some(Ordering::Equal)
+  }
+
+  fn is_true(&self) -> bool
+  {
+    match self
+    {
+      ClaimValue::String(value) => !value.is_empty(),
+      ClaimValue::Sid(_) => true,
+      ClaimValue::OctetString(value) => !value.is_empty(),
+      other => other.integer().map(|value| value != 0).unwrap_or(false)
+    }
+  }
+}
+
+// the claims of the principal being checked, keyed by claim name.  names are matched without regard
+// to case
+#[derive(Debug, Clone, Default)]
+pub struct ClaimsContext
+{
+  pub user: HashMap<String, Vec<ClaimValue>>,
+  pub device: HashMap<String, Vec<ClaimValue>>,
+  pub local: HashMap<String, Vec<ClaimValue>>,
+  pub resource: HashMap<String, Vec<ClaimValue>>,
+  // only known when checking a compound identity
+  pub device_groups: Option<Vec<SID>>
+}
+
+impl ClaimsContext
+{
+  fn attribute(&self, source: &AttributeSource, name: &str) -> Option<&'_ Vec<ClaimValue>>
+  {
+    let attributes = match source
+    {
+      AttributeSource::Local => &self.local,
+      AttributeSource::User => &self.user,
+      AttributeSource::Resource => &self.resource,
+      AttributeSource::Device => &self.device
+    };
+    attributes.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, values)| values)
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AttributeSource
+{
+  Local,
+  User,
+  Resource,
+  Device
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOperator
+{
+  Exists,
+  NotExists,
+  MemberOf,
+  DeviceMemberOf,
+  MemberOfAny,
+  DeviceMemberOfAny,
+  NotMemberOf,
+  NotDeviceMemberOf,
+  NotMemberOfAny,
+  NotDeviceMemberOfAny,
+  Not
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOperator
+{
+  Equals,
+  NotEquals,
+  LessThan,
+  LessThanOrEqual,
+  GreaterThan,
+  GreaterThanOrEqual,
+  Contains,
+  AnyOf,
+  NotContains,
+  NotAnyOf,
+  And,
+  Or
+}
+
+// MS-DTYP 2.4.4.17.6 - operator tokens
+const UNARY_OPERATORS: &[(u8, UnaryOperator)] =
+&[
+  (0x87, UnaryOperator::Exists),
+  (0x8d, UnaryOperator::NotExists),
+  (0x89, UnaryOperator::MemberOf),
+  (0x8a, UnaryOperator::DeviceMemberOf),
+  (0x8b, UnaryOperator::MemberOfAny),
+  (0x8c, UnaryOperator::DeviceMemberOfAny),
+  (0x90, UnaryOperator::NotMemberOf),
+  (0x91, UnaryOperator::NotDeviceMemberOf),
+  (0x92, UnaryOperator::NotMemberOfAny),
+  (0x93, UnaryOperator::NotDeviceMemberOfAny),
+  (0xa2, UnaryOperator::Not)
+];
+
+const BINARY_OPERATORS: &[(u8, BinaryOperator)] =
+&[
+  (0x80, BinaryOperator::Equals),
+  (0x81, BinaryOperator::NotEquals),
+  (0x82, BinaryOperator::LessThan),
+  (0x83, BinaryOperator::LessThanOrEqual),
+  (0x84, BinaryOperator::GreaterThan),
+  (0x85, BinaryOperator::GreaterThanOrEqual),
+  (0x86, BinaryOperator::Contains),
+  (0x88, BinaryOperator::AnyOf),
+  (0x8e, BinaryOperator::NotContains),
+  (0x8f, BinaryOperator::NotAnyOf),
+  (0xa0, BinaryOperator::And),
+  (0xa1, BinaryOperator::Or)
+];
+
+const ATTRIBUTE_SOURCES: &[(u8, AttributeSource)] =
+&[
+  (0xf8, AttributeSource::Local),
+  (0xf9, AttributeSource::User),
+  (0xfa, AttributeSource::Resource),
+  (0xfb, AttributeSource::Device)
+];
+
+// MS-DTYP 2.4.4.17 - the postfix bytecode rebuilt into a tree
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Condition
+{
+  Literal(ClaimValue),
+  Composite(Vec<Condition>),
+  Attribute(AttributeSource, String),
+  Unary(UnaryOperator, Box<Condition>),
+  Binary(BinaryOperator, Box<Condition>, Box<Condition>)
+}
+
+fn utf16(input: &[u8]) -> Result<String, SDDLError>
+{
+  if input.len() % 2 != 0
+  {
+    return Err(SDDLError::BadCondition("odd length unicode string".to_owned()));
+  }
+  char::decode_utf16(input.chunks_exact(2).map(|unit| u16::from_le_bytes(clone_into_array(unit))))
+    .collect::<Result<String, _>>()
+    .map_err(|err| SDDLError::BadCondition(err.to_string()))
+}
+
+// a null terminated unicode string at an offset into the structure
+fn utf16_terminated(input: &[u8], field_name: &str, offset: usize) -> Result<String, SDDLError>
+{
+  let mut end = offset;
+  while le_field_u16(input, field_name, end)? != 0
+  {
+    end += 2;
+  }
+  utf16(&input[offset..end])
+}
+
+fn utf16_bytes(value: &str) -> Vec<u8>
+{
+  value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn length_prefixed(output: &mut Vec<u8>, token: u8, value: &[u8])
+{
+  output.push(token);
+  output.extend_from_slice(&(value.len() as u32).to_le_bytes());
+  output.extend_from_slice(value);
+}
+
+fn le_field_u64(input: &[u8], field_name: &str, field_index: usize) -> Result<u64, SDDLError>
+{
+  Ok(u64::from_le_bytes(clone_into_array(field(input, field_name, field_index, 8)?)))
+}
+
+impl Condition
+{
+  pub fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    let body = input
+      .strip_prefix(CONDITIONAL_SIGNATURE)
+      .ok_or_else(|| SDDLError::BadCondition("missing artx signature".to_owned()))?;
+    let mut stack = Self::tokens(body, 0)?;
+    match (stack.pop(), stack.is_empty())
+    {
+      (Some((condition, _)), true) => Ok(condition),
+      _ => Err(SDDLError::BadCondition("expression does not reduce to a single value".to_owned()))
+    }
+  }
+
+  // the signature and the tree in postfix order, padded so the ace stays aligned
+  pub fn to_bytes(&self) -> Vec<u8>
+  {
+    let mut output = CONDITIONAL_SIGNATURE.to_vec();
+    self.encode(&mut output);
+    output.resize((output.len() + 3) & !3, 0);
+    output
+  }
+
+  // integers are written as signed 64 bit decimals without an explicit sign
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    match self
+    {
+      Self::Literal(ClaimValue::String(value)) => length_prefixed(output, 0x10, &utf16_bytes(value)),
+      Self::Literal(ClaimValue::OctetString(value)) => length_prefixed(output, 0x18, value),
+      Self::Literal(ClaimValue::Sid(sid)) => length_prefixed(output, 0x51, &sid.to_bytes()),
+      Self::Literal(value) =>
+      {
+        output.push(0x04);
+        output.extend_from_slice(&(value.integer().unwrap_or_default() as i64).to_le_bytes());
+        output.extend_from_slice(&[0x03, 0x02]);
+      },
+      Self::Composite(items) =>
+      {
+        let mut inner = Vec::new();
+        for item in items
+        {
+          item.encode(&mut inner);
+        }
+        length_prefixed(output, 0x50, &inner);
+      },
+      Self::Attribute(source, name) =>
+      {
+        let token = ATTRIBUTE_SOURCES.iter().find(|(_, candidate)| candidate == source).map(|(token, _)| *token).unwrap_or_default();
+        length_prefixed(output, token, &utf16_bytes(name));
+      },
+      Self::Unary(operator, operand) =>
+      {
+        operand.encode(output);
+        output.extend(UNARY_OPERATORS.iter().find(|(_, candidate)| candidate == operator).map(|(token, _)| *token));
+      },
+      Self::Binary(operator, lhs, rhs) =>
+      {
+        lhs.encode(output);
+        rhs.encode(output);
+        output.extend(BINARY_OPERATORS.iter().find(|(_, candidate)| candidate == operator).map(|(token, _)| *token));
+      }
+    }
+  }
+
+  // MS-DTYP 2.4.4.17.4 - operands are pushed, and operators replace their operands with the result.
+  // each entry carries the depth of its tree, which is bounded so hostile input cannot build a tree
+  // too deep to walk
+  fn tokens(body: &[u8], nesting: usize) -> Result<Vec<(Self, usize)>, SDDLError>
+  {
+    if nesting > MAXIMUM_DEPTH
+    {
+      return Err(SDDLError::BadCondition("expression is nested too deeply".to_owned()));
+    }
+    let mut stack = Vec::new();
+    let mut position = 0;
+    let pop = |stack: &mut Vec<(Self, usize)>| stack
+      .pop()
+      .map(|(condition, depth)| (Box::new(condition), depth))
+      .ok_or_else(|| SDDLError::BadCondition("operator is missing an operand".to_owned()));
+    while position < body.len()
+    {
+      let token = body[position];
+      position += 1;
+      let mut length_prefixed = || -> Result<&[u8], SDDLError>
+      {
+        let length = le_field_u32(body, "token_length", position)? as usize;
+        let value = field_subslice_length(body, "token_value", position + 4, length)?;
+        position += 4 + length;
+        Ok(value)
+      };
+      let unary = UNARY_OPERATORS.iter().find(|(code, _)| *code == token).map(|(_, operator)| *operator);
+      let binary = BINARY_OPERATORS.iter().find(|(code, _)| *code == token).map(|(_, operator)| *operator);
+      let source = ATTRIBUTE_SOURCES.iter().find(|(code, _)| *code == token).map(|(_, source)| *source);
+      let (condition, depth) = match (token, unary, binary, source)
+      {
+        (_, Some(operator), _, _) =>
+        {
+          let (operand, depth) = pop(&mut stack)?;
+          (Self::Unary(operator, operand), depth + 1)
+        },
+        (_, _, Some(operator), _) =>
+        {
+          let (rhs, rhs_depth) = pop(&mut stack)?;
+          let (lhs, lhs_depth) = pop(&mut stack)?;
+          (Self::Binary(operator, lhs, rhs), lhs_depth.max(rhs_depth) + 1)
+        },
+        // padding to the end of the ace
+        (0x00, _, _, _) => continue,
+        // the value is followed by a sign and a base, which only matter when rendering
+        (0x01..=0x04, _, _, _) =>
+        {
+          let value = le_field_u64(body, "integer", position)? as i64;
+          field(body, "integer_sign_base", position + 8, 2)?;
+          position += 10;
+          (Self::Literal(ClaimValue::Int64(value)), 1)
+        },
+        (0x10, _, _, _) => (Self::Literal(ClaimValue::String(utf16(length_prefixed()?)?)), 1),
+        (0x18, _, _, _) => (Self::Literal(ClaimValue::OctetString(length_prefixed()?.to_vec())), 1),
+        (0x50, _, _, _) =>
+        {
+          let items = Self::tokens(length_prefixed()?, nesting + 1)?;
+          let depth = items.iter().map(|(_, depth)| *depth).max().unwrap_or_default() + 1;
+          (Self::Composite(items.into_iter().map(|(item, _)| item).collect()), depth)
+        },
+        (0x51, _, _, _) => (Self::Literal(ClaimValue::Sid(SID::new(length_prefixed()?)?)), 1),
+        (_, _, _, Some(source)) => (Self::Attribute(source, utf16(length_prefixed()?)?), 1),
+        (token, _, _, _) => return Err(SDDLError::BadCondition(format!("unknown token 0x{:02x}", token)))
+      };
+      if nesting + depth > MAXIMUM_DEPTH
+      {
+        return Err(SDDLError::BadCondition("expression is nested too deeply".to_owned()));
+      }
+      stack.push((condition, depth));
+    }
+    Ok(stack)
+  }
+
+  // the values an operand stands for, or None when an attribute is missing or the result is unknown
+  fn values<E>(&self, context: &ClaimsContext, does_identify: &mut impl FnMut(&SID) -> Result<bool, E>) -> Result<Option<Vec<ClaimValue>>, E>
+  {
+    Ok(match self
+    {
+      Condition::Literal(value) => Some(vec![value.clone()]),
+      Condition::Composite(items) =>
+      {
+        let mut values = Vec::new();
+        for item in items
+        {
+          match item.values(context, does_identify)?
+          {
+            Some(item) => values.extend(item),
+            None => return Ok(None)
+          }
+        }
+        Some(values)
+      },
+      Condition::Attribute(source, name) => context.attribute(source, name).cloned(),
+      condition => condition.evaluate(context, does_identify)?.map(|result| vec![ClaimValue::Boolean(result)])
+    })
+  }
+
+  // MS-DTYP 2.4.4.17.5 - three valued logic, where None is unknown
+  pub fn evaluate<E>(&self, context: &ClaimsContext, does_identify: &mut impl FnMut(&SID) -> Result<bool, E>) -> Result<Option<bool>, E>
+  {
+    Ok(match self
+    {
+      Condition::Literal(value) => Some(value.is_true()),
+      Condition::Composite(_) => None,
+      Condition::Attribute(source, name) => match context.attribute(source, name).map(Vec::as_slice)
+      {
+        Some([value]) => Some(value.is_true()),
+        _ => None
+      },
+      Condition::Unary(UnaryOperator::Not, operand) => operand.evaluate(context, does_identify)?.map(|result| !result),
+      Condition::Unary(UnaryOperator::Exists, operand) => match operand.as_ref()
+      {
+        Condition::Attribute(source, name) => Some(context.attribute(source, name).is_some()),
+        _ => None
+      },
+      Condition::Unary(UnaryOperator::NotExists, operand) => match operand.as_ref()
+      {
+        Condition::Attribute(source, name) => Some(context.attribute(source, name).is_none()),
+        _ => None
+      },
+      Condition::Unary(operator, operand) =>
+      {
+        let (device, any, negate) = match operator
+        {
+          UnaryOperator::MemberOf => (false, false, false),
+          UnaryOperator::DeviceMemberOf => (true, false, false),
+          UnaryOperator::MemberOfAny => (false, true, false),
+          UnaryOperator::DeviceMemberOfAny => (true, true, false),
+          UnaryOperator::NotMemberOf => (false, false, true),
+          UnaryOperator::NotDeviceMemberOf => (true, false, true),
+          UnaryOperator::NotMemberOfAny => (false, true, true),
+          _ => (true, true, true)
+        };
+        let sids = match operand.values(context, does_identify)?
+        {
+          Some(values) => values.into_iter().map(|value| match value { ClaimValue::Sid(sid) => Some(sid), _ => None }).collect::<Option<Vec<_>>>(),
+          None => None
+        };
+        let result = match (sids, device, &context.device_groups)
+        {
+          (None, _, _) | (_, true, None) => None,
+          (Some(sids), true, Some(device_groups)) => Some(Self::membership(&sids, any, |sid| Ok::<_, E>(device_groups.contains(sid)))?),
+          (Some(sids), false, _) => Some(Self::membership(&sids, any, &mut *does_identify)?)
+        };
+        result.map(|result| result != negate)
+      },
+      Condition::Binary(BinaryOperator::And, lhs, rhs) =>
+      {
+        match (lhs.evaluate(context, does_identify)?, rhs.evaluate(context, does_identify)?)
+        {
+          (Some(false), _) | (_, Some(false)) => Some(false),
+          (Some(true), Some(true)) => Some(true),
+          _ => None
+        }
+      },
+      Condition::Binary(BinaryOperator::Or, lhs, rhs) =>
+      {
+        match (lhs.evaluate(context, does_identify)?, rhs.evaluate(context, does_identify)?)
+        {
+          (Some(true), _) | (_, Some(true)) => Some(true),
+          (Some(false), Some(false)) => Some(false),
+          _ => None
+        }
+      },
+      Condition::Binary(operator, lhs, rhs) =>
+      {
+        match (lhs.values(context, does_identify)?, rhs.values(context, does_identify)?)
+        {
+          (Some(lhs), Some(rhs)) => Self::relation(*operator, &lhs, &rhs),
+          _ => None
+        }
+      }
+    })
+  }
+
+  fn membership<E>(sids: &[SID], any: bool, mut does_identify: impl FnMut(&SID) -> Result<bool, E>) -> Result<bool, E>
+  {
+    for sid in sids
+    {
+      if does_identify(sid)? == any
+      {
+        return Ok(any);
+      }
+    }
+    Ok(!any)
+  }
+
+  fn relation(operator: BinaryOperator, lhs: &[ClaimValue], rhs: &[ClaimValue]) -> Option<bool>
+  {
+    let contains = |set: &[ClaimValue], value: &ClaimValue| set.iter().any(|member| member.equals(value));
+    let set_equals = || lhs.len() == rhs.len() && lhs.iter().all(|value| contains(rhs, value)) && rhs.iter().all(|value| contains(lhs, value));
+    match operator
+    {
+      BinaryOperator::Contains => Some(rhs.iter().all(|value| contains(lhs, value))),
+      BinaryOperator::NotContains => Some(!rhs.iter().all(|value| contains(lhs, value))),
+      BinaryOperator::AnyOf => Some(lhs.iter().any(|value| contains(rhs, value))),
+      BinaryOperator::NotAnyOf => Some(!lhs.iter().any(|value| contains(rhs, value))),
+      BinaryOperator::Equals => Some(set_equals()),
+      BinaryOperator::NotEquals => Some(!set_equals()),
+      // ordering is only defined between single values
+      operator => match (lhs, rhs)
+      {
+        ([ClaimValue::Sid(_)], _) | (_, [ClaimValue::Sid(_)]) => None,
+        ([lhs], [rhs]) =>
+        {
+          let ordering = lhs.compare(rhs)?;
+          Some(match operator
+          {
+            BinaryOperator::LessThan => ordering == Ordering::Less,
+            BinaryOperator::LessThanOrEqual => ordering != Ordering::Greater,
+            BinaryOperator::GreaterThan => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less
+          })
+        },
+        _ => None
+      }
+    }
+  }
+}
+
+// MS-DTYP 2.4.10.1 - CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1, the payload of a resource attribute ace
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResourceAttribute
+{
+  pub name: String,
+  pub value_type: u16,
+  pub flags: u32,
+  pub values: Vec<ClaimValue>
+}
+
+impl ResourceAttribute
+{
+  pub fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    let name = utf16_terminated(input, "name", le_field_u32(input, "name_offset", 0)? as usize)?;
+    let value_type = le_field_u16(input, "value_type", 4)?;
+    let flags = le_field_u32(input, "flags", 8)?;
+    let value_count = le_field_u32(input, "value_count", 12)? as usize;
+    let mut values = Vec::new();
+    for index in 0..value_count
+    {
+      let offset = le_field_u32(input, "value_offset", 16 + index * 4)? as usize;
+      let octet_string = || -> Result<&[u8], SDDLError>
+      {
+        let length = le_field_u32(input, "octet_string_length", offset)? as usize;
+        field_subslice_length(input, "octet_string", offset.saturating_add(4), length)
+      };
+      values.push(match value_type
+      {
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_INT64 => ClaimValue::Int64(le_field_u64(input, "value", offset)? as i64),
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_UINT64 => ClaimValue::Uint64(le_field_u64(input, "value", offset)?),
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_STRING => ClaimValue::String(utf16_terminated(input, "value", offset)?),
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_SID => ClaimValue::Sid(SID::new(octet_string()?)?),
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_BOOLEAN => ClaimValue::Boolean(le_field_u64(input, "value", offset)? != 0),
+        CLAIM_SECURITY_ATTRIBUTE_TYPE_OCTET_STRING => ClaimValue::OctetString(octet_string()?.to_vec()),
+        value_type => return Err(SDDLError::BadValue { field_name: "value_type".to_owned(), value: value_type as u64 })
+      });
+    }
+    Ok(Self { name, value_type, flags, values })
+  }
+
+  // the header and value offsets, then the name, then the values in order, padded so the ace
+  // stays aligned
+  pub fn to_bytes(&self) -> Vec<u8>
+  {
+    let name_offset = 16 + 4 * self.values.len();
+    let name = utf16_bytes(&format!("{}\0", self.name));
+    let mut offsets = Vec::new();
+    let mut values = Vec::new();
+    for value in self.values.iter()
+    {
+      offsets.push((name_offset + name.len() + values.len()) as u32);
+      match value
+      {
+        ClaimValue::Int64(value) => values.extend_from_slice(&value.to_le_bytes()),
+        ClaimValue::Uint64(value) => values.extend_from_slice(&value.to_le_bytes()),
+        ClaimValue::Boolean(value) => values.extend_from_slice(&(*value as u64).to_le_bytes()),
+        ClaimValue::String(value) => values.extend(utf16_bytes(&format!("{}\0", value))),
+        ClaimValue::Sid(sid) =>
+        {
+          let sid = sid.to_bytes();
+          values.extend_from_slice(&(sid.len() as u32).to_le_bytes());
+          values.extend(sid);
+        },
+        ClaimValue::OctetString(value) =>
+        {
+          values.extend_from_slice(&(value.len() as u32).to_le_bytes());
+          values.extend_from_slice(value);
+        }
+      }
+    }
+
+    let mut output = Vec::new();
+    output.extend_from_slice(&(name_offset as u32).to_le_bytes());
+    output.extend_from_slice(&self.value_type.to_le_bytes());
+    output.extend_from_slice(&[0, 0]);
+    output.extend_from_slice(&self.flags.to_le_bytes());
+    output.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+    for offset in offsets
+    {
+      output.extend_from_slice(&offset.to_le_bytes());
+    }
+    output.extend(name);
+    output.extend(values);
+    output.resize((output.len() + 3) & !3, 0);
+    output
+  }
+}
+
+impl ACEType
+{
+  // None when the ace carries no conditional expression
+  pub fn condition(&self) -> Option<Result<Condition, SDDLError>>
+  {
+    match self
+    {
+      ACEType::SystemResourceAttribute(_) => None,
+      ace_type => ace_type.application_data().filter(|data| data.starts_with(CONDITIONAL_SIGNATURE)).map(Condition::new)
+    }
+  }
+
+  pub fn resource_attribute(&self) -> Option<Result<ResourceAttribute, SDDLError>>
+  {
+    match self
+    {
+      ACEType::SystemResourceAttribute(callback) => Some(ResourceAttribute::new(&callback.application_data)),
+      _ => None
+    }
+  }
+}
+
+impl SDDL
+{
+  // resource attributes that fail to parse are left out, as they only ever narrow conditions
+  pub fn resource_attributes(&self) -> Vec<ResourceAttribute>
+  {
+    self.sacl
+      .iter()
+      .flat_map(|sacl| sacl.aces())
+      .filter_map(|ace| ace.ace_type.resource_attribute())
+      .filter_map(Result::ok)
+      .collect()
+  }
+}
@@ -0,0 +1,142 @@
+use uuid::Uuid;
+
+use super::{SDDL, SID, ACL, ACE, ACEType, ACEFlags, Access, AccessMask, AccessObject, AccessObjectFlags, AUTO_ENROLL, ENROLL};
+
+impl ACE
+{
+  pub fn allow(subject: SID, access_mask: AccessMask) -> Self
+  {
+    Self { ace_type: ACEType::AccessAllowed(Access { access_mask, subject }), flags: ACEFlags::NONE }
+  }
+
+  pub fn deny(subject: SID, access_mask: AccessMask) -> Self
+  {
+    Self { ace_type: ACEType::AccessDenied(Access { access_mask, subject }), flags: ACEFlags::NONE }
+  }
+
+  pub fn allow_object(subject: SID, access_mask: AccessMask, object_type: Uuid) -> Self
+  {
+    Self { ace_type: ACEType::AccessAllowedObject(AccessObject::new_typed(subject, access_mask, object_type)), flags: ACEFlags::NONE }
+  }
+
+  pub fn deny_object(subject: SID, access_mask: AccessMask, object_type: Uuid) -> Self
+  {
+    Self { ace_type: ACEType::AccessDeniedObject(AccessObject::new_typed(subject, access_mask, object_type)), flags: ACEFlags::NONE }
+  }
+
+  pub fn is_inherited(&self) -> bool
+  {
+    self.flags.contains(ACEFlags::INHERITED)
+  }
+
+  // the position of an explicit ace in a canonical dacl: denies ahead of allows, and within each
+  // those for the object itself ahead of those for a property or child.  anything else goes last
+  fn canonical_rank(&self) -> u8
+  {
+    let object_specific = self.ace_type.object().map(|object| object.object_type.is_some()).unwrap_or(false) as u8;
+    match self.ace_type
+    {
+      ACEType::AccessDenied(_) | ACEType::AccessDeniedObject(_) | ACEType::AccessDeniedCallback(_) | ACEType::AccessDeniedCallbackObject(_) => object_specific,
+      ACEType::AccessAllowed(_) | ACEType::AccessAllowedObject(_) | ACEType::AccessAllowedCallback(_) | ACEType::AccessAllowedCallbackObject(_) => 2 + object_specific,
+      _ => 4
+    }
+  }
+
+  // inherited aces keep the order they were inherited in, after every explicit ace
+  fn canonical_key(&self) -> (bool, u8)
+  {
+    (self.is_inherited(), if self.is_inherited() { 0 } else { self.canonical_rank() })
+  }
+}
+
+impl AccessObject
+{
+  fn new_typed(subject: SID, access_mask: AccessMask, object_type: Uuid) -> Self
+  {
+    Self
+    {
+      access_mask,
+      flags: AccessObjectFlags::ACE_OBJECT_TYPE_PRESENT,
+      object_type: Some(object_type),
+      inherited_object_type: None,
+      subject
+    }
+  }
+}
+
+impl From<Vec<ACE>> for ACL
+{
+  fn from(aces: Vec<ACE>) -> Self
+  {
+    Self(aces)
+  }
+}
+
+impl ACL
+{
+  // the ace goes after the others of its kind, which keeps a canonical acl canonical
+  pub fn add(&mut self, ace: ACE)
+  {
+    let key = ace.canonical_key();
+    let position = self.0.iter().position(|existing| existing.canonical_key() > key).unwrap_or(self.0.len());
+    self.0.insert(position, ace);
+  }
+
+  // returns the number of aces removed
+  pub fn remove(&mut self, mut predicate: impl FnMut(&ACE) -> bool) -> usize
+  {
+    let count = self.0.len();
+    self.0.retain(|ace| !predicate(ace));
+    count - self.0.len()
+  }
+
+  pub fn is_canonical(&self) -> bool
+  {
+    self.0.windows(2).all(|pair| pair[0].canonical_key() <= pair[1].canonical_key())
+  }
+
+  // the sort is stable, so aces of the same kind keep their relative order
+  pub fn canonicalize(&mut self)
+  {
+    self.0.sort_by_key(ACE::canonical_key);
+  }
+
+  pub fn grant_extended_right(&mut self, subject: SID, right: Uuid)
+  {
+    let ace = ACE::allow_object(subject, AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, right);
+    if !self.0.contains(&ace)
+    {
+      self.add(ace);
+    }
+  }
+
+  // only explicit grants of exactly this right are removed; broader grants are left alone
+  pub fn revoke_extended_right(&mut self, subject: &SID, right: &Uuid) -> usize
+  {
+    self.remove(|ace| !ace.is_inherited() && match &ace.ace_type
+    {
+      ACEType::AccessAllowedObject(object) => object.subject == *subject && object.object_type.as_ref() == Some(right) && object.access_mask == AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS,
+      _ => false
+    })
+  }
+}
+
+impl SDDL
+{
+  // a descriptor without a dacl already grants everyone everything, so there is nothing to add
+  pub fn grant_enroll(&mut self, subject: SID)
+  {
+    if let Some(dacl) = self.dacl.as_mut()
+    {
+      dacl.grant_extended_right(subject, ENROLL);
+    }
+  }
+
+  pub fn grant_auto_enroll(&mut self, subject: SID)
+  {
+    if let Some(dacl) = self.dacl.as_mut()
+    {
+      dacl.grant_extended_right(subject, AUTO_ENROLL);
+    }
+  }
+}
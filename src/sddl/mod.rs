@@ -0,0 +1,872 @@
+mod text;
+mod access;
+mod edit;
+mod conditional;
+
+use bitflags::bitflags;
+use thiserror::Error;
+use uuid::Uuid;
+use uuid::uuid;
+
+pub use access::AccessDecision;
+pub use conditional::{Condition, ClaimValue, ClaimsContext, AttributeSource, UnaryOperator, BinaryOperator, ResourceAttribute};
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests;
+
+#[derive(Error, Debug)]
+pub enum SDDLError
+{
+  #[error("bad revision")]
+  BadRevision,
+  #[error("sddl is not self-relative")]
+  NotSelfRelative,
+  #[error("field {field_name:?} at {field_index:?} with length {field_size:?} is out of input bounds (length {input_length:?})")]
+  FieldOutOfBounds
+  {
+    field_name: String,
+    field_index: usize,
+    field_size: usize,
+    input_length: usize
+  },
+  #[error("offset of {offset:?} from field {field_name:?} (length: {field_size:?}) out of bound {length:?}")]
+  OffsetOutOfBounds
+  {
+    field_name: String,
+    offset: usize,
+    field_size: Option<usize>,
+    length: usize
+  },
+  #[error("invalid value {value:?} for field {field_name:?}")]
+  BadValue
+  {
+    field_name: String,
+    value: u64
+  },
+  #[error("invalid ace_type value")]
+  InvalidACEType(u8),
+  #[error("bad uuid")]
+  BadUUID(#[from] uuid::Error),
+  #[error("invalid sddl string at {position:?}: {message}")]
+  InvalidSyntax
+  {
+    position: usize,
+    message: String
+  },
+  #[error("unknown sid alias {0:?}")]
+  UnknownAlias(String),
+  #[error("sid alias {0:?} is relative to a domain, but no domain sid was given")]
+  DomainRequired(String),
+  #[error("invalid conditional expression: {0}")]
+  BadCondition(String)
+}
+
+fn clone_into_array<A: Sized + Default + AsMut<[T]>, T: Clone>(slice: &[T]) -> A
+{
+  let mut a = Default::default();
+  <A as AsMut<[T]>>::as_mut(&mut a).clone_from_slice(slice);
+  a
+}
+
+// every read is bounds checked, as descriptors arrive from the directory and may be truncated
+fn field<'a>(input: &'a[u8], field_name: &str, field_index: usize, field_size: usize) -> Result<&'a[u8], SDDLError>
+{
+  field_index
+    .checked_add(field_size)
+    .and_then(|end| input.get(field_index..end))
+    .ok_or_else(|| SDDLError::FieldOutOfBounds { field_name: field_name.to_owned(), field_index, field_size, input_length: input.len() })
+}
+
+fn field_u8(input: &[u8], field_name: &str, field_index: usize) -> Result<u8, SDDLError>
+{
+  Ok(field(input, field_name, field_index, 1)?[0])
+}
+
+fn le_field_u16(input: &[u8], field_name: &str, field_index: usize) -> Result<u16, SDDLError>
+{
+  Ok(u16::from_le_bytes(clone_into_array(field(input, field_name, field_index, 2)?)))
+}
+
+fn le_field_u32(input: &[u8], field_name: &str, field_index: usize) -> Result<u32, SDDLError>
+{
+  Ok(u32::from_le_bytes(clone_into_array(field(input, field_name, field_index, 4)?)))
+}
+
+fn field_subslice<'a>(input: &'a[u8], field_name: &str, offset: usize) -> Result<&'a[u8], SDDLError>
+{
+  match input.get(offset..)
+  {
+    Some(subslice) if !subslice.is_empty() => Ok(subslice),
+    _ => Err(SDDLError::OffsetOutOfBounds { field_name: field_name.to_owned(), offset, length: input.len(), field_size: None })
+  }
+}
+
+fn field_subslice_length<'a>(input: &'a[u8], field_name: &str, offset: usize, length: usize) -> Result<&'a[u8], SDDLError>
+{
+  offset
+    .checked_add(length)
+    .and_then(|end| input.get(offset..end))
+    .ok_or_else(|| SDDLError::OffsetOutOfBounds { field_name: field_name.to_owned(), offset, length: input.len(), field_size: Some(length) })
+}
+
+fn field_uuid(input: &[u8], field_name: &str, offset: usize) -> Result<Uuid, SDDLError>
+{
+  let raw = field_subslice_length(input, field_name, offset, 16)?;
+  Ok(Uuid::from_bytes(
+    [
+      raw[3], raw[2], raw[1], raw[0],
+      raw[5], raw[4],
+      raw[7], raw[6],
+      raw[8], raw[9],
+      raw[10], raw[11], raw[12], raw[13], raw[14], raw[15]
+    ]))
+}
+
+fn size_u16(field_name: &str, size: usize) -> Result<u16, SDDLError>
+{
+  u16::try_from(size).map_err(|_| SDDLError::BadValue { field_name: field_name.to_owned(), value: size as u64 })
+}
+
+fn check_revision(input: u8, revision: u8) -> Result<(), SDDLError>
+{
+  if input != revision { Err(SDDLError::BadRevision) } else { Ok(()) }
+}
+
+bitflags!
+{
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+  pub struct AccessMask: u32
+  {
+    const ADS_RIGHT_DS_CREATE_CHILD   = 0x0000_0001;
+    const ADS_RIGHT_DS_DELETE_CHILD   = 0x0000_0002;
+    const ADS_RIGHT_ACTRL_DS_LIST     = 0x0000_0004;
+    const ADS_RIGHT_DS_SELF           = 0x0000_0008;
+    const ADS_RIGHT_DS_READ_PROP      = 0x0000_0010;
+    const ADS_RIGHT_DS_WRITE_PROP     = 0x0000_0020;
+    const ADS_RIGHT_DS_DELETE_TREE    = 0x0000_0040;
+    const ADS_RIGHT_DS_LIST_OBJECT    = 0x0000_0080;
+    const ADS_RIGHT_DS_CONTROL_ACCESS = 0x0000_0100;
+    const DELETE                      = 0x0001_0000;
+    const READ_CONTROL                = 0x0002_0000;
+    const WRITE_DAC                   = 0x0004_0000;
+    const WRITE_OWNER                 = 0x0008_0000;
+    const SYNCHRONIZE                 = 0x0010_0000;
+    const ACCESS_SYSTEM_SECURITY      = 0x0100_0000;
+    const MAXIMUM_ALLOWED             = 0x0200_0000;
+    const GENERIC_ALL                 = 0x1000_0000;
+    const GENERIC_EXECUTE             = 0x2000_0000;
+    const GENERIC_WRITE               = 0x4000_0000;
+    const GENERIC_READ                = 0x8000_0000;
+    const STANDARD_RIGHTS_REQUIRED    = 0x000F_0000;
+    const STANDARD_RIGHTS_READ        = Self::READ_CONTROL.bits();
+    const STANDARD_RIGHTS_WRITE       = Self::READ_CONTROL.bits();
+    const STANDARD_RIGHTS_EXECUTE     = Self::READ_CONTROL.bits();
+    const SPECIFIC_RIGHTS_ALL         = 0x0000_FFFF;
+    const STANDARD_RIGHTS_ALL         = 0x001F_0000;
+  }
+}
+
+impl AccessMask
+{
+  // object specific rights are defined by the resource manager, so unknown bits are kept
+  fn field(input: &[u8], field_index: usize) -> Result<Self, SDDLError>
+  {
+    Ok(Self::from_bits_retain(le_field_u32(input, "access_mask", field_index)?))
+  }
+}
+
+bitflags!
+{
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+  pub struct AccessObjectFlags: u32
+  {
+    const NONE                              = 0x0000_0000;
+    const ACE_OBJECT_TYPE_PRESENT           = 0x0000_0001;
+    const ACE_INHERITED_OBJECT_TYPE_PRESENT = 0x0000_0002;
+  }
+}
+
+impl AccessObjectFlags
+{
+  fn field(input: &[u8], field_index: usize) -> Result<Self, SDDLError>
+  {
+    let value = le_field_u32(input, "access_object_flags", field_index)?;
+    match Self::from_bits(value)
+    {
+      Some(flags) => Ok(flags),
+      None => Err(SDDLError::BadValue { field_name: "access_object_flags".to_owned(), value: value as u64 })
+    }
+  }
+}
+
+bitflags!
+{
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+  pub struct ACEFlags: u8
+  {
+    const NONE                  = 0b0000_0000;
+    const OBJECT_INHERIT        = 0b0000_0001;
+    const CONTAINER_INHERIT     = 0b0000_0010;
+    const NO_PROPAGATE_INHERITE = 0b0000_0100;
+    const INHERIT_ONLY          = 0b0000_1000;
+    const INHERITED             = 0b0001_0000;
+    const SUCCESSFUL_ACCESS     = 0b0100_0000;
+    const FAILED_ACCESS         = 0b1000_0000;
+    const AUDIT_FLAGS           = Self::FAILED_ACCESS.bits() | Self::SUCCESSFUL_ACCESS.bits();
+    const INHERITANCE_FLAGS     = Self::INHERIT_ONLY.bits() | Self::NO_PROPAGATE_INHERITE.bits() | Self::CONTAINER_INHERIT.bits() | Self::OBJECT_INHERIT.bits();
+  }
+}
+
+impl ACEFlags
+{
+  fn field(input: &[u8], field_index: usize) -> Result<Self, SDDLError>
+  {
+    let value = field_u8(input, "ace_flags", field_index)?;
+    match Self::from_bits(value)
+    {
+      Some(flags) => Ok(flags),
+      None => Err(SDDLError::BadValue { field_name: "ace_flags".to_owned(), value: value as u64 })
+    }
+  }
+}
+
+bitflags!
+{
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+  pub struct ControlFlags: u16
+  {
+    const OWNER_DEFAULTED           = 0b0000_0000_0000_0001;
+    const GROUP_DEFAULTED           = 0b0000_0000_0000_0010;
+    const DACL_PRESENT              = 0b0000_0000_0000_0100;
+    const DACL_DEFAULTED            = 0b0000_0000_0000_1000;
+
+    const SACL_PRESENT              = 0b0000_0000_0001_0000;
+    const SACL_DEFAULTED            = 0b0000_0000_0010_0000;
+    const SERVER_SECURITY           = 0b0000_0000_0100_0000;
+    const DACL_TRUSTED              = 0b0000_0000_1000_0000;
+
+    const DACL_INHERITANCE_REQUIRED = 0b0000_0001_0000_0000;
+    const SACL_INHERITANCE_REQUIRED = 0b0000_0010_0000_0000;
+    const DACL_AUTO_INHERITED       = 0b0000_0100_0000_0000;
+    const SACL_AUTO_INHERITED       = 0b0000_1000_0000_0000;
+
+    const DACL_PROTECTED            = 0b0001_0000_0000_0000;
+    const SACL_PROTECTED            = 0b0010_0000_0000_0000;
+    const CONTROL_VALID             = 0b0100_0000_0000_0000;
+    const SELF_RELATIVE             = 0b1000_0000_0000_0000;
+  }
+}
+
+impl ControlFlags
+{
+  fn field(input: &[u8], field_index: usize) -> Result<Self, SDDLError>
+  {
+    let value = le_field_u16(input, "control_flags", field_index)?;
+    match Self::from_bits(value)
+    {
+      Some(flags) => Ok(flags),
+      None => Err(SDDLError::BadValue { field_name: "control_flags".to_owned(), value: value as u64 })
+    }
+  }
+}
+
+// MS-DTYP 2.4.4.2 - the mask and sid shared by the simple ace types
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Access
+{
+  pub access_mask: AccessMask,
+  pub subject: SID
+}
+
+impl Access
+{
+  fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    Ok(Self
+    {
+      access_mask: AccessMask::field(input, 0)?,
+      subject: SID::new(field_subslice(input, "sid", 4)?)?
+    })
+  }
+
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    output.extend_from_slice(&self.access_mask.bits().to_le_bytes());
+    self.subject.encode(output);
+  }
+}
+
+// MS-DTYP 2.4.4.6 - callback aces carry application data after the sid, normally a conditional expression
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct CallbackAccess
+{
+  pub access_mask: AccessMask,
+  pub subject: SID,
+  pub application_data: Vec<u8>
+}
+
+impl CallbackAccess
+{
+  fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    let Access { access_mask, subject } = Access::new(input)?;
+    let application_data = input.get((4 + subject.size())..).unwrap_or_default().to_vec();
+    Ok(Self { access_mask, subject, application_data })
+  }
+
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    output.extend_from_slice(&self.access_mask.bits().to_le_bytes());
+    self.subject.encode(output);
+    output.extend_from_slice(&self.application_data);
+  }
+}
+
+// MS-DTYP 2.4.4.3
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct AccessObject
+{
+  pub access_mask: AccessMask,
+  pub flags: AccessObjectFlags,
+  pub object_type: Option<Uuid>,
+  pub inherited_object_type: Option<Uuid>,
+  pub subject: SID
+}
+
+impl AccessObject
+{
+  fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    let flags = AccessObjectFlags::field(input, 4)?;
+    let object_type_present = flags.contains(AccessObjectFlags::ACE_OBJECT_TYPE_PRESENT);
+    let inherited_object_type_present = flags.contains(AccessObjectFlags::ACE_INHERITED_OBJECT_TYPE_PRESENT);
+    let (object_type, inherited_object_type, sid_start) = match (object_type_present, inherited_object_type_present)
+    {
+      (true, false)  => (Some(field_uuid(input, "object_type", 8)?), None, 24),
+      (false, true)  => (None, Some(field_uuid(input, "inherited_object_type", 8)?), 24),
+      (true, true)   => (Some(field_uuid(input, "object_type", 8)?), Some(field_uuid(input, "inherited_object_type", 24)?), 40),
+      (false, false) => (None, None, 8)
+    };
+    Ok(Self
+    {
+      access_mask: AccessMask::field(input, 0)?,
+      flags,
+      object_type,
+      inherited_object_type,
+      subject: SID::new(field_subslice(input, "sid", sid_start)?)?
+    })
+  }
+
+  fn size(&self) -> usize
+  {
+    8 + 16 * (self.object_type.iter().count() + self.inherited_object_type.iter().count()) + self.subject.size()
+  }
+
+  // the flags are derived from the object types present, so the two can never disagree on the wire
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    let mut flags = AccessObjectFlags::NONE;
+    if self.object_type.is_some() { flags |= AccessObjectFlags::ACE_OBJECT_TYPE_PRESENT }
+    if self.inherited_object_type.is_some() { flags |= AccessObjectFlags::ACE_INHERITED_OBJECT_TYPE_PRESENT }
+    output.extend_from_slice(&self.access_mask.bits().to_le_bytes());
+    output.extend_from_slice(&flags.bits().to_le_bytes());
+    for object_type in self.object_type.iter().chain(self.inherited_object_type.iter())
+    {
+      output.extend_from_slice(&object_type.to_bytes_le());
+    }
+    self.subject.encode(output);
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8>
+  {
+    let mut result = Vec::with_capacity(self.size());
+    self.encode(&mut result);
+    result
+  }
+}
+
+// MS-DTYP 2.4.4.7
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct CallbackAccessObject
+{
+  pub object: AccessObject,
+  pub application_data: Vec<u8>
+}
+
+impl CallbackAccessObject
+{
+  fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    let object = AccessObject::new(input)?;
+    let application_data = input.get(object.size()..).unwrap_or_default().to_vec();
+    Ok(Self { object, application_data })
+  }
+
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    self.object.encode(output);
+    output.extend_from_slice(&self.application_data);
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct SID
+{
+  identifier_authority: [u8; 6],
+  sub_authority: Vec<u32>
+}
+
+impl SID
+{
+  pub fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    check_revision(field_u8(input, "revision", 0)?, 1)?;
+    // MS-DTYP 2.4.2.2 - at most 15 sub authorities
+    let sub_authority_count = field_u8(input, "sub_authority_count", 1)?;
+    if sub_authority_count > 15
+    {
+      return Err(SDDLError::BadValue { field_name: "sub_authority_count".to_owned(), value: sub_authority_count as u64 });
+    }
+    Ok(Self
+    {
+      identifier_authority: clone_into_array(field_subslice_length(input, "identifier_authority", 2, 6)?),
+      sub_authority: field_subslice_length(input, "sub_authority", 8, sub_authority_count as usize * 4)?.chunks_exact(4).map(|sub| u32::from_le_bytes(clone_into_array(sub))).collect::<Vec<u32>>()
+    })
+  }
+
+  pub fn from_components(identifier_authority: u64, sub_authority: &[u32]) -> Self
+  {
+    Self
+    {
+      identifier_authority: clone_into_array(&identifier_authority.to_be_bytes()[2..]),
+      sub_authority: sub_authority.to_vec()
+    }
+  }
+
+  // a sid relative to this one, as domain accounts are relative to the domain sid
+  pub fn with_rid(&self, rid: u32) -> Self
+  {
+    let mut sub_authority = self.sub_authority.clone();
+    sub_authority.push(rid);
+    Self { identifier_authority: self.identifier_authority, sub_authority }
+  }
+
+  // the domain an account sid (S-1-5-21-a-b-c-rid) belongs to
+  pub fn domain(&self) -> Option<Self>
+  {
+    match (self.identifier_authority, self.sub_authority.as_slice())
+    {
+      ([0, 0, 0, 0, 0, 5], [21, domain @ .., _]) if domain.len() == 3 => Some(Self { identifier_authority: self.identifier_authority, sub_authority: [&[21], domain].concat() }),
+      _ => None
+    }
+  }
+
+  pub fn size(&self) -> usize
+  {
+    8 + self.sub_authority.len() * 4
+  }
+
+  fn encode(&self, output: &mut Vec<u8>)
+  {
+    output.push(1);
+    output.push(self.sub_authority.len() as u8);
+    output.extend_from_slice(&self.identifier_authority);
+    for sub_authority in &self.sub_authority
+    {
+      output.extend_from_slice(&sub_authority.to_le_bytes());
+    }
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8>
+  {
+    let mut result = Vec::with_capacity(self.size());
+    self.encode(&mut result);
+    result
+  }
+
+  pub fn to_ldap_predicate(self) -> String
+  {
+    let bytes = self.to_bytes();
+    format!("(objectSID={})", bytes.into_iter().fold(String::new(), |a, byte| a + &format!("\\{}", hex::encode(&[byte]))))
+  }
+}
+
+impl std::fmt::Display for SID
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+  {
+    f.write_fmt(format_args!("S-1-"))?;
+    if self.identifier_authority[0] == 0x00 && self.identifier_authority[1] == 0x00
+    {
+      f.write_fmt(format_args!("{}", u32::from_be_bytes(clone_into_array(&self.identifier_authority[2..6]))))?;
+    }
+    else
+    {
+      f.write_fmt(format_args!("0x{}", hex::encode_upper(self.identifier_authority)))?;
+    }
+    for sub_authority in &self.sub_authority
+    {
+      f.write_fmt(format_args!("-{}", sub_authority))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default)]
+pub struct ACL(Vec<ACE>);
+
+impl ACL
+{
+  fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    // MS-DTYP 2.4.5 - ACL_REVISION_DS is only required once object aces are present
+    let revision = field_u8(input, "revision", 0)?;
+    if revision != 2 && revision != 4 { return Err(SDDLError::BadRevision) }
+    let acl_size = le_field_u16(input, "acl_size", 2)? as usize;
+    let input = field_subslice_length(input, "acl_size", 0, acl_size)?;
+    let ace_count = le_field_u16(input, "ace_count", 4)? as usize;
+    let mut ace_start = 8;
+    let mut ace_list = Vec::with_capacity(ace_count);
+    for _ in 0..ace_count
+    {
+      let (ace, size) = ACE::new(field_subslice(input, "ace_offset", ace_start)?)?;
+      ace_start += size;
+      ace_list.push(ace);
+    }
+    Ok(Self(ace_list))
+  }
+
+  pub fn aces(&self) -> impl Iterator<Item = &'_ ACE>
+  {
+    self.0.iter()
+  }
+
+  pub fn to_bytes(&self) -> Result<Vec<u8>, SDDLError>
+  {
+    let revision = if self.0.iter().any(|ace| ace.ace_type.object().is_some()) { 4 } else { 2 };
+    let mut aces = Vec::new();
+    for ace in &self.0
+    {
+      aces.extend(ace.to_bytes()?);
+    }
+    let mut result = vec![revision, 0];
+    result.extend_from_slice(&size_u16("acl_size", 8 + aces.len())?.to_le_bytes());
+    result.extend_from_slice(&size_u16("ace_count", self.0.len())?.to_le_bytes());
+    result.extend_from_slice(&[0, 0]);
+    result.extend(aces);
+    Ok(result)
+  }
+}
+
+// MS-DTYP 2.4.4.1
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum ACEType
+{
+  AccessAllowed(Access),
+  AccessDenied(Access),
+  SystemAudit(Access),
+  SystemAlarm(Access),
+  // reserved by MS-DTYP and never produced by Windows, so the payload is kept opaque
+  AccessAllowedCompound(Vec<u8>),
+  AccessAllowedObject(AccessObject),
+  AccessDeniedObject(AccessObject),
+  SystemAuditObject(AccessObject),
+  SystemAlarmObject(AccessObject),
+  AccessAllowedCallback(CallbackAccess),
+  AccessDeniedCallback(CallbackAccess),
+  AccessAllowedCallbackObject(CallbackAccessObject),
+  AccessDeniedCallbackObject(CallbackAccessObject),
+  SystemAuditCallback(CallbackAccess),
+  SystemAlarmCallback(CallbackAccess),
+  SystemAuditCallbackObject(CallbackAccessObject),
+  SystemAlarmCallbackObject(CallbackAccessObject),
+  SystemMandatoryLabel(Access),
+  // MS-DTYP 2.4.4.15 - the application data holds the CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1
+  SystemResourceAttribute(CallbackAccess),
+  SystemScopedPolicyId(Access),
+  // ace types beyond those in MS-DTYP are kept as they are, so the rest of the acl still parses
+  Unknown(u8, Vec<u8>)
+}
+
+impl ACEType
+{
+  pub fn code(&self) -> u8
+  {
+    match self
+    {
+      ACEType::AccessAllowed(_) => 0x00,
+      ACEType::AccessDenied(_) => 0x01,
+      ACEType::SystemAudit(_) => 0x02,
+      ACEType::SystemAlarm(_) => 0x03,
+      ACEType::AccessAllowedCompound(_) => 0x04,
+      ACEType::AccessAllowedObject(_) => 0x05,
+      ACEType::AccessDeniedObject(_) => 0x06,
+      ACEType::SystemAuditObject(_) => 0x07,
+      ACEType::SystemAlarmObject(_) => 0x08,
+      ACEType::AccessAllowedCallback(_) => 0x09,
+      ACEType::AccessDeniedCallback(_) => 0x0A,
+      ACEType::AccessAllowedCallbackObject(_) => 0x0B,
+      ACEType::AccessDeniedCallbackObject(_) => 0x0C,
+      ACEType::SystemAuditCallback(_) => 0x0D,
+      ACEType::SystemAlarmCallback(_) => 0x0E,
+      ACEType::SystemAuditCallbackObject(_) => 0x0F,
+      ACEType::SystemAlarmCallbackObject(_) => 0x10,
+      ACEType::SystemMandatoryLabel(_) => 0x11,
+      ACEType::SystemResourceAttribute(_) => 0x12,
+      ACEType::SystemScopedPolicyId(_) => 0x13,
+      ACEType::Unknown(code, _) => *code
+    }
+  }
+
+  pub fn object(&self) -> Option<&'_ AccessObject>
+  {
+    match self
+    {
+      ACEType::AccessAllowedObject(object) |
+      ACEType::AccessDeniedObject(object) |
+      ACEType::SystemAuditObject(object) |
+      ACEType::SystemAlarmObject(object) => Some(object),
+      ACEType::AccessAllowedCallbackObject(callback) |
+      ACEType::AccessDeniedCallbackObject(callback) |
+      ACEType::SystemAuditCallbackObject(callback) |
+      ACEType::SystemAlarmCallbackObject(callback) => Some(&callback.object),
+      _ => None
+    }
+  }
+
+  pub fn application_data(&self) -> Option<&'_ [u8]>
+  {
+    match self
+    {
+      ACEType::AccessAllowedCallback(callback) |
+      ACEType::AccessDeniedCallback(callback) |
+      ACEType::SystemAuditCallback(callback) |
+      ACEType::SystemAlarmCallback(callback) |
+      ACEType::SystemResourceAttribute(callback) => Some(&callback.application_data),
+      ACEType::AccessAllowedCallbackObject(callback) |
+      ACEType::AccessDeniedCallbackObject(callback) |
+      ACEType::SystemAuditCallbackObject(callback) |
+      ACEType::SystemAlarmCallbackObject(callback) => Some(&callback.application_data),
+      _ => None
+    }
+  }
+
+  pub fn access_mask(&self) -> Option<&'_ AccessMask>
+  {
+    match self
+    {
+      ACEType::AccessAllowed(access) |
+      ACEType::AccessDenied(access) |
+      ACEType::SystemAudit(access) |
+      ACEType::SystemAlarm(access) |
+      ACEType::SystemMandatoryLabel(access) |
+      ACEType::SystemScopedPolicyId(access) => Some(&access.access_mask),
+      ACEType::AccessAllowedObject(object) |
+      ACEType::AccessDeniedObject(object) |
+      ACEType::SystemAuditObject(object) |
+      ACEType::SystemAlarmObject(object) => Some(&object.access_mask),
+      ACEType::AccessAllowedCallback(callback) |
+      ACEType::AccessDeniedCallback(callback) |
+      ACEType::SystemAuditCallback(callback) |
+      ACEType::SystemAlarmCallback(callback) |
+      ACEType::SystemResourceAttribute(callback) => Some(&callback.access_mask),
+      ACEType::AccessAllowedCallbackObject(callback) |
+      ACEType::AccessDeniedCallbackObject(callback) |
+      ACEType::SystemAuditCallbackObject(callback) |
+      ACEType::SystemAlarmCallbackObject(callback) => Some(&callback.object.access_mask),
+      ACEType::AccessAllowedCompound(_) | ACEType::Unknown(_, _) => None
+    }
+  }
+
+  pub fn subject(&self) -> Option<&'_ SID>
+  {
+    match self
+    {
+      ACEType::AccessAllowed(access) |
+      ACEType::AccessDenied(access) |
+      ACEType::SystemAudit(access) |
+      ACEType::SystemAlarm(access) |
+      ACEType::SystemMandatoryLabel(access) |
+      ACEType::SystemScopedPolicyId(access) => Some(&access.subject),
+      ACEType::AccessAllowedObject(object) |
+      ACEType::AccessDeniedObject(object) |
+      ACEType::SystemAuditObject(object) |
+      ACEType::SystemAlarmObject(object) => Some(&object.subject),
+      ACEType::AccessAllowedCallback(callback) |
+      ACEType::AccessDeniedCallback(callback) |
+      ACEType::SystemAuditCallback(callback) |
+      ACEType::SystemAlarmCallback(callback) |
+      ACEType::SystemResourceAttribute(callback) => Some(&callback.subject),
+      ACEType::AccessAllowedCallbackObject(callback) |
+      ACEType::AccessDeniedCallbackObject(callback) |
+      ACEType::SystemAuditCallbackObject(callback) |
+      ACEType::SystemAlarmCallbackObject(callback) => Some(&callback.object.subject),
+      ACEType::AccessAllowedCompound(_) | ACEType::Unknown(_, _) => None
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct ACE
+{
+  pub ace_type: ACEType,
+  pub flags: ACEFlags
+}
+
+impl ACE
+{
+  fn parse_ace_type(input: u8, remaining: &[u8]) -> Result<ACEType, SDDLError>
+  {
+    match input
+    {
+      0x00 => Ok(ACEType::AccessAllowed(Access::new(remaining)?)),
+      0x01 => Ok(ACEType::AccessDenied(Access::new(remaining)?)),
+      0x02 => Ok(ACEType::SystemAudit(Access::new(remaining)?)),
+      0x03 => Ok(ACEType::SystemAlarm(Access::new(remaining)?)),
+      0x04 => Ok(ACEType::AccessAllowedCompound(remaining.to_vec())),
+      0x05 => Ok(ACEType::AccessAllowedObject(AccessObject::new(remaining)?)),
+      0x06 => Ok(ACEType::AccessDeniedObject(AccessObject::new(remaining)?)),
+      0x07 => Ok(ACEType::SystemAuditObject(AccessObject::new(remaining)?)),
+      0x08 => Ok(ACEType::SystemAlarmObject(AccessObject::new(remaining)?)),
+      0x09 => Ok(ACEType::AccessAllowedCallback(CallbackAccess::new(remaining)?)),
+      0x0A => Ok(ACEType::AccessDeniedCallback(CallbackAccess::new(remaining)?)),
+      0x0B => Ok(ACEType::AccessAllowedCallbackObject(CallbackAccessObject::new(remaining)?)),
+      0x0C => Ok(ACEType::AccessDeniedCallbackObject(CallbackAccessObject::new(remaining)?)),
+      0x0D => Ok(ACEType::SystemAuditCallback(CallbackAccess::new(remaining)?)),
+      0x0E => Ok(ACEType::SystemAlarmCallback(CallbackAccess::new(remaining)?)),
+      0x0F => Ok(ACEType::SystemAuditCallbackObject(CallbackAccessObject::new(remaining)?)),
+      0x10 => Ok(ACEType::SystemAlarmCallbackObject(CallbackAccessObject::new(remaining)?)),
+      0x11 => Ok(ACEType::SystemMandatoryLabel(Access::new(remaining)?)),
+      0x12 => Ok(ACEType::SystemResourceAttribute(CallbackAccess::new(remaining)?)),
+      0x13 => Ok(ACEType::SystemScopedPolicyId(Access::new(remaining)?)),
+      ace_type => Ok(ACEType::Unknown(ace_type, remaining.to_vec()))
+    }
+  }
+
+  // returns the ace along with its size, which locates the next ace in the acl
+  fn new(input: &[u8]) -> Result<(Self, usize), SDDLError>
+  {
+    let size = le_field_u16(input, "ace_size", 2)? as usize;
+    if size < 4
+    {
+      return Err(SDDLError::BadValue { field_name: "ace_size".to_owned(), value: size as u64 });
+    }
+    let ace = Self
+    {
+      ace_type: Self::parse_ace_type(field_u8(input, "ace_type", 0)?, field_subslice_length(input, "ace_contents", 4, size - 4)?)?,
+      flags: ACEFlags::field(input, 1)?
+    };
+    Ok((ace, size))
+  }
+
+  // MS-DTYP 2.4.4.1 - the size of an ace is kept to a multiple of 4 bytes
+  pub fn to_bytes(&self) -> Result<Vec<u8>, SDDLError>
+  {
+    let mut contents = Vec::new();
+    match &self.ace_type
+    {
+      ACEType::AccessAllowed(access) |
+      ACEType::AccessDenied(access) |
+      ACEType::SystemAudit(access) |
+      ACEType::SystemAlarm(access) |
+      ACEType::SystemMandatoryLabel(access) |
+      ACEType::SystemScopedPolicyId(access) => access.encode(&mut contents),
+      ACEType::AccessAllowedCompound(raw) | ACEType::Unknown(_, raw) => contents.extend_from_slice(raw),
+      ACEType::AccessAllowedObject(object) |
+      ACEType::AccessDeniedObject(object) |
+      ACEType::SystemAuditObject(object) |
+      ACEType::SystemAlarmObject(object) => object.encode(&mut contents),
+      ACEType::AccessAllowedCallback(callback) |
+      ACEType::AccessDeniedCallback(callback) |
+      ACEType::SystemAuditCallback(callback) |
+      ACEType::SystemAlarmCallback(callback) |
+      ACEType::SystemResourceAttribute(callback) => callback.encode(&mut contents),
+      ACEType::AccessAllowedCallbackObject(callback) |
+      ACEType::AccessDeniedCallbackObject(callback) |
+      ACEType::SystemAuditCallbackObject(callback) |
+      ACEType::SystemAlarmCallbackObject(callback) => callback.encode(&mut contents)
+    }
+    contents.resize((contents.len() + 3) & !3, 0);
+    let mut result = vec![self.ace_type.code(), self.flags.bits()];
+    result.extend_from_slice(&size_u16("ace_size", 4 + contents.len())?.to_le_bytes());
+    result.extend(contents);
+    Ok(result)
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct SDDL
+{
+  pub control: ControlFlags,
+  pub owner: Option<SID>,
+  pub group: Option<SID>,
+  pub sacl: Option<ACL>,
+  pub dacl: Option<ACL>
+}
+
+impl SDDL
+{
+  pub fn new(input: &[u8]) -> Result<Self, SDDLError>
+  {
+    check_revision(field_u8(input, "revision", 0)?, 1)?;
+    let control = ControlFlags::field(input, 2)?;
+    if !control.contains(ControlFlags::SELF_RELATIVE) { return Err(SDDLError::NotSelfRelative) }
+    let sacl_offset = le_field_u32(input, "sacl_offset", 12)? as usize;
+    let dacl_offset = le_field_u32(input, "dacl_offset", 16)? as usize;
+    let owner_offset = le_field_u32(input, "owner_offset", 4)? as usize;
+    let group_offset = le_field_u32(input, "group_offset", 8)? as usize;
+    Ok(Self
+    {
+      control,
+      owner: if owner_offset == 0 { None } else { Some(SID::new(field_subslice(input, "owner_offset", owner_offset)?)?) },
+      group: if group_offset == 0 { None } else { Some(SID::new(field_subslice(input, "group_offset", group_offset)?)?) },
+      sacl: if sacl_offset == 0 { None } else { Some(ACL::new(field_subslice(input, "sacl_offset", sacl_offset)?)?) },
+      dacl: if dacl_offset == 0 { None } else { Some(ACL::new(field_subslice(input, "dacl_offset", dacl_offset)?)?) }
+    })
+  }
+
+  // MS-DTYP 2.4.6 - the self-relative form, with the acls ahead of the owner and group as Active
+  // Directory lays them out.  a dacl present without an acl is kept as a null dacl
+  pub fn to_bytes(&self) -> Result<Vec<u8>, SDDLError>
+  {
+    let mut control = self.control.clone() | ControlFlags::SELF_RELATIVE;
+    control.set(ControlFlags::SACL_PRESENT, self.sacl.is_some() || control.contains(ControlFlags::SACL_PRESENT));
+    control.set(ControlFlags::DACL_PRESENT, self.dacl.is_some() || control.contains(ControlFlags::DACL_PRESENT));
+
+    let mut body = Vec::new();
+    let mut offset = |bytes: Option<Vec<u8>>| -> u32
+    {
+      match bytes
+      {
+        Some(bytes) =>
+        {
+          let offset = 20 + body.len() as u32;
+          body.extend(bytes);
+          offset
+        },
+        None => 0
+      }
+    };
+    let sacl_offset = offset(self.sacl.as_ref().map(ACL::to_bytes).transpose()?);
+    let dacl_offset = offset(self.dacl.as_ref().map(ACL::to_bytes).transpose()?);
+    let owner_offset = offset(self.owner.as_ref().map(SID::to_bytes));
+    let group_offset = offset(self.group.as_ref().map(SID::to_bytes));
+
+    let mut result = vec![1, 0];
+    result.extend_from_slice(&control.bits().to_le_bytes());
+    for offset in [owner_offset, group_offset, sacl_offset, dacl_offset]
+    {
+      result.extend_from_slice(&offset.to_le_bytes());
+    }
+    result.extend(body);
+    Ok(result)
+  }
+}
+
+pub const ENROLL: Uuid = uuid!("0e10c968-78fb-11d2-90d4-00c04f79dc55");
+pub const AUTO_ENROLL: Uuid = uuid!("a05b8cc2-17bc-4802-a710-e7c15ab866a2");
\ No newline at end of file
@@ -0,0 +1,635 @@
+use std::str::FromStr;
+
+use base64::{engine::general_purpose, Engine};
+use proptest::{prelude::*, collection::vec, option, sample::select};
+use uuid::Uuid;
+
+use super::{SDDL, SDDLError, SID, ACL, ACE, ACEType, ACEFlags, Access, AccessMask, AccessObject, AccessObjectFlags, CallbackAccess, CallbackAccessObject, AccessDecision, ControlFlags, Condition, ClaimValue, ClaimsContext, AttributeSource, UnaryOperator, BinaryOperator, ResourceAttribute, AUTO_ENROLL, ENROLL};
+
+const TEMPLATE_SDDL: &str = "O:DAG:DAD:PAI(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;DA)(A;;LCRPLORC;;;AU)";
+
+fn domain() -> SID
+{
+  SID::from_str("S-1-5-21-3623811015-3361044348-30300820").expect("bad domain sid")
+}
+
+#[test]
+fn sid_round_trip()
+{
+  let sid = SID::from_str("S-1-5-21-3623811015-3361044348-30300820-1013").expect("failed to parse sid");
+  assert_eq!(sid, domain().with_rid(1013));
+  assert_eq!(sid.to_string(), "S-1-5-21-3623811015-3361044348-30300820-1013");
+  assert_eq!(SID::from_str("S-1-0x000000000010-8192").expect("failed to parse sid"), SID::from_components(16, &[8192]));
+  assert!(SID::from_str("S-2-5-18").is_err());
+  assert!(SID::from_str("S-1-5-x").is_err());
+}
+
+#[test]
+fn text_round_trip()
+{
+  let domain = domain();
+  let sddl = SDDL::from_str_with_domain(TEMPLATE_SDDL, Some(&domain)).expect("failed to parse sddl");
+  assert_eq!(sddl.owner, Some(domain.with_rid(512)));
+  assert_eq!(sddl.group, Some(domain.with_rid(512)));
+  assert!(sddl.sacl.is_none());
+  assert!(sddl.control.contains(ControlFlags::SELF_RELATIVE | ControlFlags::DACL_PRESENT | ControlFlags::DACL_PROTECTED | ControlFlags::DACL_AUTO_INHERITED));
+
+  let dacl = sddl.dacl.as_ref().expect("no dacl");
+  let aces: Vec<_> = dacl.aces().collect();
+  assert_eq!(aces.len(), 3);
+  match &aces[0].ace_type
+  {
+    ACEType::AccessAllowedObject(object) =>
+    {
+      assert_eq!(object.access_mask, AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS);
+      assert_eq!(object.object_type, Some(ENROLL));
+      assert_eq!(object.subject, domain.with_rid(513));
+    },
+    other => panic!("unexpected ace {:?}", other)
+  }
+  assert_eq!(aces[2].ace_type.subject(), Some(&SID::from_components(5, &[11])));
+
+  assert_eq!(sddl.to_string_with_domain(Some(&domain)), TEMPLATE_SDDL.replace("LCRPLORC", "RPLCLORC"));
+  assert_eq!(sddl.to_string(), TEMPLATE_SDDL
+    .replace("LCRPLORC", "RPLCLORC")
+    .replace("DA", "S-1-5-21-3623811015-3361044348-30300820-512")
+    .replace("DU", "S-1-5-21-3623811015-3361044348-30300820-513"));
+}
+
+#[test]
+fn text_aliases()
+{
+  let sddl = SDDL::from_str("O:SYG:BAD:(A;OICI;FA;;;WD)(D;;0x1f01ff;;;AN)S:(AU;SAFA;GA;;;WD)(ML;;NW;;;HI)").expect("failed to parse sddl");
+  assert_eq!(sddl.owner, Some(SID::from_components(5, &[18])));
+  assert_eq!(sddl.group, Some(SID::from_components(5, &[32, 544])));
+  let dacl: Vec<_> = sddl.dacl.as_ref().expect("no dacl").aces().cloned().collect();
+  assert_eq!(dacl[0].ace_type.access_mask(), dacl[1].ace_type.access_mask());
+  assert!(sddl.control.contains(ControlFlags::SACL_PRESENT));
+  assert_eq!(sddl.to_string(), "O:SYG:BAD:(A;OICI;0x1f01ff;;;WD)(D;;0x1f01ff;;;AN)S:(AU;SAFA;GA;;;WD)(ML;;NW;;;HI)");
+}
+
+#[test]
+fn text_null_dacl()
+{
+  let sddl = SDDL::from_str("D:NO_ACCESS_CONTROL").expect("failed to parse sddl");
+  assert!(sddl.control.contains(ControlFlags::DACL_PRESENT));
+  assert!(sddl.dacl.is_none());
+  assert_eq!(sddl.to_string(), "D:NO_ACCESS_CONTROL");
+}
+
+#[test]
+fn text_errors()
+{
+  assert!(matches!(SDDL::from_str(TEMPLATE_SDDL), Err(SDDLError::DomainRequired(alias)) if alias == "DA"));
+  assert!(matches!(SDDL::from_str("O:ZZ"), Err(SDDLError::UnknownAlias(alias)) if alias == "ZZ"));
+  assert!(matches!(SDDL::from_str("D:(A;;GA;;;WD"), Err(SDDLError::InvalidSyntax { position: 2, .. })));
+  assert!(matches!(SDDL::from_str("D:(A;;GA;;WD)"), Err(SDDLError::InvalidSyntax { .. })));
+  assert!(matches!(SDDL::from_str("D:(A;;GA;0e10c968-78fb-11d2-90d4-00c04f79dc55;;WD)"), Err(SDDLError::InvalidSyntax { .. })));
+  assert!(matches!(SDDL::from_str("X:"), Err(SDDLError::InvalidSyntax { position: 0, .. })));
+}
+
+#[test]
+fn binary_without_owner()
+{
+  // self-relative descriptor with only a dacl granting everyone generic all
+  let input: &[u8] =
+  &[
+    0x01, 0x00, 0x04, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00
+  ];
+  let sddl = SDDL::new(input).expect("failed to parse sddl");
+  assert!(sddl.owner.is_none());
+  assert!(sddl.group.is_none());
+  assert_eq!(sddl.to_string(), "D:(A;;GA;;;WD)");
+}
+
+fn check(sddl: &str, object_type: &Uuid) -> (bool, Option<String>)
+{
+  let domain = domain();
+  let member_of = [domain.with_rid(513), SID::from_components(5, &[11]), SID::from_components(1, &[0])];
+  let sddl = SDDL::from_str_with_domain(sddl, Some(&domain)).expect("failed to parse sddl");
+  let decision = sddl
+    .access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(object_type), |sid| Ok::<_, ()>(member_of.contains(sid)))
+    .expect("access check failed");
+  let ace = match &decision
+  {
+    AccessDecision::Allowed(ace) | AccessDecision::Denied(ace) => ace.map(|ace| ace.to_string())
+  };
+  (decision.is_allowed(), ace)
+}
+
+#[test]
+fn access_check_ordering()
+{
+  let deny = "(OD;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;AU)";
+  let allow = "(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;WD)";
+  assert_eq!(check(&format!("D:{}{}", deny, allow), &ENROLL), (false, Some(deny.to_owned())));
+  // a deny after the rights were granted is never reached
+  assert_eq!(check(&format!("D:{}{}", allow, deny), &ENROLL), (true, Some(allow.to_owned())));
+  // denies for other principals and other object types do not apply
+  assert!(check(&format!("D:(OD;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DA){}", allow), &ENROLL).0);
+  assert!(check(&format!("D:(OD;;CR;a05b8cc2-17bc-4802-a710-e7c15ab866a2;;AU){}", allow), &ENROLL).0);
+}
+
+#[test]
+fn access_check_rights()
+{
+  // control access without an object type grants every extended right
+  assert!(check("D:(OA;;CR;;;AU)", &AUTO_ENROLL).0);
+  assert!(check("D:(A;;CR;;;AU)", &AUTO_ENROLL).0);
+  assert!(check("D:(A;;GA;;;DU)", &ENROLL).0);
+  assert!(check("D:(A;;0xf01ff;;;DU)", &ENROLL).0);
+  assert!(!check("D:(A;;GR;;;DU)", &ENROLL).0);
+  assert!(!check("D:(A;;RPWP;;;AU)", &ENROLL).0);
+  assert_eq!(check("D:(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)", &AUTO_ENROLL), (false, None));
+  // inherit only aces apply to children, not the template itself
+  assert!(!check("D:(OA;CIIO;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)", &ENROLL).0);
+  assert!(check("D:(D;OICIIO;GA;;;AU)(A;;GA;;;AU)", &ENROLL).0);
+}
+
+#[test]
+fn access_check_null_dacl()
+{
+  assert_eq!(check("D:NO_ACCESS_CONTROL", &ENROLL), (true, None));
+  assert_eq!(check("O:DA", &ENROLL), (true, None));
+  assert_eq!(check("D:", &ENROLL), (false, None));
+}
+
+#[test]
+fn binary_round_trip()
+{
+  let domain = domain();
+  let text = "O:DAG:DAD:PAI(OD;;CR;a05b8cc2-17bc-4802-a710-e7c15ab866a2;;AU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)(OA;CIIO;WP;bf967a86-0de6-11d0-a285-00aa003049e2;bf967aba-0de6-11d0-a285-00aa003049e2;DA)(A;;GA;;;DA)S:(AU;SAFA;WOWD;;;WD)";
+  let sddl = SDDL::from_str_with_domain(text, Some(&domain)).expect("failed to parse sddl");
+  let bytes = sddl.to_bytes().expect("failed to encode sddl");
+  assert_eq!(&bytes[0..4], &[0x01, 0x00, 0x14, 0x94]);
+  assert_eq!(bytes.len() % 4, 0);
+  let decoded = SDDL::new(&bytes).expect("failed to decode sddl");
+  assert_eq!(decoded, sddl);
+  assert_eq!(decoded.to_string_with_domain(Some(&domain)), text);
+}
+
+#[test]
+fn binary_null_dacl()
+{
+  let sddl = SDDL::from_str("O:SYD:NO_ACCESS_CONTROL").expect("failed to parse sddl");
+  let decoded = SDDL::new(&sddl.to_bytes().expect("failed to encode sddl")).expect("failed to decode sddl");
+  assert_eq!(decoded.to_string(), "O:SYD:NO_ACCESS_CONTROL");
+}
+
+#[test]
+fn edit_grant_and_revoke()
+{
+  let domain = domain();
+  let mut sddl = SDDL::from_str_with_domain("O:DAG:DAD:(A;;RPLCLORC;;;AU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)(A;ID;GA;;;DA)", Some(&domain)).expect("failed to parse sddl");
+  let computers = domain.with_rid(515);
+  sddl.grant_enroll(computers.clone());
+  sddl.grant_enroll(computers.clone());
+  sddl.grant_auto_enroll(computers.clone());
+  let dacl = sddl.dacl.as_mut().expect("no dacl");
+  assert!(dacl.is_canonical());
+  dacl.add(ACE::deny(SID::from_components(5, &[7]), AccessMask::GENERIC_ALL));
+  assert_eq!(sddl.to_string_with_domain(Some(&domain)), "O:DAG:DAD:(D;;GA;;;AN)(A;;RPLCLORC;;;AU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DC)(OA;;CR;a05b8cc2-17bc-4802-a710-e7c15ab866a2;;DC)(A;ID;GA;;;DA)");
+
+  let dacl = sddl.dacl.as_mut().expect("no dacl");
+  assert_eq!(dacl.revoke_extended_right(&computers, &ENROLL), 1);
+  assert_eq!(dacl.revoke_extended_right(&computers, &ENROLL), 0);
+  assert_eq!(dacl.remove(|ace| ace.is_inherited()), 1);
+  assert_eq!(dacl.aces().count(), 4);
+}
+
+#[test]
+fn edit_canonicalize()
+{
+  let mut sddl = SDDL::from_str("D:(A;ID;GA;;;SY)(A;;GR;;;AU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;AU)(D;;GA;;;AN)(A;ID;GR;;;BA)(OD;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;AN)").expect("failed to parse sddl");
+  let dacl = sddl.dacl.as_mut().expect("no dacl");
+  assert!(!dacl.is_canonical());
+  dacl.canonicalize();
+  assert!(dacl.is_canonical());
+  assert_eq!(sddl.to_string(), "D:(D;;GA;;;AN)(OD;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;AN)(A;;GR;;;AU)(OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;AU)(A;ID;GA;;;SY)(A;ID;GR;;;BA)");
+}
+
+const FIXTURE_DOMAIN: &str = "S-1-5-21-1004336348-1177238915-682003330";
+
+fn fixture(encoded: &str) -> Vec<u8>
+{
+  general_purpose::STANDARD.decode(encoded.trim()).expect("error base64 decoding fixture")
+}
+
+#[test]
+fn fixture_user_template()
+{
+  let domain = SID::from_str(FIXTURE_DOMAIN).expect("bad domain sid");
+  let bytes = fixture(include_str!("user_template.txt"));
+  let sddl = SDDL::new(&bytes).expect("failed to parse sddl");
+  assert_eq!(sddl.to_string_with_domain(Some(&domain)), "O:EAG:DUD:AI\
+    (OA;;RPWPCR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DA)\
+    (OA;;RPWPCR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DU)\
+    (OA;;RPWPCR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;EA)\
+    (A;;RPWPCCDCLCLORCWOWDSDDTSW;;;DA)\
+    (A;;RPWPCCDCLCLORCWOWDSDDTSW;;;EA)\
+    (A;;RPLCLORC;;;AU)");
+  assert_eq!(sddl.to_bytes().expect("failed to encode sddl"), bytes);
+}
+
+#[test]
+fn fixture_machine_template()
+{
+  let domain = SID::from_str(FIXTURE_DOMAIN).expect("bad domain sid");
+  let bytes = fixture(include_str!("machine_template.txt"));
+  let sddl = SDDL::new(&bytes).expect("failed to parse sddl");
+  assert_eq!(sddl.to_string_with_domain(Some(&domain)), "O:BAG:DUD:PAI\
+    (OD;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DG)\
+    (OA;;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;DC)\
+    (OA;;CR;a05b8cc2-17bc-4802-a710-e7c15ab866a2;;DC)\
+    (OA;CIIO;WP;bf967a86-0de6-11d0-a285-00aa003049e2;bf967aba-0de6-11d0-a285-00aa003049e2;DA)\
+    (A;;RPWPCCDCLCLORCWOWDSDDTSW;;;DA)\
+    (A;ID;GA;;;SY)\
+    (A;;RPLCLORC;;;AU)\
+    S:AI(OU;SAFA;CR;0e10c968-78fb-11d2-90d4-00c04f79dc55;;WD)");
+  assert_eq!(sddl.to_bytes().expect("failed to encode sddl"), bytes);
+
+  let computer = [domain.with_rid(515), SID::from_components(5, &[11])];
+  let enroll = sddl.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(&ENROLL), |sid| Ok::<_, ()>(computer.contains(sid))).expect("access check failed");
+  assert!(enroll.is_allowed());
+  let guest = [domain.with_rid(514), domain.with_rid(515)];
+  let enroll = sddl.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(&ENROLL), |sid| Ok::<_, ()>(guest.contains(sid))).expect("access check failed");
+  assert!(!enroll.is_allowed());
+}
+
+#[test]
+fn fixture_truncation()
+{
+  for bytes in [fixture(include_str!("user_template.txt")), fixture(include_str!("machine_template.txt"))]
+  {
+    for length in 0..bytes.len()
+    {
+      assert!(SDDL::new(&bytes[..length]).is_err(), "truncation to {} bytes parsed", length);
+    }
+  }
+}
+
+#[test]
+fn hostile_lengths()
+{
+  // an ace claiming to be smaller than its own header
+  let mut bytes = fixture(include_str!("user_template.txt"));
+  bytes[0x1e] = 0;
+  bytes[0x1f] = 0;
+  assert!(matches!(SDDL::new(&bytes), Err(SDDLError::BadValue { .. })));
+  // an acl claiming to be larger than the descriptor
+  let mut bytes = fixture(include_str!("user_template.txt"));
+  bytes[0x16] = 0xff;
+  bytes[0x17] = 0xff;
+  assert!(matches!(SDDL::new(&bytes), Err(SDDLError::OffsetOutOfBounds { .. })));
+  // a sid with more sub authorities than MS-DTYP allows
+  assert!(matches!(SID::new(&[1, 16, 0, 0, 0, 0, 0, 5]), Err(SDDLError::BadValue { .. })));
+  assert!(SDDL::new(&[1, 0, 0x04, 0x80, 0xff, 0xff, 0xff, 0xff]).is_err());
+}
+
+const BINARY_ACE_TYPES: &[u8] = &[0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13];
+// the compound ace has no text form, and text carries application data only as a condition or a
+// resource attribute
+const TEXT_ACE_TYPES: &[u8] = &[0x00, 0x01, 0x02, 0x03, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x12, 0x13];
+
+fn arbitrary_sid() -> impl Strategy<Value = SID>
+{
+  (0u64..(1 << 48), vec(any::<u32>(), 0..=15)).prop_map(|(authority, sub_authority)| SID::from_components(authority, &sub_authority))
+}
+
+fn arbitrary_attribute_name() -> impl Strategy<Value = String>
+{
+  "[A-Za-z_][A-Za-z0-9_./:]{0,8}".prop_filter("operator names are not attribute names", |name| !["Exists", "Not_Exists", "Member_of", "Device_Member_of", "Member_of_Any", "Device_Member_of_Any", "Not_Member_of", "Not_Device_Member_of", "Not_Member_of_Any", "Not_Device_Member_of_Any"].iter().any(|operator| operator.eq_ignore_ascii_case(name)))
+}
+
+fn arbitrary_condition() -> impl Strategy<Value = Condition>
+{
+  let leaf = prop_oneof![
+    any::<i64>().prop_map(|value| Condition::Literal(ClaimValue::Int64(value))),
+    "[a-zA-Z0-9 _.;()-]{0,8}".prop_map(|value| Condition::Literal(ClaimValue::String(value))),
+    vec(any::<u8>(), 0..8).prop_map(|value| Condition::Literal(ClaimValue::OctetString(value))),
+    arbitrary_sid().prop_map(|sid| Condition::Literal(ClaimValue::Sid(sid))),
+    (select(&[AttributeSource::Local, AttributeSource::User, AttributeSource::Resource, AttributeSource::Device][..]), arbitrary_attribute_name()).prop_map(|(source, name)| Condition::Attribute(source, name))
+  ];
+  leaf.prop_recursive(4, 16, 4, |inner| prop_oneof![
+    vec(inner.clone(), 0..4).prop_map(Condition::Composite),
+    (select(&[UnaryOperator::Not, UnaryOperator::Exists, UnaryOperator::NotExists, UnaryOperator::MemberOf, UnaryOperator::DeviceMemberOf, UnaryOperator::MemberOfAny, UnaryOperator::DeviceMemberOfAny, UnaryOperator::NotMemberOf, UnaryOperator::NotDeviceMemberOf, UnaryOperator::NotMemberOfAny, UnaryOperator::NotDeviceMemberOfAny][..]), inner.clone())
+      .prop_map(|(operator, operand)| Condition::Unary(operator, Box::new(operand))),
+    (select(&[BinaryOperator::Equals, BinaryOperator::NotEquals, BinaryOperator::LessThan, BinaryOperator::LessThanOrEqual, BinaryOperator::GreaterThan, BinaryOperator::GreaterThanOrEqual, BinaryOperator::Contains, BinaryOperator::AnyOf, BinaryOperator::NotContains, BinaryOperator::NotAnyOf, BinaryOperator::And, BinaryOperator::Or][..]), inner.clone(), inner)
+      .prop_map(|(operator, lhs, rhs)| Condition::Binary(operator, Box::new(lhs), Box::new(rhs)))
+  ])
+}
+
+fn arbitrary_resource_attribute() -> impl Strategy<Value = ResourceAttribute>
+{
+  let values = prop_oneof![
+    vec(any::<i64>().prop_map(ClaimValue::Int64), 0..4).prop_map(|values| (0x0001, values)),
+    vec(any::<u64>().prop_map(ClaimValue::Uint64), 0..4).prop_map(|values| (0x0002, values)),
+    vec("[^\"\\x00]{0,8}".prop_map(ClaimValue::String), 0..4).prop_map(|values| (0x0003, values)),
+    vec(arbitrary_sid().prop_map(ClaimValue::Sid), 0..4).prop_map(|values| (0x0005, values)),
+    vec(any::<bool>().prop_map(ClaimValue::Boolean), 0..4).prop_map(|values| (0x0006, values)),
+    vec(vec(any::<u8>(), 0..8).prop_map(ClaimValue::OctetString), 0..4).prop_map(|values| (0x0010, values))
+  ];
+  ("[^\"\\x00]{0,8}", values, any::<u32>())
+    .prop_map(|(name, (value_type, values), flags)| ResourceAttribute { name, value_type, flags, values })
+}
+
+// in text mode application data only survives as a condition on callback aces or an attribute on
+// resource attribute aces
+fn arbitrary_ace(ace_types: &'static [u8], application_data: bool) -> impl Strategy<Value = ACE>
+{
+  let extra = (vec(any::<[u8; 4]>(), 0..4), option::of(arbitrary_condition()), option::of(arbitrary_resource_attribute()));
+  (select(ace_types), any::<u8>(), any::<u32>(), arbitrary_sid(), option::of(any::<u128>()), option::of(any::<u128>()), extra)
+    .prop_map(move |(code, flags, access_mask, subject, object_type, inherited_object_type, (data, condition, attribute))|
+    {
+      let access_mask = AccessMask::from_bits_retain(access_mask);
+      let application_data = match (application_data, code)
+      {
+        (true, _) => data.concat(),
+        (false, 0x09..=0x10) => condition.map(|condition| condition.to_bytes()).unwrap_or_default(),
+        (false, 0x12) => attribute.map(|attribute| attribute.to_bytes()).unwrap_or_default(),
+        (false, _) => vec![]
+      };
+      let mut object_flags = AccessObjectFlags::NONE;
+      if object_type.is_some() { object_flags |= AccessObjectFlags::ACE_OBJECT_TYPE_PRESENT }
+      if inherited_object_type.is_some() { object_flags |= AccessObjectFlags::ACE_INHERITED_OBJECT_TYPE_PRESENT }
+      let object = AccessObject
+      {
+        access_mask: access_mask.clone(),
+        flags: object_flags,
+        object_type: object_type.map(Uuid::from_u128),
+        inherited_object_type: inherited_object_type.map(Uuid::from_u128),
+        subject: subject.clone()
+      };
+      let access = Access { access_mask: access_mask.clone(), subject: subject.clone() };
+      let callback = CallbackAccess { access_mask, subject, application_data: application_data.clone() };
+      let callback_object = CallbackAccessObject { object: object.clone(), application_data: application_data.clone() };
+      let ace_type = match code
+      {
+        0x00 => ACEType::AccessAllowed(access),
+        0x01 => ACEType::AccessDenied(access),
+        0x02 => ACEType::SystemAudit(access),
+        0x03 => ACEType::SystemAlarm(access),
+        0x04 => ACEType::AccessAllowedCompound(application_data),
+        0x05 => ACEType::AccessAllowedObject(object),
+        0x06 => ACEType::AccessDeniedObject(object),
+        0x07 => ACEType::SystemAuditObject(object),
+        0x08 => ACEType::SystemAlarmObject(object),
+        0x09 => ACEType::AccessAllowedCallback(callback),
+        0x0A => ACEType::AccessDeniedCallback(callback),
+        0x0B => ACEType::AccessAllowedCallbackObject(callback_object),
+        0x0C => ACEType::AccessDeniedCallbackObject(callback_object),
+        0x0D => ACEType::SystemAuditCallback(callback),
+        0x0E => ACEType::SystemAlarmCallback(callback),
+        0x0F => ACEType::SystemAuditCallbackObject(callback_object),
+        0x10 => ACEType::SystemAlarmCallbackObject(callback_object),
+        0x11 => ACEType::SystemMandatoryLabel(access),
+        0x12 => ACEType::SystemResourceAttribute(callback),
+        _ => ACEType::SystemScopedPolicyId(access)
+      };
+      ACE { ace_type, flags: ACEFlags::from_bits_retain(flags & 0b1101_1111) }
+    })
+}
+
+fn arbitrary_acl(ace_types: &'static [u8], application_data: bool) -> impl Strategy<Value = Option<(ACL, [bool; 3])>>
+{
+  option::of((vec(arbitrary_ace(ace_types, application_data), 0..8).prop_map(ACL::from), any::<[bool; 3]>()))
+}
+
+fn arbitrary_sddl(ace_types: &'static [u8], application_data: bool) -> impl Strategy<Value = SDDL>
+{
+  (option::of(arbitrary_sid()), option::of(arbitrary_sid()), arbitrary_acl(ace_types, application_data), arbitrary_acl(ace_types, application_data))
+    .prop_map(|(owner, group, sacl, dacl)|
+    {
+      let mut control = ControlFlags::SELF_RELATIVE;
+      let mut present = |acl: &Option<(ACL, [bool; 3])>, flags: [ControlFlags; 4]|
+      {
+        if let Some((_, [protected, inheritance_required, auto_inherited])) = acl
+        {
+          control |= flags[0].clone();
+          control.set(flags[1].clone(), *protected);
+          control.set(flags[2].clone(), *inheritance_required);
+          control.set(flags[3].clone(), *auto_inherited);
+        }
+      };
+      present(&sacl, [ControlFlags::SACL_PRESENT, ControlFlags::SACL_PROTECTED, ControlFlags::SACL_INHERITANCE_REQUIRED, ControlFlags::SACL_AUTO_INHERITED]);
+      present(&dacl, [ControlFlags::DACL_PRESENT, ControlFlags::DACL_PROTECTED, ControlFlags::DACL_INHERITANCE_REQUIRED, ControlFlags::DACL_AUTO_INHERITED]);
+      SDDL { control, owner, group, sacl: sacl.map(|(acl, _)| acl), dacl: dacl.map(|(acl, _)| acl) }
+    })
+}
+
+proptest!
+{
+  #[test]
+  fn prop_binary_round_trip(sddl in arbitrary_sddl(BINARY_ACE_TYPES, true))
+  {
+    let bytes = sddl.to_bytes().expect("failed to encode sddl");
+    prop_assert_eq!(SDDL::new(&bytes).expect("failed to decode sddl"), sddl);
+  }
+
+  #[test]
+  fn prop_text_round_trip(sddl in arbitrary_sddl(TEXT_ACE_TYPES, false))
+  {
+    let text = sddl.to_string();
+    prop_assert_eq!(SDDL::from_str(&text).expect("failed to parse sddl"), sddl);
+  }
+
+  #[test]
+  fn prop_binary_never_panics(bytes in vec(any::<u8>(), 0..512))
+  {
+    let _ = SDDL::new(&bytes);
+    let _ = SID::new(&bytes);
+  }
+
+  #[test]
+  fn prop_corrupted_fixture_never_panics(index in any::<usize>(), value in any::<u8>(), length in any::<usize>())
+  {
+    let mut bytes = fixture(include_str!("machine_template.txt"));
+    let index = index % bytes.len();
+    bytes[index] = value;
+    bytes.truncate(length % (bytes.len() + 1));
+    if let Ok(sddl) = SDDL::new(&bytes)
+    {
+      let _ = sddl.to_string();
+      let _ = sddl.to_bytes();
+    }
+  }
+
+  #[test]
+  fn prop_text_never_panics(text in "[OGDSAPIRNCLWXZUMF0-9a-f:;()\"x \\-]{0,96}")
+  {
+    let _ = SDDL::from_str(&text);
+  }
+}
+
+fn utf16(value: &str) -> Vec<u8>
+{
+  value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn token(tag: u8, payload: &[u8]) -> Vec<u8>
+{
+  [&[tag][..], &(payload.len() as u32).to_le_bytes(), payload].concat()
+}
+
+// MS-DTYP 2.4.4.17 - the signature, the postfix tokens and padding to a multiple of 4
+fn conditional(tokens: &[&[u8]]) -> Vec<u8>
+{
+  let mut result = [&b"artx"[..], &tokens.concat()].concat();
+  result.resize((result.len() + 3) & !3, 0);
+  result
+}
+
+fn callback(allow: bool, subject: SID, application_data: Vec<u8>) -> ACE
+{
+  let callback = CallbackAccess { access_mask: AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, subject, application_data };
+  let ace_type = if allow { ACEType::AccessAllowedCallback(callback) } else { ACEType::AccessDeniedCallback(callback) };
+  ACE { ace_type, flags: ACEFlags::NONE }
+}
+
+fn department_is_sales() -> Vec<u8>
+{
+  conditional(&[&token(0xf9, &utf16("department")), &token(0x10, &utf16("Sales")), &[0x80]])
+}
+
+#[test]
+fn condition_parse_and_evaluate()
+{
+  let condition = Condition::new(&department_is_sales()).expect("failed to parse condition");
+  assert_eq!(condition, Condition::Binary(BinaryOperator::Equals,
+    Box::new(Condition::Attribute(AttributeSource::User, "department".to_owned())),
+    Box::new(Condition::Literal(ClaimValue::String("Sales".to_owned())))));
+
+  let mut never = |_: &SID| Ok::<_, ()>(false);
+  let mut claims = ClaimsContext::default();
+  assert_eq!(condition.evaluate(&claims, &mut never), Ok(None));
+  claims.user.insert("Department".to_owned(), vec![ClaimValue::String("sales".to_owned())]);
+  assert_eq!(condition.evaluate(&claims, &mut never), Ok(Some(true)));
+  claims.user.insert("Department".to_owned(), vec![ClaimValue::String("Engineering".to_owned())]);
+  assert_eq!(condition.evaluate(&claims, &mut never), Ok(Some(false)));
+
+  let administrators = SID::from_components(5, &[32, 544]);
+  let member_of = Condition::new(&conditional(&[&token(0x50, &token(0x51, &administrators.to_bytes())), &[0x89]])).expect("failed to parse condition");
+  assert_eq!(member_of.evaluate(&claims, &mut |sid: &SID| Ok::<_, ()>(*sid == administrators)), Ok(Some(true)));
+  assert_eq!(member_of.evaluate(&claims, &mut never), Ok(Some(false)));
+  let device_member_of = Condition::new(&conditional(&[&token(0x50, &token(0x51, &administrators.to_bytes())), &[0x8a]])).expect("failed to parse condition");
+  assert_eq!(device_member_of.evaluate(&claims, &mut never), Ok(None));
+
+  // (@User.clearance >= 3) || !(Exists @Device.managed)
+  let mut integer = vec![0x03];
+  integer.extend_from_slice(&3u64.to_le_bytes());
+  integer.extend_from_slice(&[0x01, 0x02]);
+  let either = Condition::new(&conditional(&[&token(0xf9, &utf16("clearance")), &integer, &[0x85], &token(0xfb, &utf16("managed")), &[0x87, 0xa2, 0xa1]])).expect("failed to parse condition");
+  assert_eq!(either.evaluate(&claims, &mut never), Ok(Some(true)));
+  claims.device.insert("managed".to_owned(), vec![ClaimValue::Boolean(true)]);
+  assert_eq!(either.evaluate(&claims, &mut never), Ok(None));
+  claims.user.insert("clearance".to_owned(), vec![ClaimValue::Uint64(2)]);
+  assert_eq!(either.evaluate(&claims, &mut never), Ok(Some(false)));
+}
+
+#[test]
+fn condition_rejects_malformed()
+{
+  assert!(Condition::new(b"xtra").is_err());
+  assert!(Condition::new(&conditional(&[&[0x80]])).is_err());
+  assert!(Condition::new(&conditional(&[&token(0x10, &utf16("a")), &token(0x10, &utf16("b"))])).is_err());
+  assert!(Condition::new(&conditional(&[&[0x10, 0xff, 0xff, 0xff, 0xff]])).is_err());
+  assert!(Condition::new(&conditional(&[&[0x7f]])).is_err());
+  let deep: Vec<u8> = std::iter::repeat(0xa2).take(200).collect();
+  assert!(matches!(Condition::new(&conditional(&[&token(0xf9, &utf16("a")), &deep])), Err(SDDLError::BadCondition(_))));
+  let nested = (0..200).fold(vec![], |inner, _| token(0x50, &inner));
+  assert!(matches!(Condition::new(&conditional(&[&nested])), Err(SDDLError::BadCondition(_))));
+}
+
+#[test]
+fn callback_access_check()
+{
+  let everyone = SID::from_components(1, &[0]);
+  let identify = |_: &SID| Ok::<_, ()>(true);
+  let mut sales = ClaimsContext::default();
+  sales.user.insert("department".to_owned(), vec![ClaimValue::String("Sales".to_owned())]);
+  let mut engineering = ClaimsContext::default();
+  engineering.user.insert("department".to_owned(), vec![ClaimValue::String("Engineering".to_owned())]);
+
+  let allow = ACL::from(vec![callback(true, everyone.clone(), department_is_sales())]);
+  let check = |acl: &ACL, claims: &ClaimsContext| acl.access_check_with_claims(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(&ENROLL), claims, identify).expect("access check failed").is_allowed();
+  assert!(check(&allow, &sales));
+  assert!(!check(&allow, &engineering));
+  assert!(!check(&allow, &ClaimsContext::default()));
+
+  // a deny applies unless its condition is known to be false
+  let deny = ACL::from(vec![callback(false, everyone.clone(), department_is_sales()), ACE::allow(everyone.clone(), AccessMask::GENERIC_ALL)]);
+  assert!(!check(&deny, &sales));
+  assert!(check(&deny, &engineering));
+  assert!(!check(&deny, &ClaimsContext::default()));
+
+  // application data that is not a condition never grants
+  let garbage = ACL::from(vec![callback(true, everyone.clone(), vec![1, 2, 3, 4]), callback(true, everyone, conditional(&[&[0x80]]))]);
+  assert!(!check(&garbage, &sales));
+}
+
+#[test]
+fn resource_attribute_ace()
+{
+  // CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1 for ("Project",TS,0x0,"Alpha")
+  let mut attribute = Vec::new();
+  attribute.extend_from_slice(&20u32.to_le_bytes());
+  attribute.extend_from_slice(&3u16.to_le_bytes());
+  attribute.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+  attribute.extend_from_slice(&1u32.to_le_bytes());
+  attribute.extend_from_slice(&36u32.to_le_bytes());
+  attribute.extend(utf16("Project\0"));
+  attribute.extend(utf16("Alpha\0"));
+
+  let everyone = SID::from_components(1, &[0]);
+  let authenticated_users = SID::from_components(5, &[11]);
+  let resource_attribute = ACE
+  {
+    ace_type: ACEType::SystemResourceAttribute(CallbackAccess { access_mask: AccessMask::empty(), subject: everyone, application_data: attribute }),
+    flags: ACEFlags::NONE
+  };
+  let project_is_alpha = conditional(&[&token(0xfa, &utf16("Project")), &token(0x10, &utf16("Alpha")), &[0x80]]);
+  let sddl = SDDL
+  {
+    control: ControlFlags::SELF_RELATIVE | ControlFlags::DACL_PRESENT | ControlFlags::SACL_PRESENT,
+    owner: None,
+    group: None,
+    sacl: Some(ACL::from(vec![resource_attribute])),
+    dacl: Some(ACL::from(vec![callback(true, authenticated_users, project_is_alpha)]))
+  };
+
+  assert_eq!(sddl.resource_attributes(), vec![ResourceAttribute { name: "Project".to_owned(), value_type: 3, flags: 0, values: vec![ClaimValue::String("Alpha".to_owned())] }]);
+  assert!(sddl.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(&ENROLL), |_| Ok::<_, ()>(true)).expect("access check failed").is_allowed());
+  assert_eq!(sddl.to_string(), "D:(XA;;CR;;;AU;(@Resource.Project == \"Alpha\"))S:(RA;;;;;WD;(\"Project\",TS,0x0,\"Alpha\"))");
+  assert_eq!(SDDL::from_str(&sddl.to_string()).expect("failed to parse sddl"), sddl);
+  assert_eq!(SDDL::new(&sddl.to_bytes().expect("failed to encode sddl")).expect("failed to decode sddl"), sddl);
+}
+
+#[test]
+fn conditional_ace_text()
+{
+  // && binds tighter than ||, and rendering parenthesizes every operation
+  let sddl = SDDL::from_str("D:(XA;;CR;;;AU;(@User.Department == \"Sales\" || Member_of {SID(BA)} && !(Exists Title)))").expect("failed to parse sddl");
+  assert_eq!(sddl.to_string(), "D:(XA;;CR;;;AU;((@User.Department == \"Sales\") || ((Member_of {SID(BA)}) && (!(Exists Title)))))");
+  assert!(SDDL::from_str("D:(A;;GA;;;WD;(Title == 1))").is_err());
+  assert!(SDDL::from_str("D:(XA;;CR;;;AU;(Title == ))").is_err());
+}
+
+#[test]
+fn unknown_ace_type()
+{
+  let everyone = SID::from_components(1, &[0]);
+  let sddl = SDDL
+  {
+    control: ControlFlags::SELF_RELATIVE | ControlFlags::DACL_PRESENT,
+    owner: None,
+    group: None,
+    sacl: None,
+    dacl: Some(ACL::from(vec![ACE { ace_type: ACEType::Unknown(0x20, vec![0xff; 12]), flags: ACEFlags::NONE }, ACE::allow(everyone, AccessMask::GENERIC_ALL)]))
+  };
+  let decoded = SDDL::new(&sddl.to_bytes().expect("failed to encode sddl")).expect("failed to decode sddl");
+  assert_eq!(decoded, sddl);
+  assert_eq!(decoded.to_string(), "D:(0x20;;;;;)(A;;GA;;;WD)");
+  assert!(decoded.access_check(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS, Some(&ENROLL), |_| Ok::<_, ()>(true)).expect("access check failed").is_allowed());
+}
@@ -0,0 +1,1090 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use uuid::Uuid;
+
+use super::{SDDL, SDDLError, SID, ACL, ACE, ACEType, ACEFlags, Access, AccessMask, AccessObject, AccessObjectFlags, CallbackAccess, CallbackAccessObject, ControlFlags, Condition, ClaimValue, AttributeSource, UnaryOperator, BinaryOperator, ResourceAttribute, conditional::MAXIMUM_DEPTH};
+
+// MS-DTYP 2.4.2.4 - aliases for sids with a fixed value
+const WELL_KNOWN_SIDS: &[(&str, u64, &[u32])] =
+&[
+  ("WD", 1, &[0]),
+  ("CO", 3, &[0]),
+  ("CG", 3, &[1]),
+  ("OW", 3, &[4]),
+  ("NU", 5, &[2]),
+  ("IU", 5, &[4]),
+  ("SU", 5, &[6]),
+  ("AN", 5, &[7]),
+  ("ED", 5, &[9]),
+  ("PS", 5, &[10]),
+  ("AU", 5, &[11]),
+  ("RC", 5, &[12]),
+  ("SY", 5, &[18]),
+  ("LS", 5, &[19]),
+  ("NS", 5, &[20]),
+  ("WR", 5, &[33]),
+  ("BA", 5, &[32, 544]),
+  ("BU", 5, &[32, 545]),
+  ("BG", 5, &[32, 546]),
+  ("PU", 5, &[32, 547]),
+  ("AO", 5, &[32, 548]),
+  ("SO", 5, &[32, 549]),
+  ("PO", 5, &[32, 550]),
+  ("BO", 5, &[32, 551]),
+  ("RE", 5, &[32, 552]),
+  ("RU", 5, &[32, 554]),
+  ("RD", 5, &[32, 555]),
+  ("NO", 5, &[32, 556]),
+  ("MU", 5, &[32, 558]),
+  ("LU", 5, &[32, 559]),
+  ("IS", 5, &[32, 568]),
+  ("CY", 5, &[32, 569]),
+  ("ER", 5, &[32, 573]),
+  ("CD", 5, &[32, 574]),
+  ("RA", 5, &[32, 575]),
+  ("ES", 5, &[32, 576]),
+  ("MS", 5, &[32, 577]),
+  ("HA", 5, &[32, 578]),
+  ("AA", 5, &[32, 579]),
+  ("RM", 5, &[32, 580]),
+  ("AC", 15, &[2, 1]),
+  ("LW", 16, &[4096]),
+  ("ME", 16, &[8192]),
+  ("MP", 16, &[8448]),
+  ("HI", 16, &[12288]),
+  ("SI", 16, &[16384]),
+  ("AS", 18, &[1]),
+  ("SS", 18, &[2])
+];
+
+// MS-DTYP 2.4.2.4 - aliases for accounts relative to the domain.  the forest root aliases (RO, SA,
+// EA, EK) are resolved against the same domain, which is only correct in the forest root domain
+const DOMAIN_RELATIVE_SIDS: &[(&str, u32)] =
+&[
+  ("RO", 498),
+  ("LA", 500),
+  ("LG", 501),
+  ("DA", 512),
+  ("DU", 513),
+  ("DG", 514),
+  ("DC", 515),
+  ("DD", 516),
+  ("CA", 517),
+  ("SA", 518),
+  ("EA", 519),
+  ("PA", 520),
+  ("CN", 522),
+  ("AP", 525),
+  ("KA", 526),
+  ("EK", 527),
+  ("RS", 553)
+];
+
+// MS-DTYP 2.5.1.1 - in the order Windows renders directory service rights
+const RIGHTS: &[(&str, u32)] =
+&[
+  ("RP", AccessMask::ADS_RIGHT_DS_READ_PROP.bits()),
+  ("WP", AccessMask::ADS_RIGHT_DS_WRITE_PROP.bits()),
+  ("CR", AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS.bits()),
+  ("CC", AccessMask::ADS_RIGHT_DS_CREATE_CHILD.bits()),
+  ("DC", AccessMask::ADS_RIGHT_DS_DELETE_CHILD.bits()),
+  ("LC", AccessMask::ADS_RIGHT_ACTRL_DS_LIST.bits()),
+  ("LO", AccessMask::ADS_RIGHT_DS_LIST_OBJECT.bits()),
+  ("RC", AccessMask::READ_CONTROL.bits()),
+  ("WO", AccessMask::WRITE_OWNER.bits()),
+  ("WD", AccessMask::WRITE_DAC.bits()),
+  ("SD", AccessMask::DELETE.bits()),
+  ("DT", AccessMask::ADS_RIGHT_DS_DELETE_TREE.bits()),
+  ("SW", AccessMask::ADS_RIGHT_DS_SELF.bits()),
+  ("GA", AccessMask::GENERIC_ALL.bits()),
+  ("GX", AccessMask::GENERIC_EXECUTE.bits()),
+  ("GW", AccessMask::GENERIC_WRITE.bits()),
+  ("GR", AccessMask::GENERIC_READ.bits())
+];
+
+// file and registry rights are accepted, but are rendered as the rights they expand to
+const COMPOSITE_RIGHTS: &[(&str, u32)] =
+&[
+  ("FA", 0x001F_01FF),
+  ("FR", 0x0012_0089),
+  ("FW", 0x0012_0116),
+  ("FX", 0x0012_00A0),
+  ("KA", 0x000F_003F),
+  ("KR", 0x0002_0019),
+  ("KW", 0x0002_0006),
+  ("KX", 0x0002_0019)
+];
+
+// MS-DTYP 2.4.4.13 - mandatory label policies reuse the low bits of the mask
+const LABEL_RIGHTS: &[(&str, u32)] =
+&[
+  ("NW", 0x0000_0001),
+  ("NR", 0x0000_0002),
+  ("NX", 0x0000_0004)
+];
+
+const ACE_FLAGS: &[(&str, ACEFlags)] =
+&[
+  ("OI", ACEFlags::OBJECT_INHERIT),
+  ("CI", ACEFlags::CONTAINER_INHERIT),
+  ("NP", ACEFlags::NO_PROPAGATE_INHERITE),
+  ("IO", ACEFlags::INHERIT_ONLY),
+  ("ID", ACEFlags::INHERITED),
+  ("SA", ACEFlags::SUCCESSFUL_ACCESS),
+  ("FA", ACEFlags::FAILED_ACCESS)
+];
+
+// MS-DTYP 2.5.1.1 - the remaining ace types have no alias, and are written as their numeric type
+const ACE_TYPES: &[(&str, u8)] =
+&[
+  ("A", 0x00),
+  ("D", 0x01),
+  ("AU", 0x02),
+  ("AL", 0x03),
+  ("OA", 0x05),
+  ("OD", 0x06),
+  ("OU", 0x07),
+  ("OL", 0x08),
+  ("XA", 0x09),
+  ("XD", 0x0A),
+  ("ZA", 0x0B),
+  ("XU", 0x0D),
+  ("ML", 0x11),
+  ("RA", 0x12),
+  ("SP", 0x13)
+];
+
+// MS-DTYP 2.5.1.1 - conditional expression operators.  not is written as !, and the symbolic
+// relations are matched before the names
+const UNARY_OPERATORS: &[(&str, UnaryOperator)] =
+&[
+  ("Exists", UnaryOperator::Exists),
+  ("Not_Exists", UnaryOperator::NotExists),
+  ("Member_of", UnaryOperator::MemberOf),
+  ("Device_Member_of", UnaryOperator::DeviceMemberOf),
+  ("Member_of_Any", UnaryOperator::MemberOfAny),
+  ("Device_Member_of_Any", UnaryOperator::DeviceMemberOfAny),
+  ("Not_Member_of", UnaryOperator::NotMemberOf),
+  ("Not_Device_Member_of", UnaryOperator::NotDeviceMemberOf),
+  ("Not_Member_of_Any", UnaryOperator::NotMemberOfAny),
+  ("Not_Device_Member_of_Any", UnaryOperator::NotDeviceMemberOfAny)
+];
+
+const BINARY_OPERATORS: &[(&str, BinaryOperator)] =
+&[
+  ("==", BinaryOperator::Equals),
+  ("!=", BinaryOperator::NotEquals),
+  ("<=", BinaryOperator::LessThanOrEqual),
+  (">=", BinaryOperator::GreaterThanOrEqual),
+  ("<", BinaryOperator::LessThan),
+  (">", BinaryOperator::GreaterThan),
+  ("Contains", BinaryOperator::Contains),
+  ("Any_of", BinaryOperator::AnyOf),
+  ("Not_Contains", BinaryOperator::NotContains),
+  ("Not_Any_of", BinaryOperator::NotAnyOf),
+  ("&&", BinaryOperator::And),
+  ("||", BinaryOperator::Or)
+];
+
+const ATTRIBUTE_PREFIXES: &[(&str, AttributeSource)] =
+&[
+  ("@User.", AttributeSource::User),
+  ("@Resource.", AttributeSource::Resource),
+  ("@Device.", AttributeSource::Device)
+];
+
+// MS-DTYP 2.5.1.1 - resource attribute value types
+const CLAIM_TYPES: &[(&str, u16)] =
+&[
+  ("TI", 0x0001),
+  ("TU", 0x0002),
+  ("TS", 0x0003),
+  ("TD", 0x0005),
+  ("TB", 0x0006),
+  ("TX", 0x0010)
+];
+
+impl SDDL
+{
+  // domain relative aliases such as DA can only be rendered and parsed with the domain sid at hand
+  pub fn to_string_with_domain(&self, domain: Option<&SID>) -> String
+  {
+    let renderer = Renderer { domain };
+    let mut result = String::new();
+    if let Some(owner) = &self.owner
+    {
+      result.push_str(&format!("O:{}", renderer.sid(owner)));
+    }
+    if let Some(group) = &self.group
+    {
+      result.push_str(&format!("G:{}", renderer.sid(group)));
+    }
+    if self.control.contains(ControlFlags::DACL_PRESENT) || self.dacl.is_some()
+    {
+      let flags = renderer.acl_flags(&self.control, ControlFlags::DACL_PROTECTED, ControlFlags::DACL_INHERITANCE_REQUIRED, ControlFlags::DACL_AUTO_INHERITED);
+      result.push_str(&format!("D:{}", renderer.acl(flags, self.dacl.as_ref())));
+    }
+    if self.control.contains(ControlFlags::SACL_PRESENT) || self.sacl.is_some()
+    {
+      let flags = renderer.acl_flags(&self.control, ControlFlags::SACL_PROTECTED, ControlFlags::SACL_INHERITANCE_REQUIRED, ControlFlags::SACL_AUTO_INHERITED);
+      result.push_str(&format!("S:{}", renderer.acl(flags, self.sacl.as_ref())));
+    }
+    result
+  }
+
+  pub fn from_str_with_domain(input: &str, domain: Option<&SID>) -> Result<Self, SDDLError>
+  {
+    Parser { input, position: 0, domain }.sddl()
+  }
+}
+
+impl Display for SDDL
+{
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+  {
+    f.write_str(&self.to_string_with_domain(None))
+  }
+}
+
+impl FromStr for SDDL
+{
+  type Err = SDDLError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err>
+  {
+    Self::from_str_with_domain(s, None)
+  }
+}
+
+impl Display for ACE
+{
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result
+  {
+    f.write_str(&Renderer { domain: None }.ace(self))
+  }
+}
+
+// MS-DTYP 2.4.2.1
+impl FromStr for SID
+{
+  type Err = SDDLError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err>
+  {
+    let invalid = || SDDLError::InvalidSyntax { position: 0, message: format!("invalid sid {:?}", s) };
+    let mut components = s
+      .strip_prefix("S-1-")
+      .or_else(|| s.strip_prefix("s-1-"))
+      .ok_or_else(invalid)?
+      .split('-');
+    let authority = components.next().ok_or_else(invalid)?;
+    let authority = match authority.strip_prefix("0x").or_else(|| authority.strip_prefix("0X"))
+    {
+      Some(hex) => u64::from_str_radix(hex, 16),
+      None => authority.parse()
+    }.map_err(|_| invalid())?;
+    let sub_authority = components
+      .map(|component| component.parse::<u32>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| invalid())?;
+    if authority >= 1 << 48 || sub_authority.len() > 15
+    {
+      return Err(invalid());
+    }
+    Ok(SID::from_components(authority, &sub_authority))
+  }
+}
+
+struct Renderer<'a>
+{
+  domain: Option<&'a SID>
+}
+
+impl Renderer<'_>
+{
+  fn sid(&self, sid: &SID) -> String
+  {
+    let well_known = WELL_KNOWN_SIDS
+      .iter()
+      .find(|(_, authority, sub_authority)| SID::from_components(*authority, sub_authority) == *sid)
+      .map(|(alias, _, _)| *alias);
+    let domain_relative = || self.domain.and_then(|domain| DOMAIN_RELATIVE_SIDS
+      .iter()
+      .find(|(_, rid)| domain.with_rid(*rid) == *sid)
+      .map(|(alias, _)| *alias));
+    match well_known.or_else(domain_relative)
+    {
+      Some(alias) => alias.to_owned(),
+      None => sid.to_string()
+    }
+  }
+
+  fn acl_flags(&self, control: &ControlFlags, protected: ControlFlags, inheritance_required: ControlFlags, auto_inherited: ControlFlags) -> String
+  {
+    [("P", protected), ("AR", inheritance_required), ("AI", auto_inherited)]
+      .into_iter()
+      .filter(|(_, flag)| control.contains(flag))
+      .map(|(alias, _)| alias)
+      .collect()
+  }
+
+  fn acl(&self, flags: String, acl: Option<&ACL>) -> String
+  {
+    match acl
+    {
+      // a present but null acl grants everyone everything
+      None => format!("{}NO_ACCESS_CONTROL", flags),
+      Some(acl) => acl.aces().fold(flags, |result, ace| result + &self.ace(ace))
+    }
+  }
+
+  fn ace(&self, ace: &ACE) -> String
+  {
+    let code = ace.ace_type.code();
+    let ace_type = ACE_TYPES
+      .iter()
+      .find(|(_, candidate)| *candidate == code)
+      .map(|(alias, _)| (*alias).to_owned())
+      .unwrap_or_else(|| format!("0x{:02x}", code));
+    let flags: String = ACE_FLAGS
+      .iter()
+      .filter(|(_, flag)| ace.flags.contains(flag.clone()))
+      .map(|(alias, _)| *alias)
+      .collect();
+    let rights = ace.ace_type.access_mask().map(|access_mask| match ace.ace_type
+    {
+      ACEType::SystemMandatoryLabel(_) => Self::rights(access_mask, LABEL_RIGHTS),
+      _ => Self::rights(access_mask, RIGHTS)
+    }).unwrap_or_default();
+    let object = ace.ace_type.object();
+    let guid = |guid: Option<Uuid>| guid.map(|guid| guid.to_string()).unwrap_or_default();
+    let object_type = guid(object.and_then(|object| object.object_type));
+    let inherited_object_type = guid(object.and_then(|object| object.inherited_object_type));
+    let subject = ace.ace_type.subject().map(|subject| self.sid(subject)).unwrap_or_default();
+    // application data that does not parse has no text form, and is left out
+    let extra = match (ace.ace_type.condition(), ace.ace_type.resource_attribute())
+    {
+      (Some(Ok(condition)), _) => format!(";{}", self.condition(&condition)),
+      (_, Some(Ok(attribute))) => format!(";{}", self.resource_attribute(&attribute)),
+      _ => String::new()
+    };
+    format!("({};{};{};{};{};{}{})", ace_type, flags, rights, object_type, inherited_object_type, subject, extra)
+  }
+
+  // the whole expression is parenthesized, as is every operation within it
+  fn condition(&self, condition: &Condition) -> String
+  {
+    match condition
+    {
+      Condition::Unary(_, _) | Condition::Binary(_, _, _) => self.expression(condition),
+      condition => format!("({})", self.expression(condition))
+    }
+  }
+
+  fn expression(&self, condition: &Condition) -> String
+  {
+    match condition
+    {
+      Condition::Literal(value) => self.claim_value(value),
+      Condition::Composite(items) => format!("{{{}}}", items.iter().map(|item| self.expression(item)).collect::<Vec<_>>().join(", ")),
+      Condition::Attribute(source, name) => match ATTRIBUTE_PREFIXES.iter().find(|(_, candidate)| candidate == source)
+      {
+        Some((prefix, _)) => format!("{}{}", prefix, name),
+        None => name.to_owned()
+      },
+      Condition::Unary(UnaryOperator::Not, operand) => format!("(!{})", self.expression(operand)),
+      Condition::Unary(operator, operand) =>
+      {
+        let operator = UNARY_OPERATORS.iter().find(|(_, candidate)| candidate == operator).map(|(name, _)| *name).unwrap_or_default();
+        format!("({} {})", operator, self.expression(operand))
+      },
+      Condition::Binary(operator, lhs, rhs) =>
+      {
+        let operator = BINARY_OPERATORS.iter().find(|(_, candidate)| candidate == operator).map(|(name, _)| *name).unwrap_or_default();
+        format!("({} {} {})", self.expression(lhs), operator, self.expression(rhs))
+      }
+    }
+  }
+
+  fn claim_value(&self, value: &ClaimValue) -> String
+  {
+    match value
+    {
+      ClaimValue::Int64(value) => value.to_string(),
+      ClaimValue::Uint64(value) => value.to_string(),
+      ClaimValue::String(value) => format!("\"{}\"", value),
+      ClaimValue::Sid(sid) => format!("SID({})", self.sid(sid)),
+      ClaimValue::Boolean(value) => (*value as u8).to_string(),
+      ClaimValue::OctetString(value) => format!("#{}", hex::encode(value))
+    }
+  }
+
+  // ("name",type,flags,value,...), with octet strings written as bare hex
+  fn resource_attribute(&self, attribute: &ResourceAttribute) -> String
+  {
+    let value_type = CLAIM_TYPES
+      .iter()
+      .find(|(_, value_type)| *value_type == attribute.value_type)
+      .map(|(alias, _)| (*alias).to_owned())
+      .unwrap_or_else(|| format!("0x{:x}", attribute.value_type));
+    let values = attribute.values.iter().map(|value| match value
+    {
+      ClaimValue::OctetString(value) => hex::encode(value),
+      value => self.claim_value(value)
+    });
+    let fields: Vec<String> = [format!("\"{}\"", attribute.name), value_type, format!("0x{:x}", attribute.flags)]
+      .into_iter()
+      .chain(values)
+      .collect();
+    format!("({})", fields.join(","))
+  }
+
+  fn rights(access_mask: &AccessMask, aliases: &[(&str, u32)]) -> String
+  {
+    let mask = access_mask.bits();
+    let covered = aliases.iter().fold(0, |covered, (_, bits)| covered | bits);
+    if mask & !covered != 0
+    {
+      format!("0x{:x}", mask)
+    }
+    else
+    {
+      aliases
+        .iter()
+        .filter(|(_, bits)| mask & bits != 0)
+        .map(|(alias, _)| *alias)
+        .collect()
+    }
+  }
+}
+
+struct Parser<'a>
+{
+  input: &'a str,
+  position: usize,
+  domain: Option<&'a SID>
+}
+
+impl<'a> Parser<'a>
+{
+  fn error(&self, position: usize, message: impl Into<String>) -> SDDLError
+  {
+    SDDLError::InvalidSyntax { position, message: message.into() }
+  }
+
+  fn rest(&self) -> &'a str
+  {
+    &self.input[self.position..]
+  }
+
+  fn at_section(&self) -> bool
+  {
+    let rest = self.rest().as_bytes();
+    rest.len() >= 2 && matches!(rest[0], b'O' | b'G' | b'D' | b'S') && rest[1] == b':'
+  }
+
+  fn skip_whitespace(&mut self)
+  {
+    self.position += self.rest().len() - self.rest().trim_start().len();
+  }
+
+  fn sddl(mut self) -> Result<SDDL, SDDLError>
+  {
+    let mut sddl = SDDL { control: ControlFlags::SELF_RELATIVE, owner: None, group: None, sacl: None, dacl: None };
+    loop
+    {
+      self.skip_whitespace();
+      if self.rest().is_empty()
+      {
+        return Ok(sddl);
+      }
+      if !self.at_section()
+      {
+        return Err(self.error(self.position, "expected one of O:, G:, D: or S:"));
+      }
+      let section = self.rest().as_bytes()[0];
+      self.position += 2;
+      match section
+      {
+        b'O' => sddl.owner = Some(self.section_sid()?),
+        b'G' => sddl.group = Some(self.section_sid()?),
+        b'D' =>
+        {
+          let (flags, dacl) = self.acl(ControlFlags::DACL_PROTECTED, ControlFlags::DACL_INHERITANCE_REQUIRED, ControlFlags::DACL_AUTO_INHERITED)?;
+          sddl.control |= ControlFlags::DACL_PRESENT | flags;
+          sddl.dacl = dacl;
+        },
+        _ =>
+        {
+          let (flags, sacl) = self.acl(ControlFlags::SACL_PROTECTED, ControlFlags::SACL_INHERITANCE_REQUIRED, ControlFlags::SACL_AUTO_INHERITED)?;
+          sddl.control |= ControlFlags::SACL_PRESENT | flags;
+          sddl.sacl = sacl;
+        }
+      }
+    }
+  }
+
+  fn section_sid(&mut self) -> Result<SID, SDDLError>
+  {
+    let start = self.position;
+    while !self.rest().is_empty() && !self.at_section()
+    {
+      self.position += self.rest().chars().next().map(char::len_utf8).unwrap_or(1);
+    }
+    self.sid(self.input[start..self.position].trim(), start)
+  }
+
+  fn sid(&self, token: &str, position: usize) -> Result<SID, SDDLError>
+  {
+    if token.starts_with("S-") || token.starts_with("s-")
+    {
+      return token.parse().map_err(|_| self.error(position, format!("invalid sid {:?}", token)));
+    }
+    if let Some((_, authority, sub_authority)) = WELL_KNOWN_SIDS.iter().find(|(alias, _, _)| *alias == token)
+    {
+      return Ok(SID::from_components(*authority, sub_authority));
+    }
+    match DOMAIN_RELATIVE_SIDS.iter().find(|(alias, _)| *alias == token)
+    {
+      Some((_, rid)) => self.domain
+        .map(|domain| domain.with_rid(*rid))
+        .ok_or_else(|| SDDLError::DomainRequired(token.to_owned())),
+      None => Err(SDDLError::UnknownAlias(token.to_owned()))
+    }
+  }
+
+  // the flags run up to the first ace, or the next section for an empty acl
+  fn acl(&mut self, protected: ControlFlags, inheritance_required: ControlFlags, auto_inherited: ControlFlags) -> Result<(ControlFlags, Option<ACL>), SDDLError>
+  {
+    let mut flags = ControlFlags::empty();
+    let mut aces = Vec::new();
+    let mut null_acl = false;
+    loop
+    {
+      let rest = self.rest();
+      if let Some(remaining) = rest.strip_prefix("NO_ACCESS_CONTROL")
+      {
+        null_acl = true;
+        self.position += rest.len() - remaining.len();
+      }
+      else if let Some(remaining) = rest.strip_prefix("AR")
+      {
+        flags |= inheritance_required.clone();
+        self.position += rest.len() - remaining.len();
+      }
+      else if let Some(remaining) = rest.strip_prefix("AI")
+      {
+        flags |= auto_inherited.clone();
+        self.position += rest.len() - remaining.len();
+      }
+      else if let Some(remaining) = rest.strip_prefix('P')
+      {
+        flags |= protected.clone();
+        self.position += rest.len() - remaining.len();
+      }
+      else
+      {
+        break;
+      }
+    }
+    loop
+    {
+      self.skip_whitespace();
+      if !self.rest().starts_with('(')
+      {
+        break;
+      }
+      let start = self.position + 1;
+      let end = self.closing_paren()?;
+      aces.push(self.ace(&self.input[start..end], start)?);
+      self.position = end + 1;
+    }
+    if !self.rest().is_empty() && !self.at_section()
+    {
+      return Err(self.error(self.position, "expected an ace or the next section"));
+    }
+    Ok((flags, if null_acl { None } else { Some(ACL(aces)) }))
+  }
+
+  // conditional expressions nest parentheses and may quote them in string literals
+  fn closing_paren(&self) -> Result<usize, SDDLError>
+  {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (offset, character) in self.rest().char_indices()
+    {
+      match character
+      {
+        '"' => quoted = !quoted,
+        '(' if !quoted => depth += 1,
+        ')' if !quoted =>
+        {
+          depth -= 1;
+          if depth == 0
+          {
+            return Ok(self.position + offset);
+          }
+        },
+        _ => {}
+      }
+    }
+    Err(self.error(self.position, "unterminated ace"))
+  }
+
+  // MS-DTYP 2.5.1.1 - ace_type;ace_flags;rights;object_guid;inherit_object_guid;account_sid[;extra]
+  fn ace(&self, input: &str, position: usize) -> Result<ACE, SDDLError>
+  {
+    let fields: Vec<&str> = input.splitn(7, ';').collect();
+    if fields.len() < 6
+    {
+      return Err(self.error(position, format!("expected at least 6 fields in ace, found {}", fields.len())));
+    }
+    let code = self.ace_type(fields[0].trim(), position)?;
+    let flags = self.ace_flags(fields[1].trim(), position)?;
+    let access_mask = self.rights(fields[2].trim(), position)?;
+    let object_type = self.guid(fields[3].trim(), position)?;
+    let inherited_object_type = self.guid(fields[4].trim(), position)?;
+    let subject = self.sid(fields[5].trim(), position)?;
+    // the seventh field is a condition on callback aces, and the attribute on resource attribute aces
+    let application_data = match (fields.get(6), code)
+    {
+      (None, _) => vec![],
+      (Some(extra), 0x09..=0x10) => Expression::new(self, extra, position + input.len() - extra.len()).condition()?.to_bytes(),
+      (Some(extra), 0x12) => Expression::new(self, extra, position + input.len() - extra.len()).resource_attribute()?.to_bytes(),
+      (Some(_), _) => return Err(self.error(position, "only callback and resource attribute aces take a seventh field"))
+    };
+
+    let mut object_flags = AccessObjectFlags::NONE;
+    if object_type.is_some() { object_flags |= AccessObjectFlags::ACE_OBJECT_TYPE_PRESENT }
+    if inherited_object_type.is_some() { object_flags |= AccessObjectFlags::ACE_INHERITED_OBJECT_TYPE_PRESENT }
+    let is_object = matches!(code, 0x05..=0x08 | 0x0B | 0x0C | 0x0F | 0x10);
+    if !is_object && object_flags != AccessObjectFlags::NONE
+    {
+      return Err(self.error(position, "object types are only valid on object aces"));
+    }
+    let object = || AccessObject { access_mask: access_mask.clone(), flags: object_flags.clone(), object_type, inherited_object_type, subject: subject.clone() };
+    let access = || Access { access_mask: access_mask.clone(), subject: subject.clone() };
+    let callback = || CallbackAccess { access_mask: access_mask.clone(), subject: subject.clone(), application_data: application_data.clone() };
+    let callback_object = || CallbackAccessObject { object: object(), application_data: application_data.clone() };
+
+    let ace_type = match code
+    {
+      0x00 => ACEType::AccessAllowed(access()),
+      0x01 => ACEType::AccessDenied(access()),
+      0x02 => ACEType::SystemAudit(access()),
+      0x03 => ACEType::SystemAlarm(access()),
+      0x04 => ACEType::AccessAllowedCompound(vec![]),
+      0x05 => ACEType::AccessAllowedObject(object()),
+      0x06 => ACEType::AccessDeniedObject(object()),
+      0x07 => ACEType::SystemAuditObject(object()),
+      0x08 => ACEType::SystemAlarmObject(object()),
+      0x09 => ACEType::AccessAllowedCallback(callback()),
+      0x0A => ACEType::AccessDeniedCallback(callback()),
+      0x0B => ACEType::AccessAllowedCallbackObject(callback_object()),
+      0x0C => ACEType::AccessDeniedCallbackObject(callback_object()),
+      0x0D => ACEType::SystemAuditCallback(callback()),
+      0x0E => ACEType::SystemAlarmCallback(callback()),
+      0x0F => ACEType::SystemAuditCallbackObject(callback_object()),
+      0x10 => ACEType::SystemAlarmCallbackObject(callback_object()),
+      0x11 => ACEType::SystemMandatoryLabel(access()),
+      0x12 => ACEType::SystemResourceAttribute(callback()),
+      0x13 => ACEType::SystemScopedPolicyId(access()),
+      code => return Err(SDDLError::InvalidACEType(code))
+    };
+    Ok(ACE { ace_type, flags })
+  }
+
+  fn ace_type(&self, token: &str, position: usize) -> Result<u8, SDDLError>
+  {
+    match ACE_TYPES.iter().find(|(alias, _)| *alias == token)
+    {
+      Some((_, code)) => Ok(*code),
+      None => token
+        .strip_prefix("0x")
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| self.error(position, format!("unknown ace type {:?}", token)))
+    }
+  }
+
+  fn ace_flags(&self, token: &str, position: usize) -> Result<ACEFlags, SDDLError>
+  {
+    Self::pairs(token)
+      .map(|pair| ACE_FLAGS
+        .iter()
+        .find(|(alias, _)| *alias == pair)
+        .map(|(_, flag)| flag.clone())
+        .ok_or_else(|| self.error(position, format!("unknown ace flag {:?}", pair))))
+      .try_fold(ACEFlags::NONE, |flags, flag| Ok(flags | flag?))
+  }
+
+  fn rights(&self, token: &str, position: usize) -> Result<AccessMask, SDDLError>
+  {
+    let invalid = || self.error(position, format!("invalid rights {:?}", token));
+    if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X"))
+    {
+      return u32::from_str_radix(hex, 16).map(AccessMask::from_bits_retain).map_err(|_| invalid());
+    }
+    if !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit())
+    {
+      return token.parse().map(AccessMask::from_bits_retain).map_err(|_| invalid());
+    }
+    Self::pairs(token)
+      .map(|pair| RIGHTS
+        .iter()
+        .chain(COMPOSITE_RIGHTS)
+        .chain(LABEL_RIGHTS)
+        .find(|(alias, _)| *alias == pair)
+        .map(|(_, bits)| *bits)
+        .ok_or_else(invalid))
+      .try_fold(0, |mask, bits| Ok(mask | bits?))
+      .map(AccessMask::from_bits_retain)
+  }
+
+  fn guid(&self, token: &str, position: usize) -> Result<Option<Uuid>, SDDLError>
+  {
+    match token
+    {
+      "" => Ok(None),
+      token => Uuid::parse_str(token).map(Some).map_err(|_| self.error(position, format!("invalid guid {:?}", token)))
+    }
+  }
+
+  fn pairs(token: &str) -> impl Iterator<Item = &'_ str>
+  {
+    (0..token.len()).step_by(2).map(move |start| token.get(start..(start + 2).min(token.len())).unwrap_or_default())
+  }
+}
+
+// the text form of a conditional expression or resource attribute, the seventh field of an ace.
+// || binds loosest, then &&, then everything else, though rendered expressions parenthesize every
+// operation anyway
+struct Expression<'p, 'a>
+{
+  parser: &'p Parser<'a>,
+  input: &'a str,
+  start: usize,
+  position: usize
+}
+
+impl<'p, 'a> Expression<'p, 'a>
+{
+  fn new(parser: &'p Parser<'a>, input: &'a str, start: usize) -> Self
+  {
+    Self { parser, input, start, position: 0 }
+  }
+
+  fn error(&self, message: impl Into<String>) -> SDDLError
+  {
+    self.parser.error(self.start + self.position, message)
+  }
+
+  fn rest(&self) -> &'a str
+  {
+    &self.input[self.position..]
+  }
+
+  fn skip_whitespace(&mut self)
+  {
+    self.position += self.rest().len() - self.rest().trim_start().len();
+  }
+
+  fn eat(&mut self, token: &str) -> bool
+  {
+    self.skip_whitespace();
+    let matched = self.rest().get(..token.len()).map(|prefix| prefix.eq_ignore_ascii_case(token)).unwrap_or(false);
+    if matched
+    {
+      self.position += token.len();
+    }
+    matched
+  }
+
+  fn expect(&mut self, token: &str) -> Result<(), SDDLError>
+  {
+    match self.eat(token)
+    {
+      true => Ok(()),
+      false => Err(self.error(format!("expected {:?}", token)))
+    }
+  }
+
+  fn end(&mut self) -> Result<(), SDDLError>
+  {
+    self.skip_whitespace();
+    match self.rest().is_empty()
+    {
+      true => Ok(()),
+      false => Err(self.error("unexpected text after the expression"))
+    }
+  }
+
+  // operator names and attribute names run up to the next character that cannot be part of a name
+  fn word(&self) -> &'a str
+  {
+    let rest = self.rest();
+    let length = rest
+      .find(|character: char| !(character.is_ascii_alphanumeric() || matches!(character, '_' | ':' | '.' | '/')))
+      .unwrap_or(rest.len());
+    &rest[..length]
+  }
+
+  fn condition(mut self) -> Result<Condition, SDDLError>
+  {
+    let condition = self.or(0)?;
+    self.end()?;
+    Ok(condition)
+  }
+
+  fn or(&mut self, depth: usize) -> Result<Condition, SDDLError>
+  {
+    if depth > MAXIMUM_DEPTH
+    {
+      return Err(self.error("expression is nested too deeply"));
+    }
+    let mut lhs = self.and(depth)?;
+    while self.eat("||")
+    {
+      lhs = Condition::Binary(BinaryOperator::Or, Box::new(lhs), Box::new(self.and(depth)?));
+    }
+    Ok(lhs)
+  }
+
+  fn and(&mut self, depth: usize) -> Result<Condition, SDDLError>
+  {
+    let mut lhs = self.unary(depth)?;
+    while self.eat("&&")
+    {
+      lhs = Condition::Binary(BinaryOperator::And, Box::new(lhs), Box::new(self.unary(depth)?));
+    }
+    Ok(lhs)
+  }
+
+  fn unary(&mut self, depth: usize) -> Result<Condition, SDDLError>
+  {
+    if depth > MAXIMUM_DEPTH
+    {
+      return Err(self.error("expression is nested too deeply"));
+    }
+    self.skip_whitespace();
+    if self.rest().starts_with('!') && !self.rest().starts_with("!=")
+    {
+      self.position += 1;
+      return Ok(Condition::Unary(UnaryOperator::Not, Box::new(self.unary(depth + 1)?)));
+    }
+    let word = self.word();
+    if let Some((_, operator)) = UNARY_OPERATORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(word))
+    {
+      self.position += word.len();
+      return Ok(Condition::Unary(*operator, Box::new(self.term(depth + 1)?)));
+    }
+    let lhs = self.term(depth + 1)?;
+    self.skip_whitespace();
+    let word = self.word();
+    let operator = BINARY_OPERATORS
+      .iter()
+      .filter(|(_, operator)| !matches!(operator, BinaryOperator::And | BinaryOperator::Or))
+      .find(|(name, _)| match name.starts_with(|character: char| character.is_ascii_alphabetic())
+      {
+        true => name.eq_ignore_ascii_case(word),
+        false => self.rest().starts_with(name)
+      });
+    match operator
+    {
+      Some((name, operator)) =>
+      {
+        self.position += name.len();
+        Ok(Condition::Binary(*operator, Box::new(lhs), Box::new(self.term(depth + 1)?)))
+      },
+      None => Ok(lhs)
+    }
+  }
+
+  fn term(&mut self, depth: usize) -> Result<Condition, SDDLError>
+  {
+    if depth > MAXIMUM_DEPTH
+    {
+      return Err(self.error("expression is nested too deeply"));
+    }
+    self.skip_whitespace();
+    if self.eat("(")
+    {
+      let condition = self.or(depth + 1)?;
+      self.expect(")")?;
+      return Ok(condition);
+    }
+    if self.eat("{")
+    {
+      let mut items = Vec::new();
+      if !self.eat("}")
+      {
+        loop
+        {
+          items.push(self.or(depth + 1)?);
+          if self.eat("}")
+          {
+            break;
+          }
+          self.expect(",")?;
+        }
+      }
+      return Ok(Condition::Composite(items));
+    }
+    if let Some((prefix, source)) = ATTRIBUTE_PREFIXES.iter().find(|(prefix, _)| self.rest().get(..prefix.len()).map(|candidate| candidate.eq_ignore_ascii_case(prefix)).unwrap_or(false))
+    {
+      self.position += prefix.len();
+      return Ok(Condition::Attribute(*source, self.name()?));
+    }
+    match self.rest().chars().next()
+    {
+      Some(character) if character.is_ascii_alphabetic() || character == '_' =>
+      {
+        match self.rest().get(..4).map(|prefix| prefix.eq_ignore_ascii_case("SID(")).unwrap_or(false)
+        {
+          true => Ok(Condition::Literal(self.value()?)),
+          false => Ok(Condition::Attribute(AttributeSource::Local, self.name()?))
+        }
+      },
+      _ => Ok(Condition::Literal(self.value()?))
+    }
+  }
+
+  fn name(&mut self) -> Result<String, SDDLError>
+  {
+    let name = self.word();
+    if name.is_empty()
+    {
+      return Err(self.error("expected an attribute name"));
+    }
+    self.position += name.len();
+    Ok(name.to_owned())
+  }
+
+  // a literal as written in a condition: a quoted string, SID(...), #hex or an integer
+  fn value(&mut self) -> Result<ClaimValue, SDDLError>
+  {
+    self.skip_whitespace();
+    if self.eat("\"")
+    {
+      let length = self.rest().find('"').ok_or_else(|| self.error("unterminated string"))?;
+      let value = self.rest()[..length].to_owned();
+      self.position += length + 1;
+      return Ok(ClaimValue::String(value));
+    }
+    if self.eat("SID(")
+    {
+      let length = self.rest().find(')').ok_or_else(|| self.error("unterminated sid"))?;
+      let sid = self.parser.sid(self.rest()[..length].trim(), self.start + self.position)?;
+      self.position += length + 1;
+      return Ok(ClaimValue::Sid(sid));
+    }
+    if self.eat("#")
+    {
+      return Ok(ClaimValue::OctetString(self.hex()?));
+    }
+    let (negative, magnitude) = self.integer()?;
+    match negative
+    {
+      false => i64::try_from(magnitude).map(ClaimValue::Int64).map_err(|_| self.error("integer out of range")),
+      true if magnitude <= i64::MAX as u64 + 1 => Ok(ClaimValue::Int64((magnitude as i64).wrapping_neg())),
+      true => Err(self.error("integer out of range"))
+    }
+  }
+
+  fn hex(&mut self) -> Result<Vec<u8>, SDDLError>
+  {
+    let length = self.rest().find(|character: char| !character.is_ascii_hexdigit()).unwrap_or(self.rest().len());
+    let value = hex::decode(&self.rest()[..length]).map_err(|_| self.error("invalid octet string"))?;
+    self.position += length;
+    Ok(value)
+  }
+
+  // MS-DTYP 2.5.1.2.1 - decimal, hex with 0x, or octal with a leading 0
+  fn integer(&mut self) -> Result<(bool, u64), SDDLError>
+  {
+    self.skip_whitespace();
+    let negative = self.rest().starts_with('-');
+    if negative || self.rest().starts_with('+')
+    {
+      self.position += 1;
+    }
+    let rest = self.rest();
+    let (radix, prefix) = match rest.get(..2)
+    {
+      Some("0x") | Some("0X") => (16, 2),
+      _ if rest.len() > 1 && rest.starts_with('0') && rest.as_bytes()[1].is_ascii_digit() => (8, 1),
+      _ => (10, 0)
+    };
+    let digits = &rest[prefix..];
+    let length = digits.find(|character: char| !character.is_digit(radix)).unwrap_or(digits.len());
+    let magnitude = u64::from_str_radix(&digits[..length], radix).map_err(|_| self.error("expected a value"))?;
+    self.position += prefix + length;
+    Ok((negative, magnitude))
+  }
+
+  // ("name",type,flags,value,...)
+  fn resource_attribute(mut self) -> Result<ResourceAttribute, SDDLError>
+  {
+    self.expect("(")?;
+    let name = match self.value()?
+    {
+      ClaimValue::String(name) => name,
+      _ => return Err(self.error("expected a quoted attribute name"))
+    };
+    self.expect(",")?;
+    self.skip_whitespace();
+    let word = self.word();
+    let value_type = match CLAIM_TYPES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(word))
+    {
+      Some((_, value_type)) =>
+      {
+        self.position += word.len();
+        *value_type
+      },
+      None => match self.integer()?
+      {
+        (false, value_type) if value_type <= u16::MAX as u64 => value_type as u16,
+        _ => return Err(self.error("invalid attribute type"))
+      }
+    };
+    self.expect(",")?;
+    let flags = match self.integer()?
+    {
+      (false, flags) if flags <= u32::MAX as u64 => flags as u32,
+      _ => return Err(self.error("invalid attribute flags"))
+    };
+    let mut values = Vec::new();
+    while self.eat(",")
+    {
+      self.skip_whitespace();
+      values.push(match value_type
+      {
+        0x0001 => match self.value()?
+        {
+          value @ ClaimValue::Int64(_) => value,
+          _ => return Err(self.error("expected an integer"))
+        },
+        0x0002 => match self.integer()?
+        {
+          (false, value) => ClaimValue::Uint64(value),
+          _ => return Err(self.error("expected an unsigned integer"))
+        },
+        0x0003 => match self.value()?
+        {
+          value @ ClaimValue::String(_) => value,
+          _ => return Err(self.error("expected a string"))
+        },
+        0x0005 => match self.value()?
+        {
+          value @ ClaimValue::Sid(_) => value,
+          _ => return Err(self.error("expected a sid"))
+        },
+        0x0006 => ClaimValue::Boolean(self.integer()?.1 != 0),
+        0x0010 => ClaimValue::OctetString(self.hex()?),
+        _ => return Err(self.error("values of an unknown attribute type have no text form"))
+      });
+    }
+    self.expect(")")?;
+    self.end()?;
+    Ok(ResourceAttribute { name, value_type, flags, values })
+  }
+}
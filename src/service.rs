@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
+
+use thiserror::Error;
+use tokio::runtime::Runtime;
+use tracing::instrument;
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+
+#[derive(Error, Debug)]
+pub enum ServiceLocatorError
+{
+  #[error("could not start the dns runtime: {0}")]
+  Runtime(std::io::Error),
+
+  #[error("could not read the system dns configuration: {0}")]
+  Configuration(String),
+
+  #[error("dns error: {0}")]
+  Resolve(#[from] ResolveError)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SrvRecord
+{
+  pub priority: u16,
+  pub weight: u16,
+  pub port: u16,
+  pub target: String
+}
+
+// where services are found.  a name with no records is not an error, just an empty result
+pub trait ServiceLocator: Debug + Send + Sync
+{
+  fn srv_lookup(&self, name: &str) -> Result<Vec<SrvRecord>, ServiceLocatorError>;
+  fn ip_lookup(&self, host_name: &str) -> Result<Vec<IpAddr>, ServiceLocatorError>;
+}
+
+pub struct DnsServiceLocator
+{
+  runtime: Runtime,
+  resolver: TokioAsyncResolver
+}
+
+impl Debug for DnsServiceLocator
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+  {
+    f.debug_struct("DnsServiceLocator").finish_non_exhaustive()
+  }
+}
+
+impl DnsServiceLocator
+{
+  fn new(config: ResolverConfig, options: ResolverOpts) -> Result<Self, ServiceLocatorError>
+  {
+    let runtime = Runtime::new().map_err(ServiceLocatorError::Runtime)?;
+    let resolver = TokioAsyncResolver::tokio(config, options)?;
+    Ok(Self { runtime, resolver })
+  }
+
+  // the nameservers and search domains of the host
+  pub fn system() -> Result<Self, ServiceLocatorError>
+  {
+    let (config, options) = trust_dns_resolver::system_conf::read_system_conf().map_err(|err| ServiceLocatorError::Configuration(err.to_string()))?;
+    Self::new(config, options)
+  }
+
+  // queries only the given nameservers, over udp
+  pub fn with_nameservers(nameservers: &[SocketAddr]) -> Result<Self, ServiceLocatorError>
+  {
+    let nameservers: Vec<NameServerConfig> = nameservers.iter().map(|address| NameServerConfig::new(*address, Protocol::Udp)).collect();
+    Self::new(ResolverConfig::from_parts(None, vec![], nameservers), ResolverOpts::default())
+  }
+}
+
+fn is_no_records(err: &ResolveError) -> bool
+{
+  matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+impl ServiceLocator for DnsServiceLocator
+{
+  #[instrument(skip(self))]
+  fn srv_lookup(&self, name: &str) -> Result<Vec<SrvRecord>, ServiceLocatorError>
+  {
+    match self.runtime.block_on(async { self.resolver.srv_lookup(name).await })
+    {
+      Ok(result) => Ok(result
+        .iter()
+        .map(|srv| SrvRecord { priority: srv.priority(), weight: srv.weight(), port: srv.port(), target: srv.target().to_utf8() })
+        .collect()),
+      Err(err) if is_no_records(&err) => Ok(vec![]),
+      Err(err) => Err(err.into())
+    }
+  }
+
+  #[instrument(skip(self))]
+  fn ip_lookup(&self, host_name: &str) -> Result<Vec<IpAddr>, ServiceLocatorError>
+  {
+    match self.runtime.block_on(async { self.resolver.lookup_ip(host_name).await })
+    {
+      Ok(result) => Ok(result.iter().collect()),
+      Err(err) if is_no_records(&err) => Ok(vec![]),
+      Err(err) => Err(err.into())
+    }
+  }
+}
+
+// fixed records, for tests and for networks where dns cannot be relied on.  names are matched
+// without regard to case or a trailing dot
+#[derive(Debug, Clone, Default)]
+pub struct StaticServiceLocator
+{
+  services: HashMap<String, Vec<SrvRecord>>,
+  hosts: HashMap<String, Vec<IpAddr>>
+}
+
+fn normalize(name: &str) -> String
+{
+  name.trim_end_matches('.').to_lowercase()
+}
+
+impl StaticServiceLocator
+{
+  pub fn add_service(&mut self, name: &str, record: SrvRecord) -> &mut Self
+  {
+    self.services.entry(normalize(name)).or_default().push(record);
+    self
+  }
+
+  pub fn add_host(&mut self, host_name: &str, address: IpAddr) -> &mut Self
+  {
+    self.hosts.entry(normalize(host_name)).or_default().push(address);
+    self
+  }
+}
+
+impl ServiceLocator for StaticServiceLocator
+{
+  fn srv_lookup(&self, name: &str) -> Result<Vec<SrvRecord>, ServiceLocatorError>
+  {
+    Ok(self.services.get(&normalize(name)).cloned().unwrap_or_default())
+  }
+
+  fn ip_lookup(&self, host_name: &str) -> Result<Vec<IpAddr>, ServiceLocatorError>
+  {
+    Ok(self.hosts.get(&normalize(host_name)).cloned().unwrap_or_default())
+  }
+}
@@ -41,14 +41,23 @@ pub enum SoapHttpError
 
 pub struct SoapClient
 {
-  http_client: Client
+  http_client: Client,
+  negotiate: bool,
+  principal: Option<String>
 }
 
 impl SoapClient
 {
-  pub fn new() -> Self
+  // negotiates as the given kerberos principal, or with the default credentials when there is none
+  pub fn new(principal: Option<String>) -> Self
   {
-    Self { http_client: Client::new() }
+    Self { http_client: Client::new(), negotiate: true, principal }
+  }
+
+  // for endpoints where authentication is carried in the message itself (i.e. CmsSignature)
+  pub fn without_negotiate() -> Self
+  {
+    Self { http_client: Client::new(), negotiate: false, principal: None }
   }
 
   #[instrument(skip(self, header), err, ret)]
@@ -56,14 +65,29 @@ impl SoapClient
   {
     if let Some(to) = header.get_to()?
     {
-      let mut request = SoapClientRequest::new(&self.http_client, &format!("HTTP/{}", to.host_str().unwrap_or_default()))?;
       let body = body.clone_to_soap(header)?;
-      let response = loop
+      let response = if self.negotiate
+      {
+        let mut request = SoapClientRequest::new(&self.http_client, self.principal.as_deref(), &format!("HTTP/{}", to.host_str().unwrap_or_default()))?;
+        loop
+        {
+          match request.step(to.as_str(), body.clone())?
+          {
+            Some(bytes) => break bytes,
+            None => continue
+          }
+        }
+      }
+      else
       {
-        match request.step(to.as_str(), body.clone())?
+        let response = self.http_client.post(to.as_str())
+          .header(header::CONTENT_TYPE, "application/soap+xml")
+          .body(body)
+          .send()?;
+        match response.status()
         {
-          Some(bytes) => break bytes,
-          None => continue
+          StatusCode::OK => response.bytes()?,
+          status => return Err(SoapHttpError::InvalidHttpResponse(status))
         }
       };
       event!(Level::DEBUG, "{}", String::from_utf8_lossy(&response));
@@ -85,9 +109,9 @@ struct SoapClientRequest<'a>
 
 impl<'a> SoapClientRequest<'a>
 {
-  fn new(http_client: &'a Client, spn: &str) -> Result<Self, SoapHttpError>
+  fn new(http_client: &'a Client, principal: Option<&str>, spn: &str) -> Result<Self, SoapHttpError>
   {
-    let (client, token) = ClientCtx::new(InitiateFlags::empty(), None, spn, None)?;
+    let (client, token) = ClientCtx::new(InitiateFlags::empty(), principal, spn, None)?;
     Ok(Self
     {
       http_client,
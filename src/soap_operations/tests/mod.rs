@@ -4,6 +4,10 @@ use crate::soap::SoapBody;
 use crate::soap::HeaderBuilder;
 use crate::soap::EndpointReferenceBuilder;
 use crate::soap_operations::wstrust::RequestSecurityToken;
+use crate::soap_operations::wstrust::RequestSecurityTokenResponseCollection;
+use crate::EnrollmentResponse;
+use crate::PendingRequest;
+use crate::Transport;
 use super::xcep::GetPoliciesRequest;
 use super::xcep::GetPoliciesResponse;
 use test_log::test;
@@ -28,6 +32,23 @@ fn round_trip()
   assert_eq!(body, new_body);
 }
 
+#[test]
+fn query_round_trip()
+{
+  let header = HeaderBuilder::default()
+    .action("http://schemas.microsoft.com/windows/pki/2009/01/enrollment/RST/wstep".to_owned())
+    .message_id(format!("urn:uuid:{}", Uuid::new_v4()))
+    .build().expect("error building header");
+  let body = RequestSecurityToken::query(43);
+  let envelope = body.clone_to_soap(&header).expect("failed to create soap envelope");
+  assert!(envelope.contains("QueryTokenStatus"));
+  assert!(!envelope.contains("BinarySecurityToken"));
+
+  let (_, new_body) = RequestSecurityToken::from_soap(envelope.as_bytes()).expect("failed to reparse soap envelope");
+
+  assert_eq!(body, new_body);
+}
+
 #[test]
 fn parse_known_wstep()
 {
@@ -35,6 +56,52 @@ fn parse_known_wstep()
   RequestSecurityToken::from_soap(known.as_bytes()).expect("failed to parse known good wstep message");
 }
 
+#[test]
+fn parse_known_wstep_response()
+{
+  let known = include_str!("wstep_response.xml");
+  let (_, response) = RequestSecurityTokenResponseCollection::from_soap(known.as_bytes()).expect("failed to parse known good wstep response");
+  match EnrollmentResponse::try_from(response).expect("failed to decode known good wstep response")
+  {
+    EnrollmentResponse::Issued { entity, chain } =>
+    {
+      assert_eq!(entity.subject_common_name(), Some("host.contoso.com".to_owned()));
+      assert_eq!(chain.len(), 1);
+    },
+    _ => panic!("known good wstep response was not issued")
+  }
+}
+
+#[test]
+fn parse_known_wstep_pending()
+{
+  let known = include_str!("wstep_pending.xml");
+  let (_, response) = RequestSecurityTokenResponseCollection::from_soap(known.as_bytes()).expect("failed to parse known good wstep response");
+  match EnrollmentResponse::try_from(response).expect("failed to decode known good wstep response")
+  {
+    EnrollmentResponse::Pending(pending) => assert_eq!(pending.request_id(), 43),
+    _ => panic!("known good wstep response was not pending")
+  }
+}
+
+#[test]
+fn pending_request_cookie()
+{
+  let endpoint = "https://ca.contoso.com/contoso-CA_CES_Kerberos/service.svc/CES".parse().expect("bad endpoint");
+  let response = EnrollmentResponse::Pending(PendingRequest::new(43)).located("contoso-CA", Transport::Https(endpoint), "Web Server");
+  match response
+  {
+    EnrollmentResponse::Pending(pending) =>
+    {
+      let cookie = pending.to_string();
+      assert_eq!(cookie.parse::<PendingRequest>().expect("failed to parse cookie"), pending);
+    },
+    _ => panic!("pending response was not pending")
+  }
+  assert!("43".parse::<PendingRequest>().is_err());
+  assert!("id=43&rpc=ca.contoso.com".parse::<PendingRequest>().is_err());
+}
+
 #[test]
 fn parse_known_xcep_request()
 {
@@ -48,7 +115,10 @@ fn parse_known_xcep_response()
   let known = include_str!("xcep_response.xml");
   let (header, response) = GetPoliciesResponse::from_soap(known.as_bytes()).expect("failed to parse known good xcep message");
   let xml = response.clone_to_soap(&header.expect("no header")).expect("failed to serialize known good message");
-  let policy = response.into_policy();
+  let policy = response.into_policy(vec![]);
   println!("{}", xml);
-  assert_eq!(policy.templates.len(), 1);
+  let templates: Vec<_> = policy.get_templates().collect();
+  assert_eq!(templates.len(), 1);
+  assert_eq!(templates[0].get_validity_period(), Some(std::time::Duration::from_secs(31536000)));
+  assert_eq!(templates[0].get_key_requirements().minimal_key_length, 2048);
 }
\ No newline at end of file
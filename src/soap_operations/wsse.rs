@@ -26,4 +26,11 @@ impl From<&[u8]> for BinarySecurityTokenType
       encoding_type: Some("http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd#base64binary".to_owned())
     }
   }
-}
\ No newline at end of file
+}
+impl BinarySecurityTokenType
+{
+  pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError>
+  {
+    general_purpose::STANDARD.decode(self.content.split_whitespace().collect::<String>())
+  }
+}
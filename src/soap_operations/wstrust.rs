@@ -1,5 +1,6 @@
+use x509_certificate::X509Certificate;
 use yaserde_derive::{YaDeserialize, YaSerialize};
-use crate::EnrollmentResponse;
+use crate::{EnrollmentResponse, PendingRequest, DecodeError, cmc::CmcResponse};
 
 use super::wsse::BinarySecurityTokenType;
 
@@ -14,19 +15,65 @@ pub struct RequestSecurityToken
 impl RequestSecurityToken
 {
   pub fn new(request: &[u8], request_id: impl Into<Option<String>>) -> Self
+  {
+    Self::with_request_type(request, request_id, "http://docs.oasis-open.org/ws-sx/ws-trust/200512/Issue")
+  }
+
+  // MS-WSTEP 3.1.4.1.2.1 - renewal requests are signed by the certificate being renewed
+  pub fn renew(request: &[u8]) -> Self
+  {
+    Self::with_request_type(request, None, "http://docs.oasis-open.org/ws-sx/ws-trust/200512/Renew")
+  }
+
+  fn with_request_type(request: &[u8], request_id: impl Into<Option<String>>, request_type: &str) -> Self
   {
     Self
     {
       request: RequestSecurityTokenType
       {
         token_type: "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-x509-token-profile-1.0#X509v3".to_owned(),
-        request_type: "http://docs.oasis-open.org/ws-sx/ws-trust/200512/Issue".to_owned(),
-        binary_security_token: BinarySecurityTokenType::from(request),
+        request_type: request_type.to_owned(),
+        binary_security_token: Some(BinarySecurityTokenType::from(request)),
+        request_ket: None,
         request_id: request_id.into(),
         context: None
       }
     }
   }
+
+  // MS-WSTEP 3.1.4.2.2.1 - the key exchange token is the CA exchange certificate, used for key archival
+  pub fn key_exchange_token() -> Self
+  {
+    Self
+    {
+      request: RequestSecurityTokenType
+      {
+        token_type: "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-x509-token-profile-1.0#X509v3".to_owned(),
+        request_type: "http://docs.oasis-open.org/ws-sx/ws-trust/200512/KET".to_owned(),
+        binary_security_token: None,
+        request_ket: Some(RequestKetType::default()),
+        request_id: None,
+        context: None
+      }
+    }
+  }
+
+  // MS-WSTEP 3.1.4.1.2.1 - a query carries only the RequestID of the pending request
+  pub fn query(request_id: u32) -> Self
+  {
+    Self
+    {
+      request: RequestSecurityTokenType
+      {
+        token_type: "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-x509-token-profile-1.0#X509v3".to_owned(),
+        request_type: "http://schemas.microsoft.com/windows/pki/2009/01/enrollment/QueryTokenStatus".to_owned(),
+        binary_security_token: None,
+        request_ket: None,
+        request_id: Some(request_id.to_string()),
+        context: None
+      }
+    }
+  }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
@@ -40,7 +87,10 @@ struct RequestSecurityTokenType
   request_type: String,
 
   #[yaserde(rename = "BinarySecurityToken", prefix = "wsse")]
-  binary_security_token: BinarySecurityTokenType,
+  binary_security_token: Option<BinarySecurityTokenType>,
+
+  #[yaserde(rename = "RequestKET", prefix = "wst")]
+  request_ket: Option<RequestKetType>,
 
   #[yaserde(rename = "RequestID", prefix = "wstep")]
   request_id: Option<String>,
@@ -51,31 +101,48 @@ struct RequestSecurityTokenType
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
 #[yaserde(prefix = "wst", namespace = "wst: http://docs.oasis-open.org/ws-sx/ws-trust/200512")]
+struct RequestKetType {}
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(prefix = "wst", namespace = "wst: http://docs.oasis-open.org/ws-sx/ws-trust/200512", namespace = "wstep: http://schemas.microsoft.com/windows/pki/2009/01/enrollment", namespace = "wsse: http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd")]
 struct RequestSecurityTokenResponseType
 {
   #[yaserde(rename = "TokenType", prefix = "wst")]
-  token_type: String,
+  token_type: Option<String>,
 
-  #[yaserde(rename = "DispositionMessage", prefix = "wst")]
-  disposition_message: String,
+  #[yaserde(rename = "DispositionMessage", prefix = "wstep")]
+  disposition_message: Option<String>,
 
-  #[yaserde(rename = "BinarySecurityToken", prefix = "wst")]
-  binary_security_token: BinarySecurityTokenType,
+  #[yaserde(rename = "BinarySecurityToken", prefix = "wsse")]
+  binary_security_token: Option<BinarySecurityTokenType>,
 
   #[yaserde(rename = "RequestedSecurityToken", prefix = "wst")]
-  requested_security_token: RequestedSecurityTokenType,
+  requested_security_token: Option<RequestedSecurityTokenType>,
 
-  #[yaserde(rename = "RequestID", prefix = "wst")]
-  request_id: String,
+  #[yaserde(rename = "RequestID", prefix = "wstep")]
+  request_id: Option<String>,
 
   #[yaserde(attribute, rename = "Context")]
   context: Option<String>
 }
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
-#[yaserde(prefix = "wst", namespace = "wst: http://docs.oasis-open.org/ws-sx/ws-trust/200512")]
+#[yaserde(prefix = "wst", namespace = "wst: http://docs.oasis-open.org/ws-sx/ws-trust/200512", namespace = "wsse: http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd")]
 struct RequestedSecurityTokenType
 {
+  #[yaserde(rename = "BinarySecurityToken", prefix = "wsse")]
+  binary_security_token: Option<BinarySecurityTokenType>,
+
+  #[yaserde(rename = "KeyExchangeToken", prefix = "wst")]
+  key_exchange_token: Option<KeyExchangeTokenType>
+}
+
+#[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(prefix = "wst", namespace = "wst: http://docs.oasis-open.org/ws-sx/ws-trust/200512", namespace = "wsse: http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd")]
+struct KeyExchangeTokenType
+{
+  #[yaserde(rename = "BinarySecurityToken", prefix = "wsse")]
+  binary_security_token: Option<BinarySecurityTokenType>
 }
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
@@ -94,10 +161,62 @@ pub struct RequestSecurityTokenResponseCollection
   inner: RequestSecurityTokenResponseCollectionInner
 }
 
-impl From<RequestSecurityTokenResponseCollection> for EnrollmentResponse
+impl RequestSecurityTokenResponseCollection
 {
-  fn from(value: RequestSecurityTokenResponseCollection) -> Self
+  // MS-WSTEP 3.1.4.2.2.1 - the exchange certificate is wrapped in a KeyExchangeToken
+  pub fn into_key_exchange_token(self) -> Result<X509Certificate, DecodeError>
   {
-    todo!()
+    let token = self.inner.request_security_token_responses
+      .into_iter()
+      .filter_map(|response| response.requested_security_token)
+      .filter_map(|token| token.key_exchange_token)
+      .find_map(|token| token.binary_security_token)
+      .ok_or(DecodeError::EmptyResponse)?;
+    Ok(X509Certificate::from_der(token.decode()?)?)
   }
-}
\ No newline at end of file
+}
+
+// MS-WSTEP 3.1.4.1.2.2: an issued certificate is carried in RequestedSecurityToken, while a
+// request held for approval only carries its RequestID
+impl TryFrom<RequestSecurityTokenResponseCollection> for EnrollmentResponse
+{
+  type Error = DecodeError;
+
+  fn try_from(value: RequestSecurityTokenResponseCollection) -> Result<Self, Self::Error>
+  {
+    let response = value.inner.request_security_token_responses
+      .into_iter()
+      .next()
+      .ok_or(DecodeError::EmptyResponse)?;
+    let issued = response.requested_security_token.and_then(|token| token.binary_security_token);
+    let cmc_response = match response.binary_security_token
+    {
+      Some(pkcs7) => Some(CmcResponse::try_from(pkcs7.decode()?)?),
+      None => None
+    };
+    match (issued, response.request_id)
+    {
+      (Some(issued), _) =>
+      {
+        let entity = X509Certificate::from_der(issued.decode()?)?;
+        let chain = cmc_response
+          .map(CmcResponse::into_certificates)
+          .unwrap_or_default()
+          .into_iter()
+          .filter(|certificate| *certificate != entity)
+          .collect();
+        Ok(EnrollmentResponse::Issued { entity, chain })
+      },
+      (None, Some(request_id)) => match request_id.trim().parse()
+      {
+        Ok(request_id) => Ok(EnrollmentResponse::Pending(PendingRequest::new(request_id))),
+        Err(_) => Err(DecodeError::BadRequestId(request_id))
+      },
+      (None, None) => match cmc_response
+      {
+        Some(cmc_response) => cmc_response.try_into(),
+        None => Ok(EnrollmentResponse::Rejected(response.disposition_message.unwrap_or_default()))
+      }
+    }
+  }
+}
@@ -1,4 +1,4 @@
-use std::{convert::Infallible, str::FromStr};
+use std::{convert::Infallible, str::FromStr, time::Duration};
 
 use base64::{engine::general_purpose, Engine};
 use bcder::{decode::DecodeError, Oid};
@@ -10,7 +10,7 @@ use x509_certificate::X509Certificate;
 use yaserde_derive::{YaDeserialize, YaSerialize};
 use num_traits::FromPrimitive;
 
-use crate::{NamedCertificate, client::{EnrollmentService, Policy, HttpsEndpoint}, cmc::rfc5272::AttributeValue, ClientAuthentication};
+use crate::{NamedCertificate, client::{EnrollmentService, Policy, HttpsEndpoint, CertificateTemplateBuilder}, cmc::rfc5272::AttributeValue, ClientAuthentication, template::{SubjectNameFlags, EnrollmentFlags, PrivateKeyFlags, GeneralFlags, KeyRequirements, KeySpec, RaRequirements, KeyArchivalAttributes}};
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
 #[yaserde(prefix = "xcep", namespace = "xcep: http://schemas.microsoft.com/windows/pki/2009/01/enrollmentpolicy")]
@@ -126,11 +126,16 @@ impl GetPoliciesResponseInner
   #[instrument(skip_all)]
   pub fn into_policy(self, root_certificates: Vec<NamedCertificate>) -> Policy
   {
+    let resolve_oid = |oid_id: i32| self.oids.definitions
+      .iter()
+      .find(|oid| oid.id == oid_id)
+      .and_then(|oid| Oid::from_str(&oid.value).ok());
     let templates: Vec<_> = self.response.templates.templates
       .into_iter()
       .map(|template|
       {
-        let extensions = template.attributes.extensions.extensions.into_iter()
+        let attributes = template.attributes;
+        let extensions = attributes.extensions.extensions.into_iter()
           .group_by(|extension| extension.extension_definition_id).into_iter()
           .map(|(oid_id, extensions)|
           {
@@ -148,8 +153,50 @@ impl GetPoliciesResponseInner
             }
           })
           .collect::<Result<Vec<_>, _>>()?;
-        let permission = template.attributes.permission.unwrap_or_default();
-        Ok((template.certificate_authorities.ids, crate::CertificateTemplate::new(template.attributes.common_name, permission.enroll, permission.auto_enroll, extensions)))
+        let permission = attributes.permission.unwrap_or_default();
+        let revision = attributes.revision.unwrap_or_default();
+        let certificate_template = CertificateTemplateBuilder::default()
+          .cn(attributes.common_name)
+          .enroll(permission.enroll)
+          .auto_enroll(permission.auto_enroll)
+          .extensions(extensions)
+          .template_oid(resolve_oid(template.policy_oid_reference))
+          .schema_version(attributes.policy_schema)
+          .revision(revision.major_revision)
+          .minor_revision(revision.minor_revision)
+          .superseded_templates(attributes.superseded_policies.common_names)
+          .subject_name_flags(SubjectNameFlags::from_bits_retain(attributes.subject_name_flags))
+          .enrollment_flags(EnrollmentFlags::from_bits_retain(attributes.enrollment_flags))
+          .private_key_flags(PrivateKeyFlags::from_bits_retain(attributes.private_key_flags))
+          .general_flags(GeneralFlags::from_bits_retain(attributes.general_flags))
+          .key_requirements(attributes.private_key_attributes
+            .map(|private_key_attributes| KeyRequirements
+            {
+              minimal_key_length: private_key_attributes.minimal_key_length,
+              algorithm: resolve_oid(private_key_attributes.algorithm_oid_reference),
+              key_spec: KeySpec::from_u32(private_key_attributes.key_spec),
+              crypto_providers: private_key_attributes.crypto_providers.providers
+            })
+            .unwrap_or_default())
+          .hash_algorithm(resolve_oid(attributes.hash_algorithm_oid_reference))
+          .validity_period(attributes.certificate_validity.as_ref().map(|validity| Duration::from_secs(validity.validity_period_seconds)))
+          .renewal_period(attributes.certificate_validity.as_ref().map(|validity| Duration::from_secs(validity.renewal_period_seconds)))
+          .ra_requirements(RaRequirements
+          {
+            signatures: attributes.r_a_requirements.r_a_signatures,
+            application_policies: attributes.r_a_requirements.r_aek_us.o_id_references.iter().filter_map(|id| resolve_oid(*id)).collect(),
+            issuance_policies: attributes.r_a_requirements.r_a_policies.o_id_references.iter().filter_map(|id| resolve_oid(*id)).collect()
+          })
+          .key_archival(PrivateKeyFlags::from_bits_retain(attributes.private_key_flags)
+            .contains(PrivateKeyFlags::REQUIRE_PRIVATE_KEY_ARCHIVAL)
+            .then(|| KeyArchivalAttributes
+            {
+              symmetric_algorithm: resolve_oid(attributes.key_archival_attributes.symmetric_algorithm_oid_reference),
+              symmetric_key_length: attributes.key_archival_attributes.symmetric_algorithm_key_length
+            }))
+          .build()
+          .map_err(|err| err.to_string())?;
+        Ok((template.certificate_authorities.ids, certificate_template))
       })
       .filter_map(|r| r.map_err(|e: DecodeError<_>| event!(Level::WARN, "invalid template: {}", e)).ok())
       .collect();
@@ -269,10 +316,10 @@ struct Attributes
   hash_algorithm_oid_reference: i32,
 
   #[yaserde(rename = "rARequirements", prefix = "xcep")]
-  r_a_requirements: RaRequirements,
+  r_a_requirements: RaRequirementsType,
 
   #[yaserde(rename = "keyArchivalAttributes", prefix = "xcep")]
-  key_archival_attributes: KeyArchivalAttributes,
+  key_archival_attributes: KeyArchivalAttributesType,
 
   #[yaserde(rename = "extensions", prefix = "xcep")]
   extensions: ExtensionsType
@@ -352,7 +399,7 @@ struct CommonNamesType
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
 #[yaserde(prefix = "xcep", namespace = "xcep: http://schemas.microsoft.com/windows/pki/2009/01/enrollmentpolicy")]
-struct RaRequirements
+struct RaRequirementsType
 {
   #[yaserde(rename = "rASignatures", prefix = "xcep")]
   r_a_signatures: u32,
@@ -374,7 +421,7 @@ struct OidReferencesType
 
 #[derive(Clone, Debug, Default, PartialEq, YaDeserialize, YaSerialize)]
 #[yaserde(prefix = "xcep", namespace = "xcep: http://schemas.microsoft.com/windows/pki/2009/01/enrollmentpolicy")]
-struct KeyArchivalAttributes
+struct KeyArchivalAttributesType
 {
   #[yaserde(rename = "symmetricAlgorithmOIDReference", prefix = "xcep")]
   symmetric_algorithm_oid_reference: i32,
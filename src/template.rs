@@ -0,0 +1,137 @@
+use bcder::Oid;
+use bitflags::bitflags;
+use num_derive::FromPrimitive;
+
+bitflags!
+{
+  // MS-CRTD 2.28
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+  pub struct SubjectNameFlags: u32
+  {
+    const ENROLLEE_SUPPLIES_SUBJECT                = 0x0000_0001;
+    const ADD_EMAIL                                = 0x0000_0002;
+    const ADD_OBJ_GUID                             = 0x0000_0004;
+    const OLD_CERT_SUPPLIES_SUBJECT_AND_ALT_NAME   = 0x0000_0008;
+    const ADD_DIRECTORY_PATH                       = 0x0000_0100;
+    const ENROLLEE_SUPPLIES_SUBJECT_ALT_NAME       = 0x0001_0000;
+    const SUBJECT_ALT_REQUIRE_DOMAIN_DNS           = 0x0040_0000;
+    const SUBJECT_ALT_REQUIRE_SPN                  = 0x0080_0000;
+    const SUBJECT_ALT_REQUIRE_DIRECTORY_GUID       = 0x0100_0000;
+    const SUBJECT_ALT_REQUIRE_UPN                  = 0x0200_0000;
+    const SUBJECT_ALT_REQUIRE_EMAIL                = 0x0400_0000;
+    const SUBJECT_ALT_REQUIRE_DNS                  = 0x0800_0000;
+    const SUBJECT_REQUIRE_DNS_AS_CN                = 0x1000_0000;
+    const SUBJECT_REQUIRE_EMAIL                    = 0x2000_0000;
+    const SUBJECT_REQUIRE_COMMON_NAME              = 0x4000_0000;
+    const SUBJECT_REQUIRE_DIRECTORY_PATH           = 0x8000_0000;
+  }
+}
+
+bitflags!
+{
+  // MS-CRTD 2.26
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+  pub struct EnrollmentFlags: u32
+  {
+    const INCLUDE_SYMMETRIC_ALGORITHMS                                    = 0x0000_0001;
+    const PEND_ALL_REQUESTS                                               = 0x0000_0002;
+    const PUBLISH_TO_KRA_CONTAINER                                        = 0x0000_0004;
+    const PUBLISH_TO_DS                                                   = 0x0000_0008;
+    const AUTO_ENROLLMENT_CHECK_USER_DS_CERTIFICATE                       = 0x0000_0010;
+    const AUTO_ENROLLMENT                                                 = 0x0000_0020;
+    const PREVIOUS_APPROVAL_VALIDATE_REENROLLMENT                         = 0x0000_0040;
+    const USER_INTERACTION_REQUIRED                                       = 0x0000_0100;
+    const REMOVE_INVALID_CERTIFICATE_FROM_PERSONAL_STORE                  = 0x0000_0400;
+    const ALLOW_ENROLL_ON_BEHALF_OF                                       = 0x0000_0800;
+    const ADD_OCSP_NOCHECK                                                = 0x0000_1000;
+    const ENABLE_KEY_REUSE_ON_NT_TOKEN_KEYSET_STORAGE_FULL                = 0x0000_2000;
+    const NO_REVOCATION_INFO_IN_ISSUED_CERTS                              = 0x0000_4000;
+    const INCLUDE_BASIC_CONSTRAINTS_FOR_EE_CERTS                          = 0x0000_8000;
+    const ALLOW_PREVIOUS_APPROVAL_KEY_BASED_RENEWAL_VALIDATE_REENROLLMENT = 0x0001_0000;
+    const ISSUANCE_POLICIES_FROM_REQUEST                                  = 0x0002_0000;
+    const SKIP_AUTO_RENEWAL                                               = 0x0004_0000;
+    const NO_SECURITY_EXTENSION                                           = 0x0008_0000;
+  }
+}
+
+bitflags!
+{
+  // MS-CRTD 2.27, the client and server version nibbles are kept as unknown bits
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+  pub struct PrivateKeyFlags: u32
+  {
+    const REQUIRE_PRIVATE_KEY_ARCHIVAL          = 0x0000_0001;
+    const EXPORTABLE_KEY                        = 0x0000_0010;
+    const STRONG_KEY_PROTECTION_REQUIRED        = 0x0000_0020;
+    const REQUIRE_ALTERNATE_SIGNATURE_ALGORITHM = 0x0000_0040;
+    const REQUIRE_SAME_KEY_RENEWAL              = 0x0000_0080;
+    const USE_LEGACY_PROVIDER                   = 0x0000_0100;
+    const EK_TRUST_ON_USE                       = 0x0000_0200;
+    const EK_VALIDATE_CERT                      = 0x0000_0400;
+    const EK_VALIDATE_KEY                       = 0x0000_0800;
+    const ATTEST_PREFERRED                      = 0x0000_1000;
+    const ATTEST_REQUIRED                       = 0x0000_2000;
+    const ATTESTATION_WITHOUT_POLICY            = 0x0000_4000;
+    const HELLO_LOGON_KEY                       = 0x0020_0000;
+  }
+}
+
+bitflags!
+{
+  // MS-CRTD 2.4
+  #[repr(transparent)]
+  #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+  pub struct GeneralFlags: u32
+  {
+    const ADD_EMAIL            = 0x0000_0002;
+    const PUBLISH_TO_DS        = 0x0000_0008;
+    const EXPORTABLE_KEY       = 0x0000_0010;
+    const AUTO_ENROLLMENT      = 0x0000_0020;
+    const MACHINE_TYPE         = 0x0000_0040;
+    const IS_CA                = 0x0000_0080;
+    const ADD_TEMPLATE_NAME    = 0x0000_0200;
+    const DO_NOT_PERSIST_IN_DB = 0x0000_0400;
+    const IS_CROSS_CA          = 0x0000_0800;
+    const IS_DEFAULT           = 0x0001_0000;
+    const IS_MODIFIED          = 0x0002_0000;
+  }
+}
+
+// MS-XCEP 3.1.4.1.3.20
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum KeySpec
+{
+  KeyExchange = 1,
+  Signature = 2
+}
+
+// MS-XCEP 3.1.4.1.3.20
+#[derive(Debug, Clone, Default)]
+pub struct KeyRequirements
+{
+  pub minimal_key_length: u32,
+  pub algorithm: Option<Oid>,
+  pub key_spec: Option<KeySpec>,
+  pub crypto_providers: Vec<String>
+}
+
+// MS-XCEP 3.1.4.1.3.21
+#[derive(Debug, Clone, Default)]
+pub struct RaRequirements
+{
+  pub signatures: u32,
+  pub application_policies: Vec<Oid>,
+  pub issuance_policies: Vec<Oid>
+}
+
+// MS-XCEP 3.1.4.1.3.15
+#[derive(Debug, Clone, Default)]
+pub struct KeyArchivalAttributes
+{
+  pub symmetric_algorithm: Option<Oid>,
+  pub symmetric_key_length: u32
+}